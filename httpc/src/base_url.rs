@@ -0,0 +1,78 @@
+use std::boxed::FnBox;
+use std::io::{Error, ErrorKind, Result};
+
+use reqwest::Url;
+
+use pi_lib::atom::Atom;
+
+use super::{SharedHttpc, GenHttpClientBody, SharedHttpClient, HttpClient, HttpClientBody, HttpClientResponse, RequestHandle};
+
+/*
+* 绑定基础url的http客户端，请求的路径按基础url解析，绝对url忽略基础url
+* 基础url的路径总是作为目录，例如基础url为http://host/api/v1时，路径users解析为http://host/api/v1/users，以/开始的路径从主机根路径解析
+*/
+#[derive(Clone)]
+pub struct BaseUrlClient {
+    client: SharedHttpClient,   //共享的http客户端
+    base: Atom,                 //基础url
+}
+
+impl BaseUrlClient {
+    //创建绑定基础url的http客户端
+    pub fn new(client: SharedHttpClient, base: Atom) -> Self {
+        BaseUrlClient {
+            client: client,
+            base: base,
+        }
+    }
+
+    //获取共享的http客户端
+    pub fn client(&self) -> &SharedHttpClient {
+        &self.client
+    }
+
+    //获取基础url
+    pub fn base_url(&self) -> Atom {
+        self.base.clone()
+    }
+
+    //按基础url解析路径，返回完整的url
+    pub fn resolve(&self, path: &str) -> Result<Atom> {
+        let mut base = match Url::parse(self.base.as_str()) {
+            Err(e) => return Err(Error::new(ErrorKind::InvalidInput, format!("Httpc invalid base url, base: {}, reason: {}", *self.base, e))),
+            Ok(base) => base,
+        };
+        if !base.path().ends_with('/') {
+            //基础url的最后一段也作为目录，避免被路径替换
+            let dir = format!("{}/", base.path());
+            base.set_path(&dir);
+        }
+
+        match base.join(path) {
+            Err(e) => Err(Error::new(ErrorKind::InvalidInput, format!("Httpc invalid path, base: {}, path: {}, reason: {}", *self.base, path, e))),
+            Ok(url) => Ok(Atom::from(url.as_str())),
+        }
+    }
+
+    //异步发送get请求，路径按基础url解析，解析失败时直接回调错误，返回可以取消请求的句柄
+    pub fn get<T: GenHttpClientBody>(&self, path: &str, body: HttpClientBody<T>, callback: Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)>) -> RequestHandle {
+        match self.resolve(path) {
+            Err(e) => {
+                callback(self.client.clone(), Err(e));
+                RequestHandle::new()
+            },
+            Ok(url) => HttpClient::get(&self.client, url, body, callback),
+        }
+    }
+
+    //异步发送post请求，路径按基础url解析，解析失败时直接回调错误，返回可以取消请求的句柄
+    pub fn post<T: GenHttpClientBody>(&self, path: &str, body: HttpClientBody<T>, callback: Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)>) -> RequestHandle {
+        match self.resolve(path) {
+            Err(e) => {
+                callback(self.client.clone(), Err(e));
+                RequestHandle::new()
+            },
+            Ok(url) => HttpClient::post(&self.client, url, body, callback),
+        }
+    }
+}
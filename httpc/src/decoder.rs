@@ -0,0 +1,62 @@
+use std::any::Any;
+use std::sync::Arc;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+
+/*
+* 响应体解码器，参数为完整的Content-Type和响应体，返回解码后的任意类型
+*/
+pub type BodyDecoder = Arc<Fn(&str, Vec<u8>) -> Result<Box<Any>> + Send + Sync>;
+
+/*
+* 响应体解码器注册表，按MIME类型查找解码器
+* 查找顺序为精确的"type/subtype"，然后是"type/*"，最后是"*/*"，MIME类型不区分大小写，忽略参数
+*/
+#[derive(Clone, Default)]
+pub struct BodyDecoderRegistry {
+    decoders: HashMap<String, BodyDecoder>,    //MIME类型和解码器
+}
+
+impl BodyDecoderRegistry {
+    //创建解码器注册表
+    pub fn new() -> Self {
+        BodyDecoderRegistry::default()
+    }
+
+    //注册指定MIME类型的解码器，已注册则替换，返回被替换的解码器
+    pub fn register(&mut self, mime: &str, decoder: BodyDecoder) -> Option<BodyDecoder> {
+        self.decoders.insert(media_type(mime), decoder)
+    }
+
+    //注销指定MIME类型的解码器
+    pub fn unregister(&mut self, mime: &str) -> Option<BodyDecoder> {
+        self.decoders.remove(&media_type(mime))
+    }
+
+    //获取指定Content-Type的解码器
+    pub fn get(&self, content_type: &str) -> Option<BodyDecoder> {
+        let mime = media_type(content_type);
+        if let Some(decoder) = self.decoders.get(&mime) {
+            return Some(decoder.clone());
+        }
+        if let Some(index) = mime.find('/') {
+            if let Some(decoder) = self.decoders.get(&format!("{}/*", &mime[..index])) {
+                return Some(decoder.clone());
+            }
+        }
+        self.decoders.get("*/*").cloned()
+    }
+
+    //使用指定Content-Type的解码器解码响应体
+    pub fn decode(&self, content_type: &str, body: Vec<u8>) -> Result<Box<Any>> {
+        match self.get(content_type) {
+            None => Err(Error::new(ErrorKind::InvalidData, format!("Body decoder not found, content type: {}", content_type))),
+            Some(decoder) => decoder(content_type, body),
+        }
+    }
+}
+
+//获取Content-Type的MIME类型，小写且不包括参数
+pub fn media_type(content_type: &str) -> String {
+    content_type.split(';').next().unwrap_or("").trim().to_lowercase()
+}
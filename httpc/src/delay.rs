@@ -0,0 +1,103 @@
+use std::boxed::FnBox;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pi_lib::atom::Atom;
+use pi_base::task::TaskType;
+use pi_base::pi_base_impl::cast_ext_task;
+
+/*
+* 延迟任务，到期后通过任务系统派发
+* 任务与直接派发给任务系统的任务相同，捕获了请求的客户端和回调，不是Send
+* 定时线程只在持有队列锁时移动任务，不调用任务，到期后原样交给cast_ext_task，由任务系统在工作线程中调用一次
+* 所以任务跨线程的方式与cast_ext_task相同，不会被多个线程同时访问
+*/
+struct Delayed {
+    time: Instant,          //到期时间
+    name: Atom,             //派发的任务名
+    func: Box<FnBox()>,     //任务
+}
+
+unsafe impl Send for Delayed {}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.time == other.time
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    //最早到期的任务在堆顶
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.time.cmp(&self.time)
+    }
+}
+
+/*
+* 延迟任务队列，等待期间不占用工作线程，所有复制的客户端共享
+* 有任务等待时使用一个定时线程，队列为空时定时线程退出
+*/
+pub struct DelayQueue {
+    tasks: Mutex<(BinaryHeap<Delayed>, bool)>,  //等待的任务和定时线程是否在运行
+    cond: Condvar,                              //有新任务时通知定时线程
+}
+
+impl DelayQueue {
+    pub fn new() -> Self {
+        DelayQueue {
+            tasks: Mutex::new((BinaryHeap::new(), false)),
+            cond: Condvar::new(),
+        }
+    }
+
+    //延迟指定时长后派发任务
+    pub fn delay(queue: &Arc<DelayQueue>, delay: Duration, name: Atom, func: Box<FnBox()>) {
+        let mut tasks = queue.tasks.lock().unwrap();
+        tasks.0.push(Delayed {
+            time: Instant::now() + delay,
+            name: name,
+            func: func,
+        });
+        if tasks.1 {
+            queue.cond.notify_one();
+            return;
+        }
+
+        tasks.1 = true;
+        let copy = queue.clone();
+        thread::spawn(move || run(copy));
+    }
+}
+
+//定时线程，依次派发到期的任务，没有等待的任务时退出
+fn run(queue: Arc<DelayQueue>) {
+    let mut tasks = queue.tasks.lock().unwrap();
+    loop {
+        let now = Instant::now();
+        let time = match tasks.0.peek() {
+            None => {
+                tasks.1 = false;
+                return;
+            },
+            Some(task) => task.time,
+        };
+        if time > now {
+            tasks = queue.cond.wait_timeout(tasks, time - now).unwrap().0;
+            continue;
+        }
+
+        let Delayed { name, func, .. } = tasks.0.pop().unwrap();
+        cast_ext_task(TaskType::Sync, 10000000, func, name);
+    }
+}
@@ -0,0 +1,196 @@
+use std::fs;
+use std::sync::Arc;
+use std::boxed::FnBox;
+use std::time::Instant;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::io::{Read, Write, Error, ErrorKind, Result};
+
+use sha2::{Sha256, Digest};
+use reqwest::Method;
+use reqwest::header::ContentLength;
+
+use pi_lib::atom::Atom;
+use pi_base::task::TaskType;
+use pi_base::pi_base_impl::cast_ext_task;
+
+use super::{SharedHttpClient, HttpClientBody, HttpClientResponse, HttpcError, request, decode_transfer};
+
+/*
+* 每次写入文件的最大字节数
+*/
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/*
+* 下载文件的校验和
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    Sha256(Atom),   //sha256摘要的十六进制字符串，不区分大小写
+}
+
+/*
+* 下载进度回调，参数为已下载的字节数和总字节数，总字节数未知则为None
+*/
+pub type DownloadProgress = Arc<Fn(u64, Option<u64>) + Send + Sync>;
+
+/*
+* 下载选项
+*/
+#[derive(Clone)]
+pub struct DownloadOpts {
+    pub auth: Option<Atom>,                 //Authorization头的值，None表示使用客户端的请求头
+    pub resume: bool,                       //存在未完成的临时文件时是否续传
+    pub checksum: Option<Checksum>,         //下载完成后校验的校验和，None表示不校验
+    pub progress: Option<DownloadProgress>, //下载进度回调，None表示不回调
+}
+
+impl Default for DownloadOpts {
+    fn default() -> Self {
+        DownloadOpts {
+            auth: None,
+            resume: true,
+            checksum: None,
+            progress: None,
+        }
+    }
+}
+
+impl DownloadOpts {
+    //设置Authorization头的值
+    pub fn auth(mut self, auth: Atom) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    //设置是否续传
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    //设置下载完成后校验的校验和
+    pub fn checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = Some(checksum);
+        self
+    }
+
+    //设置下载进度回调
+    pub fn progress(mut self, progress: DownloadProgress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+}
+
+//获取下载的临时文件路径，为目标文件路径加上.part后缀
+pub fn part_path<P: AsRef<Path>>(dest: P) -> PathBuf {
+    let mut name = dest.as_ref().as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
+
+//异步下载文件，先流式写入临时文件，校验通过后重命名为目标文件，临时文件存在时通过Range续传，回调下载的总字节数
+//下载失败时保留临时文件用于续传，校验失败时删除临时文件
+pub fn download<P: AsRef<Path>>(client: &SharedHttpClient, url: Atom, dest: P, opts: DownloadOpts, callback: Box<FnBox(SharedHttpClient, Result<u64>)>) {
+    let origin = client.clone();
+    let mut copy = client.clone();
+    let dest = dest.as_ref().to_path_buf();
+    let dispatched = Instant::now();
+    let func = move || {
+        let part = part_path(&dest);
+        let offset = if opts.resume {
+            fs::metadata(&part).map(|meta| meta.len()).unwrap_or(0)
+        } else {
+            0
+        };
+
+        //只修改本次下载使用的请求头
+        {
+            let headers = &mut Arc::make_mut(&mut copy).headers;
+            //续传的偏移按原始字节计算，不允许压缩
+            headers.set_raw("Accept-Encoding", "identity");
+            if let Some(ref auth) = opts.auth {
+                headers.set_raw("Authorization", (**auth).clone());
+            }
+            if offset > 0 {
+                headers.set_raw("Range", format!("bytes={}-", offset));
+            }
+        }
+
+        request(copy, Method::Get, &url, None, dispatched.elapsed(), HttpClientBody::body(""), Box::new(move |_client: SharedHttpClient, result: Result<HttpClientResponse>| {
+            let r = result.and_then(|resp| save(resp, &part, &dest, offset, &opts));
+            callback(origin, r);
+        }));
+    };
+    cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc download task"));
+}
+
+//保存响应体到临时文件，校验后重命名为目标文件，返回文件的总字节数
+fn save(mut resp: HttpClientResponse, part: &Path, dest: &Path, offset: u64, opts: &DownloadOpts) -> Result<u64> {
+    let url = resp.url_str();
+    let status = resp.status();
+    let append = match status {
+        206 if offset > 0 => true,
+        //请求的范围超出文件长度，临时文件已完整
+        416 if offset > 0 => true,
+        200 => false,
+        _ => return Err(Error::from(HttpcError::Status(status))),
+    };
+
+    let mut hasher = opts.checksum.as_ref().map(|_| Sha256::default());
+    let (mut file, mut len) = if append {
+        let mut file = OpenOptions::new().read(true).append(true).open(part)?;
+        if let Some(ref mut hasher) = hasher {
+            //续传时先计算已下载部分的摘要
+            let mut buf = vec![0; BUFFER_SIZE];
+            loop {
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.input(&buf[..n]);
+            }
+        }
+        (file, offset)
+    } else {
+        (File::create(part)?, 0)
+    };
+
+    if status != 416 {
+        let encodings = resp.transfer_encodings();
+        let total = if encodings.is_empty() {
+            resp.inner.headers().get::<ContentLength>().map(|size| **size + len)
+        } else {
+            None
+        };
+        let mut reader = decode_transfer(&mut resp.inner, &encodings)?;
+        let mut buf = vec![0; BUFFER_SIZE];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buf[..n])?;
+            if let Some(ref mut hasher) = hasher {
+                hasher.input(&buf[..n]);
+            }
+            len += n as u64;
+            if let Some(ref progress) = opts.progress {
+                progress(len, total);
+            }
+        }
+    }
+    file.sync_all()?;
+    drop(file);
+
+    if let (Some(hasher), Some(&Checksum::Sha256(ref expected))) = (hasher, opts.checksum.as_ref()) {
+        let actual = hasher.result().iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        if !actual.eq_ignore_ascii_case(expected) {
+            //临时文件已损坏，删除后下次重新下载
+            let _ = fs::remove_file(part);
+            return Err(Error::new(ErrorKind::InvalidData, format!("Httpc download checksum mismatch, url: {}, expected: {}, actual: {}", url, **expected, actual)));
+        }
+    }
+    fs::rename(part, dest)?;
+    Ok(len)
+}
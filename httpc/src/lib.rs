@@ -1,394 +1,2466 @@
 #![feature(fnbox)]
 
 extern crate reqwest;
+extern crate native_tls;
+extern crate hyper;
+extern crate mime_guess;
+extern crate serde;
+extern crate serde_json;
+extern crate websocket;
+extern crate flate2;
+extern crate sha2;
+#[macro_use]
+extern crate serde_derive;
 
 extern crate pi_lib;
 extern crate pi_base;
 
+pub mod ws;
+pub mod race;
+pub mod decoder;
+pub mod multipart;
+pub mod download;
+pub mod upload;
+pub mod paginate;
+pub mod tunnel;
+pub mod base_url;
+mod delay;
+
+use std::fmt;
+use std::any::Any;
 use std::fs::File;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::channel;
 use std::path::Path;
 use std::boxed::FnBox;
 use std::path::PathBuf;
-use std::time::Duration;
-use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use std::collections::{HashMap, VecDeque};
+use std::collections::hash_map::Entry;
+use std::net::{IpAddr, ToSocketAddrs};
 use std::error::Error as StdError;
-use std::io::{Read, Error, ErrorKind, Result};
+use std::io::{Read, Write, BufRead, BufReader, Cursor, Error, ErrorKind, Result};
 
+use flate2::read::{GzDecoder, ZlibDecoder};
+use serde::ser::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
 use reqwest::multipart::Form;
-use reqwest::header::{Raw, Headers};
-use reqwest::{ClientBuilder, Client, Certificate, Identity, Proxy, RedirectPolicy, Body, RequestBuilder, Response};
+use mime_guess::guess_mime_type;
+use reqwest::header::{Raw, Headers, Connection, ConnectionOption, ContentLength, Date, Authorization, ProxyAuthorization, Basic, Bearer};
+use reqwest::{Url, Method, ClientBuilder, Client, Certificate, Identity, Proxy, RedirectPolicy, Body, Request, RequestBuilder, Response};
 
 use pi_lib::atom::Atom;
+use decoder::{BodyDecoderRegistry, media_type};
+use multipart::{MultipartReader, boundary};
+use base_url::BaseUrlClient;
+use delay::DelayQueue;
 use pi_base::task::TaskType;
 use pi_base::pi_base_impl::cast_ext_task;
 
 /*
-* http客户端选项
+* 默认选项转换为其它选项时使用的值，与底层客户端的默认值一致
 */
-pub enum HttpClientOptions {
-    Default,                                                                  //默认选项
-    Normal(bool, bool, isize, u64),                                           //一般选项
-    VaildHost(PathBuf, PathBuf, String, bool, bool, isize, u64),              //安全选项，所有https连接将验证主机证书
-    Proxy(Atom, bool, bool, isize, u64),                                      //代理选项
-    ValidHostProxy(PathBuf, PathBuf, String, Atom, bool, bool, isize, u64),   //安全代理选项，所有https连接将验证主机证书
-}
+const DEFAULT_GZIP: bool = true;
+const DEFAULT_REFERER: bool = true;
+const DEFAULT_REDIRECT_COUNT: isize = 10;
+const DEFAULT_TIMEOUT: u64 = 30000;
 
 /*
-* 通用Body
+* 默认的等待主机并发请求数低于上限的最大时长，单位毫秒
 */
-pub trait GenHttpClientBody: Into<Body> + Send + Sync + 'static {}
+const DEFAULT_PER_HOST_WAIT: u64 = 30000;
 
-impl GenHttpClientBody for &'static str {}
-impl GenHttpClientBody for String {}
-impl GenHttpClientBody for Vec<u8> {}
-impl GenHttpClientBody for File {}
+/*
+* 在缓冲预算内缓冲响应体时，每次读取的最大字节数
+*/
+const BUFFER_READ_SIZE: usize = 64 * 1024;
 
 /*
-* http的Body
+* 超时时长的上限，单位毫秒，超过上限的超时时长视为不超时，避免计算截止时间时溢出
 */
-pub enum HttpClientBody<T: GenHttpClientBody> {
-    Body(T),                        //块
-    Json(HashMap<String, String>),  //json
-    Form(Form),                     //表单
+const MAX_TIMEOUT: u64 = 365 * 24 * 60 * 60 * 1000;
+
+/*
+* http客户端选项，最后一个参数是超时时长，单位毫秒，为0或超过一年表示不超时
+* 重试次数和退避时长不在基础选项中，通过扩展选项的HttpClientExtOptions::retry和HttpClientExtOptions::retry_backoff设置
+*/
+#[derive(Debug, Clone)]
+pub enum HttpClientOptions {
+    Default,                                                                  //默认选项
+    Normal(bool, bool, isize, u64),                                           //一般选项
+    VaildHost(PathBuf, PathBuf, String, bool, bool, isize, u64),              //安全选项，所有https连接将验证主机证书
+    Proxy(Atom, bool, bool, isize, u64),                                      //代理选项，代理url只支持http
+    ValidHostProxy(PathBuf, PathBuf, String, Atom, bool, bool, isize, u64),   //安全代理选项，所有https连接将验证主机证书
 }
 
-impl<T: GenHttpClientBody> HttpClientBody<T> {
-    //创建body
-    pub fn body(body: T) -> Self {
-        HttpClientBody::Body(body)
+impl HttpClientOptions {
+    //复制并修改超时时长，单位毫秒，为0表示不超时，默认选项会转换为一般选项
+    pub fn with_timeout(&self, timeout: u64) -> Self {
+        let (valid, proxy, gzip, referer, count, _) = self.parts();
+        HttpClientOptions::from_parts(valid, proxy, gzip, referer, count, timeout)
     }
 
-    //创建json
-    pub fn json(key: Atom, value: T) -> Self where T: ToString {
-        let mut map = HashMap::new();
-        map.insert((*key).clone(), value.to_string());
-        HttpClientBody::Json(map)
+    //复制并修改超时时长，None表示不超时，不足1毫秒的部分向上取整，默认选项会转换为一般选项
+    pub fn with_timeout_duration(&self, timeout: Option<Duration>) -> Self {
+        let millis = match timeout {
+            None => 0,
+            Some(timeout) => {
+                let millis = timeout.as_secs().saturating_mul(1000).saturating_add(((timeout.subsec_nanos() + 999999) / 1000000) as u64);
+                if millis == 0 {
+                    //不能用0表示极短的超时时长
+                    1
+                } else {
+                    millis
+                }
+            },
+        };
+        self.with_timeout(millis)
     }
 
-    //创建表单
-    pub fn form(key: Atom, value: T) -> Self where T: ToString {
-        HttpClientBody::Form(Form::new().text((*key).clone(), value.to_string()))
+    //获取超时时长，None表示不超时
+    pub fn timeout(&self) -> Option<Duration> {
+        let (_, _, _, _, _, timeout) = self.parts();
+        to_timeout(timeout)
     }
 
-    //获取指定关键字的json值
-    pub fn get_json_val(&self, key: Atom) -> Option<&String> {
-        match self {
-            HttpClientBody::Json(map) => {
-                map.get(&*key)
-            },
-            _ => None,
-        }
+    //复制并修改是否启用gzip，默认选项会转换为一般选项
+    pub fn with_gzip(&self, gzip: bool) -> Self {
+        let (valid, proxy, _, referer, count, timeout) = self.parts();
+        HttpClientOptions::from_parts(valid, proxy, gzip, referer, count, timeout)
     }
 
-    //增加json键值对，返回键值对数量
-    pub fn add_json_kv(&mut self, key: Atom, value: String) -> usize {
-        match self {
-            HttpClientBody::Json(map) => {
-                map.insert((*key).clone(), value);
-                map.len()
-            },
-            _ => 0,
-        }
+    //复制并修改代理，None表示不使用代理，默认选项会转换为一般选项或代理选项
+    pub fn with_proxy(&self, proxy: Option<Atom>) -> Self {
+        let (valid, _, gzip, referer, count, timeout) = self.parts();
+        HttpClientOptions::from_parts(valid, proxy, gzip, referer, count, timeout)
     }
 
-    //移除指定关键字的json键值对，返回被移除的值
-    pub fn remove_json_kv(&mut self, key: Atom) -> Option<String> {
-        match self {
-            HttpClientBody::Json(map) => {
-                map.remove(&*key)
-            },
-            _ => None,
+    //分解选项，返回主机证书验证选项、代理、gzip、referer、重定向次数和超时时长
+    fn parts(&self) -> (Option<(PathBuf, PathBuf, String)>, Option<Atom>, bool, bool, isize, u64) {
+        match self.clone() {
+            HttpClientOptions::Default => (None, None, DEFAULT_GZIP, DEFAULT_REFERER, DEFAULT_REDIRECT_COUNT, DEFAULT_TIMEOUT),
+            HttpClientOptions::Normal(gzip, referer, count, timeout) => (None, None, gzip, referer, count, timeout),
+            HttpClientOptions::VaildHost(cert, identity, pk, gzip, referer, count, timeout) => (Some((cert, identity, pk)), None, gzip, referer, count, timeout),
+            HttpClientOptions::Proxy(proxy, gzip, referer, count, timeout) => (None, Some(proxy), gzip, referer, count, timeout),
+            HttpClientOptions::ValidHostProxy(cert, identity, pk, proxy, gzip, referer, count, timeout) => (Some((cert, identity, pk)), Some(proxy), gzip, referer, count, timeout),
         }
     }
 
-    //清空所有json键值对
-    pub fn clear_json_kvs(&mut self) {
-        match self {
-            HttpClientBody::Json(map) => {
-                map.clear()
-            },
-            _ => (),
+    //由分解的选项构建选项
+    fn from_parts(valid: Option<(PathBuf, PathBuf, String)>, proxy: Option<Atom>, gzip: bool, referer: bool, count: isize, timeout: u64) -> Self {
+        match (valid, proxy) {
+            (None, None) => HttpClientOptions::Normal(gzip, referer, count, timeout),
+            (Some((cert, identity, pk)), None) => HttpClientOptions::VaildHost(cert, identity, pk, gzip, referer, count, timeout),
+            (None, Some(proxy)) => HttpClientOptions::Proxy(proxy, gzip, referer, count, timeout),
+            (Some((cert, identity, pk)), Some(proxy)) => HttpClientOptions::ValidHostProxy(cert, identity, pk, proxy, gzip, referer, count, timeout),
         }
     }
+}
 
-    //增加表单键值对
-    pub fn add_form_kv(self, key: Atom, value: String) -> Self {
-        match self {
-            HttpClientBody::Form(form) => {
-                HttpClientBody::Form(form.text((*key).clone(), value))
-            },
-            _ => self,
-        }
-    }
+/*
+* 代理路由目标
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProxyTarget {
+    Direct,         //直连
+    Proxy(Atom),    //通过指定url的代理
+}
 
-    //增加表单文件
-    pub fn add_form_file<P: AsRef<Path>>(self, key: Atom, file: P) -> Result<Self> {
-        match self {
-            HttpClientBody::Form(form) => {
-                form.file((*key).clone(), file).or_else(|e| {
-                    Err(Error::new(ErrorKind::Other, e.description().to_string()))
-                }).and_then(|f| {
-                    Ok(HttpClientBody::Form(f))
-                })
-            },
-            _ => Ok(self),
+/*
+* 代理路由规则，主机模式支持精确匹配，"*.example.com"匹配所有子域名，"*"匹配所有主机
+*/
+#[derive(Debug, Clone)]
+pub struct ProxyRule {
+    pub pattern: Atom,          //主机模式
+    pub target: ProxyTarget,    //代理路由目标
+}
+
+impl ProxyRule {
+    //判断主机是否匹配
+    fn is_match(&self, host: &str) -> bool {
+        let pattern = self.pattern.as_str();
+        if pattern == "*" {
+            true
+        } else if pattern.starts_with("*.") {
+            let suffix = &pattern[1..];
+            host.len() > suffix.len() && host.to_lowercase().ends_with(&suffix.to_lowercase())
+        } else {
+            host.eq_ignore_ascii_case(pattern)
         }
     }
 }
 
 /*
-* 共享http客户端
+* 传递给请求签名回调的请求，在请求构建完成、发送前生成
+* method为大写的请求方法，url为解析并规范化后的完整url，headers为客户端请求头和请求体相关的请求头，
+* 不包括底层客户端在发送时附加的默认请求头，如User-Agent、Accept、Host和分块编码时的Transfer-Encoding
+* body为将要发送的请求体字节，表单、文件和流请求为None，签名回调可以使用未签名的请求体
+* 签名回调只能修改请求头，修改后的请求头替换请求的全部请求头
 */
-pub trait SharedHttpc {
-    //构建http客户端
-    fn create(options: HttpClientOptions) -> Result<Arc<Self>>;
-    //增加指定关键字的http头条目，返回头条目数量，一个关键字可以有多个条目
-    fn add_header(client: &mut SharedHttpClient, key: Atom, value: Atom) -> usize;
-    //移除指定关键字的http头条目，返回头条目数量
-    fn remove_header(client: &mut SharedHttpClient, key: Atom) -> usize;
-    //清空http头条目
-    fn clear_headers(client: &mut SharedHttpClient);
-    //异步发送get请求
-    fn get<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
-    //异步发送post请求
-    fn post<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
-    //获取当前http头条目数量
-    fn headers_size(&self) -> usize;
-    //获取所有http头条目关键字
-    fn headers_keys(&self) -> Option<Vec<Atom>>;
-    //获取指定关键字的http头条目，一个关键字可以有多个条目
-    fn get_header(&self, key: Atom) -> Option<Vec<Atom>>;
+#[derive(Debug, Clone)]
+pub struct RequestParts {
+    pub method: Atom,           //请求方法
+    pub url: String,            //请求url
+    pub headers: Headers,       //请求头
+    pub body: Option<Vec<u8>>,  //请求体
 }
 
 /*
-* 共享http客户端
+* 重定向的处理结果
 */
-pub type SharedHttpClient = Arc<HttpClient>;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedirectAction {
+    Follow,         //继续重定向
+    Stop,           //停止重定向，将重定向响应作为请求结果返回
+    Refuse(String), //拒绝重定向，以HttpcError::Redirect错误返回，参数为原因
+}
 
 /*
-* http客户端
+* 重定向策略回调，参数为重定向的目标url和之前依次访问的url，第一个是请求的url，最后一个是返回重定向响应的url
 */
-#[derive(Clone)]
-pub struct HttpClient {
-    inner: Client,      //内部客户端，因为Client依赖的mio有一个在windows下无法正常关闭socket的bug，至今未解决，所以尽量复用同一个Client，详见https://github.com/seanmonstar/reqwest/issues?utf8=%E2%9C%93&q=close 和 https://github.com/carllerche/mio/issues/776
-    headers: Headers,   //请求头
-}
+pub type RedirectHook = Arc<Fn(&Url, &[Url]) -> RedirectAction + Send + Sync>;
 
-impl SharedHttpc for HttpClient {
-    fn create(options: HttpClientOptions) -> Result<Arc<Self>> {
-        match options {
-            HttpClientOptions::Default => {
-                ClientBuilder::new()
-                            .danger_disable_hostname_verification()
-                            .build()
-            },
-            HttpClientOptions::Normal(gzip, referer, count, timeout) => {
-                ClientBuilder::new()
-                            .danger_disable_hostname_verification()
-                            .gzip(gzip)
-                            .referer(referer)
-                            .redirect(if count < 0 {
-                                RedirectPolicy::none()
-                            } else {
-                                RedirectPolicy::limited(count as usize)
-                            })
-                            .timeout(Duration::from_millis(timeout))
-                            .build()
-            },
-            HttpClientOptions::VaildHost(cert_file, identity_file, pk, gzip, referer, count, timeout) => {
-                let mut cert_buf = Vec::new();
-                File::open(cert_file)?.read_to_end(&mut cert_buf)?;
-                let cert = Certificate::from_der(&cert_buf).or_else(|e| {
-                    Err(Error::new(ErrorKind::Other, e.description().to_string()))
-                })?;
-                let mut identity_buf = Vec::new();
-                File::open(identity_file)?.read_to_end(&mut identity_buf)?;
-                let identity = Identity::from_pkcs12_der(&identity_buf, &pk).or_else(|e| {
-                    Err(Error::new(ErrorKind::Other, e.description().to_string()))
-                })?;
-                ClientBuilder::new()
-                            .add_root_certificate(cert)
-                            .identity(identity)
-                            .gzip(gzip)
-                            .referer(referer)
-                            .redirect(if count < 0 {
-                                RedirectPolicy::none()
-                            } else {
-                                RedirectPolicy::limited(count as usize)
-                            })
-                            .timeout(Duration::from_millis(timeout))
-                            .build()
-            },
-            HttpClientOptions::Proxy(proxy_url, gzip, referer, count, timeout) => {
-                let proxy = Proxy::http(&*proxy_url).or_else(|e| {
-                    Err(Error::new(ErrorKind::Other, e.description().to_string()))
-                })?;
-                ClientBuilder::new()
-                            .danger_disable_hostname_verification()
-                            .proxy(proxy)
-                            .gzip(gzip)
-                            .referer(referer)
-                            .redirect(if count < 0 {
-                                RedirectPolicy::none()
-                            } else {
-                                RedirectPolicy::limited(count as usize)
-                            })
-                            .timeout(Duration::from_millis(timeout))
-                            .build()
-            },
-            HttpClientOptions::ValidHostProxy(cert_file, identity_file, pk, proxy_url, gzip, referer, count, timeout) => {
-                let mut cert_buf = Vec::new();
-                File::open(cert_file)?.read_to_end(&mut cert_buf)?;
-                let cert = Certificate::from_der(&cert_buf).or_else(|e| {
-                    Err(Error::new(ErrorKind::Other, e.description().to_string()))
-                })?;
-                let mut identity_buf = Vec::new();
-                File::open(identity_file)?.read_to_end(&mut identity_buf)?;
-                let identity = Identity::from_pkcs12_der(&identity_buf, &pk).or_else(|e| {
-                    Err(Error::new(ErrorKind::Other, e.description().to_string()))
-                })?;
-                let proxy = Proxy::http(&*proxy_url).or_else(|e| {
-                    Err(Error::new(ErrorKind::Other, e.description().to_string()))
-                })?;
-                ClientBuilder::new()
-                            .add_root_certificate(cert)
-                            .identity(identity)
-                            .proxy(proxy)
-                            .gzip(gzip)
-                            .referer(referer)
-                            .redirect(if count < 0 {
-                                RedirectPolicy::none()
-                            } else {
-                                RedirectPolicy::limited(count as usize)
-                            })
-                            .timeout(Duration::from_millis(timeout))
-                            .build()
+/*
+* 被重定向策略拒绝的重定向，键为重定向的目标url，值为依次拒绝的原因
+* 底层客户端的重定向策略只能继续或停止，拒绝时停止重定向并记录原因，请求收到停止的重定向响应后取出原因并返回错误
+*/
+type RedirectRefusals = Arc<Mutex<HashMap<String, Vec<String>>>>;
+
+//创建最多重定向max次，且不允许从https重定向到http的重定向策略
+pub fn redirect_no_downgrade(max: usize) -> RedirectHook {
+    Arc::new(move |next: &Url, previous: &[Url]| {
+        if previous.len() > max {
+            return RedirectAction::Refuse(format!("too many redirects, max: {}", max));
+        }
+        match previous.last() {
+            Some(last) if last.scheme() == "https" && next.scheme() == "http" => {
+                RedirectAction::Refuse(format!("redirect from https to http, from: {}, to: {}", last, next))
             },
-        }.or_else(|e| {
-            Err(Error::new(ErrorKind::Other, e.description().to_string()))
-        }).and_then(|inner| {
-            Ok(Arc::new(HttpClient {
-                inner: inner,
-                headers: Headers::new(),
-            }))
-        })
-    }
+            _ => RedirectAction::Follow,
+        }
+    })
+}
 
-    fn add_header(client: &mut Arc<HttpClient>, key: Atom, value: Atom) -> usize {
-        Arc::make_mut(client).headers.append_raw((*key).clone(), (*value).as_str());
-        client.headers.len()
+/*
+* 请求签名回调，每次发送请求前调用，包括重试
+*/
+pub type RequestSigner = Arc<Fn(&mut RequestParts) + Send + Sync>;
+
+/*
+* 禁止访问规则，模式支持主机的精确匹配，"*.example.com"匹配所有子域名，ip地址，以及"10.0.0.0/8"格式的CIDR
+* ip地址和CIDR同时匹配url中的ip地址和主机解析后的所有地址
+*/
+#[derive(Debug, Clone)]
+pub struct DenyRule {
+    pub pattern: Atom,  //禁止访问的模式
+}
+
+impl DenyRule {
+    //判断主机或主机的地址是否匹配
+    fn is_match(&self, host: &str, addrs: &[IpAddr]) -> bool {
+        let pattern = self.pattern.as_str();
+        if let Some(index) = pattern.find('/') {
+            return match (pattern[..index].parse::<IpAddr>(), pattern[index + 1..].parse::<u8>()) {
+                (Ok(net), Ok(prefix)) => addrs.iter().any(|addr| is_in_cidr(addr, &net, prefix)),
+                _ => false,
+            };
+        }
+        if let Ok(ip) = pattern.parse::<IpAddr>() {
+            return addrs.iter().any(|addr| *addr == ip);
+        }
+
+        ProxyRule {
+            pattern: self.pattern.clone(),
+            target: ProxyTarget::Direct,
+        }.is_match(host)
     }
 
-    fn remove_header(client: &mut Arc<HttpClient>, key: Atom) -> usize {
-        Arc::make_mut(client).headers.remove_raw((*key).as_str());
-        client.headers.len()
+    //判断是否需要解析主机的地址
+    fn is_addr(&self) -> bool {
+        self.pattern.contains('/') || self.pattern.parse::<IpAddr>().is_ok()
     }
+}
 
-    fn clear_headers(client: &mut Arc<HttpClient>) {
-        Arc::make_mut(client).headers.clear();
+/*
+* 请求被禁止访问规则拒绝的错误，以ErrorKind::PermissionDenied的io错误返回，可以通过get_ref获取
+*/
+#[derive(Debug, Clone)]
+pub struct Blocked {
+    pub url: Atom,      //请求url
+    pub reason: String, //拒绝原因
+}
+
+impl fmt::Display for Blocked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Httpc request blocked, url: {}, reason: {}", *self.url, self.reason)
     }
+}
 
-    fn get<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
-        let copy = client.clone();
-        let func = move || {
-            let get = &mut copy.inner.get((*url).as_str());
-            request(copy, get, body, callback);
-        };
-        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), Atom::from("httpc normal get request task"));
+impl StdError for Blocked {
+    fn description(&self) -> &str {
+        "Httpc request blocked"
     }
+}
 
-    fn post<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
-        let copy = client.clone();
-        let func = move || {
-            let post = &mut copy.inner.post((*url).as_str());
-            request(copy, post, body, callback);
-        };
-        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), Atom::from("httpc normal post request task"));
+/*
+* 要求响应体有内容时，响应体为空的错误，以ErrorKind::UnexpectedEof的io错误返回，可以通过get_ref获取
+*/
+#[derive(Debug, Clone)]
+pub struct EmptyBody {
+    pub url: String,    //响应url
+    pub status: u16,    //响应状态
+}
+
+impl fmt::Display for EmptyBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Httpc response body empty, url: {}, status: {}", self.url, self.status)
     }
+}
 
-    fn headers_size(&self) -> usize {
-        self.headers.len()
+impl StdError for EmptyBody {
+    fn description(&self) -> &str {
+        "Httpc response body empty"
     }
+}
 
-    fn headers_keys(&self) -> Option<Vec<Atom>> {
-        let len = self.headers_size();
-        if len == 0 {
-            return None;
-        }
+/*
+* 读取响应体中途失败的错误，包括失败前已读取的数据，以原始错误的类型返回，可以通过get_ref获取
+*/
+#[derive(Debug, Clone)]
+pub struct PartialBody {
+    pub url: String,    //响应url
+    pub status: u16,    //响应状态
+    pub data: Vec<u8>,  //失败前已读取的数据，已解码传输编码
+    pub reason: String, //失败原因
+}
 
-        let mut vec = Vec::with_capacity(len);
-        for header in self.headers.iter() {
-            vec.push(Atom::from(header.name()))
-        }
-        Some(vec)
+impl fmt::Display for PartialBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Httpc response body partial, url: {}, status: {}, read: {}, reason: {}", self.url, self.status, self.data.len(), self.reason)
     }
+}
 
-    fn get_header(&self, key: Atom) -> Option<Vec<Atom>> {
-        self.headers.get_raw(&*key).and_then(|val: &Raw| {
-            let len = val.len();
-            let mut vec = Vec::with_capacity(len);
-            for index in 0..len {
-                vec.push(Atom::from(&val[index]))
-            }
-            Some(vec)
-        })
+impl StdError for PartialBody {
+    fn description(&self) -> &str {
+        "Httpc response body partial"
     }
 }
 
 /*
-* http响应
+* 响应头超过限制的错误，以ErrorKind::InvalidData的io错误返回，可以通过get_ref获取
 */
-pub struct HttpClientResponse {
-    inner: Response,
+#[derive(Debug, Clone)]
+pub struct HeadersTooLarge {
+    pub url: String,    //响应url
+    pub size: usize,    //响应头的字节数，按解析后的条目估算
+    pub limit: usize,   //允许的最大字节数
 }
 
-impl HttpClientResponse{
-    //获取响应url
-    pub fn url(&self) -> Atom {
-        Atom::from(self.inner.url().as_str())
+impl fmt::Display for HeadersTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Httpc response headers too large, url: {}, size: {}, limit: {}", self.url, self.size, self.limit)
     }
+}
 
-    //判断是否是消息
-    pub fn is_info(&self) -> bool {
-        self.inner.status().is_informational()
+impl StdError for HeadersTooLarge {
+    fn description(&self) -> &str {
+        "Httpc response headers too large"
     }
+}
 
-    //判断是否成功
-    pub fn is_ok(&self) -> bool {
-        self.inner.status().is_success()
+/*
+* 响应体超过限制的错误，以ErrorKind::InvalidData的io错误返回，可以通过get_ref获取
+*/
+#[derive(Debug, Clone)]
+pub struct BodyTooLarge {
+    pub url: String,    //响应url
+    pub size: u64,      //Content-Length声明的字节数，或读取中已收到的字节数
+    pub limit: usize,   //允许的最大字节数
+}
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Httpc response too large, url: {}, size: {}, limit: {}", self.url, self.size, self.limit)
     }
+}
 
-    //判断是否是重定向
-    pub fn is_redirect(self) -> bool {
-        self.inner.status().is_redirection()
+impl StdError for BodyTooLarge {
+    fn description(&self) -> &str {
+        "Httpc response too large"
     }
+}
 
-    //判断是否是客户端错误
-    pub fn is_client_error(self) -> bool {
-        self.inner.status().is_client_error()
+/*
+* 请求失败的原因，以io错误返回，可以通过HttpcError::from_io获取，用于区分超时、连接、tls等失败原因
+* 转换为io错误时，超时为ErrorKind::TimedOut，解码失败为ErrorKind::InvalidData，Io保留原io错误，其它为ErrorKind::Other
+*/
+#[derive(Debug)]
+pub enum HttpcError {
+    Timeout(String),    //请求超时
+    Connect(String),    //连接失败，例如连接被拒绝或重置
+    Tls(String),        //tls握手失败
+    Redirect(String),   //重定向失败，例如超过重定向次数
+    Decode(String),     //响应体解码失败，例如不支持的传输编码或无效的utf8
+    Status(u16),        //响应状态码表示失败
+    Request(String),    //其它传输错误
+    Cancelled(String),  //请求已通过请求句柄取消
+    Io(Error),          //io错误
+}
+
+impl HttpcError {
+    //获取io错误中的请求失败原因，不是请求失败的io错误返回None
+    pub fn from_io(e: &Error) -> Option<&HttpcError> {
+        e.get_ref().and_then(|inner| inner.downcast_ref::<HttpcError>())
     }
 
-    //判断是否是服务器端错误
-    pub fn is_server_error(self) -> bool {
-        self.inner.status().is_server_error()
+    //获取转换为io错误时的错误类型
+    pub fn kind(&self) -> ErrorKind {
+        match *self {
+            HttpcError::Timeout(_) => ErrorKind::TimedOut,
+            HttpcError::Decode(_) => ErrorKind::InvalidData,
+            HttpcError::Io(ref e) => e.kind(),
+            _ => ErrorKind::Other,
+        }
     }
+}
 
-    //判断是否是未知状态
-    pub fn is_undefined(self) -> bool {
-        self.inner.status().is_strange_status()
+//io错误不能复制，复制时只保留错误类型和描述
+impl Clone for HttpcError {
+    fn clone(&self) -> Self {
+        match *self {
+            HttpcError::Timeout(ref reason) => HttpcError::Timeout(reason.clone()),
+            HttpcError::Connect(ref reason) => HttpcError::Connect(reason.clone()),
+            HttpcError::Tls(ref reason) => HttpcError::Tls(reason.clone()),
+            HttpcError::Redirect(ref reason) => HttpcError::Redirect(reason.clone()),
+            HttpcError::Decode(ref reason) => HttpcError::Decode(reason.clone()),
+            HttpcError::Status(status) => HttpcError::Status(status),
+            HttpcError::Request(ref reason) => HttpcError::Request(reason.clone()),
+            HttpcError::Cancelled(ref reason) => HttpcError::Cancelled(reason.clone()),
+            HttpcError::Io(ref e) => HttpcError::Io(Error::new(e.kind(), e.to_string())),
+        }
     }
+}
 
-    //获取响应状态
-    pub fn status(&self) -> u16 {
-        self.inner.status().as_u16()
+impl fmt::Display for HttpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HttpcError::Timeout(ref reason) |
+            HttpcError::Connect(ref reason) |
+            HttpcError::Tls(ref reason) |
+            HttpcError::Redirect(ref reason) |
+            HttpcError::Decode(ref reason) |
+            HttpcError::Request(ref reason) |
+            HttpcError::Cancelled(ref reason) => write!(f, "{}", reason),
+            HttpcError::Status(status) => write!(f, "Httpc response status: {}", status),
+            HttpcError::Io(ref e) => write!(f, "{}", e),
+        }
     }
+}
 
-    //获取响应状态描述
-    pub fn status_info(&self) -> Option<Atom> {
-        self.inner.status().canonical_reason().and_then(|reason| {
-            Some(Atom::from(reason))
-        })
+impl StdError for HttpcError {
+    fn description(&self) -> &str {
+        match *self {
+            HttpcError::Timeout(_) => "Httpc request timeout",
+            HttpcError::Connect(_) => "Httpc connect failed",
+            HttpcError::Tls(_) => "Httpc tls failed",
+            HttpcError::Redirect(_) => "Httpc redirect failed",
+            HttpcError::Decode(_) => "Httpc decode failed",
+            HttpcError::Status(_) => "Httpc response status failed",
+            HttpcError::Request(_) => "Httpc request failed",
+            HttpcError::Cancelled(_) => "Httpc request cancelled",
+            HttpcError::Io(ref e) => e.description(),
+        }
+    }
+}
+
+impl From<HttpcError> for Error {
+    fn from(e: HttpcError) -> Self {
+        match e {
+            HttpcError::Io(e) => e,
+            e => Error::new(e.kind(), e),
+        }
+    }
+}
+
+/*
+* 代理服务器要求认证的错误，代理返回407时以ErrorKind::PermissionDenied的io错误返回，可以通过get_ref获取
+*/
+#[derive(Debug, Clone)]
+pub struct ProxyAuthRequired {
+    pub url: String,    //请求url
+}
+
+impl fmt::Display for ProxyAuthRequired {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Httpc proxy authentication required, status: 407, url: {}", self.url)
+    }
+}
+
+impl StdError for ProxyAuthRequired {
+    fn description(&self) -> &str {
+        "Httpc proxy authentication required"
+    }
+}
+
+/*
+* http客户端扩展选项，在构建内部客户端时附加到基础选项上
+*/
+#[derive(Default)]
+pub struct HttpClientExtOptions {
+    pub proxy_rules: Vec<ProxyRule>,            //代理路由规则，按顺序匹配第一条，基础选项使用代理时，基础选项的代理优先
+    pub proxy_fallback: Option<ProxyTarget>,    //没有匹配规则时的代理路由目标，None表示直连
+    pub proxy_auth: Option<(Atom, Atom)>,       //基础选项中代理的用户名和密码，优先于代理url中的用户信息，None表示使用代理url中的用户信息
+    pub retries: usize,                         //请求发生传输错误时的最大重试次数，只有可复制的body和幂等的请求才会重试
+    pub retry_budget: Option<RetryBudgetOptions>,   //客户端共享的重试预算，None表示不限制
+    pub retry_backoff: Duration,                //第一次重试前的基础退避时长，之后每次重试加倍并加入随机抖动，为0表示立即重试
+    pub retry_all_methods: bool,                //是否重试非幂等的请求，默认只重试GET、HEAD、PUT、DELETE、OPTIONS和TRACE
+    pub on_connection: Option<ConnectionHook>,  //请求的连接状态回调，None表示不回调
+    pub deny_rules: Vec<DenyRule>,              //禁止访问规则，请求前检查，匹配任一条则拒绝
+    pub block_private: bool,                    //是否拒绝主机解析为私有、回环或链路本地地址的请求
+    pub signer: Option<RequestSigner>,          //请求签名回调，None表示不签名
+    pub metrics: Option<MetricsSink>,           //请求统计回调，None表示不统计
+    pub coalesce: bool,                         //是否合并相同的进行中的get快照请求
+    pub max_response_header_bytes: Option<usize>,   //允许的响应头最大字节数，None表示不限制
+    pub validate: Option<bool>,                 //是否在发送前校验请求，None表示只在调试构建时校验
+    pub max_per_host: Option<usize>,            //每个主机的最大并发请求数，为0或None表示不限制
+    pub per_host_wait: Option<u64>,             //等待主机并发请求数低于上限的最大时长，单位毫秒，None表示使用默认值
+    pub interceptors: Vec<Arc<Interceptor>>,    //请求拦截器，按注册顺序调用
+    pub max_buffered_bytes: Option<usize>,      //所有复制的客户端缓冲响应体的最大总字节数，None表示不限制
+    pub max_response_bytes: Option<usize>,      //每个响应通过bin和text读取的响应体最大字节数，None表示不限制
+    pub redirect_hook: Option<RedirectHook>,    //重定向策略回调，设置后替换基础选项中的重定向次数，None表示使用基础选项
+}
+
+impl HttpClientExtOptions {
+    //增加代理路由规则
+    pub fn proxy_rule(mut self, pattern: Atom, target: ProxyTarget) -> Self {
+        self.proxy_rules.push(ProxyRule {
+            pattern: pattern,
+            target: target,
+        });
+        self
+    }
+
+    //设置请求的最大重试次数和重试预算
+    pub fn retry(mut self, retries: usize, budget: Option<RetryBudgetOptions>) -> Self {
+        self.retries = retries;
+        self.retry_budget = budget;
+        self
+    }
+
+    //设置重试的基础退避时长
+    pub fn retry_backoff(mut self, base: Duration) -> Self {
+        self.retry_backoff = base;
+        self
+    }
+
+    //设置是否重试非幂等的请求，例如POST和PATCH
+    pub fn retry_all_methods(mut self, enable: bool) -> Self {
+        self.retry_all_methods = enable;
+        self
+    }
+
+    //设置请求的连接状态回调
+    pub fn on_connection(mut self, hook: ConnectionHook) -> Self {
+        self.on_connection = Some(hook);
+        self
+    }
+
+    //设置请求统计回调
+    pub fn metrics(mut self, sink: MetricsSink) -> Self {
+        self.metrics = Some(sink);
+        self
+    }
+
+    //增加请求拦截器，在已注册的拦截器之后调用before，之前调用after
+    pub fn interceptor(mut self, interceptor: Arc<Interceptor>) -> Self {
+        self.interceptors.push(interceptor);
+        self
+    }
+
+    //设置所有复制的客户端缓冲响应体的最大总字节数，通过bin和text缓冲响应体时占用，响应释放时归还
+    //超过上限的缓冲失败，流式读取响应体不受限制
+    pub fn max_buffered_bytes(mut self, max: usize) -> Self {
+        self.max_buffered_bytes = Some(max);
+        self
+    }
+
+    //设置重定向策略回调，每次重定向前调用，决定继续、停止或拒绝，基础选项中的重定向次数不再生效
+    pub fn redirect_policy(mut self, hook: RedirectHook) -> Self {
+        self.redirect_hook = Some(hook);
+        self
+    }
+
+    //设置每个响应的响应体最大字节数，Content-Length超过上限时读取前立即失败，没有或错误的Content-Length在读取中超过上限时失败
+    //流式读取响应体不受限制
+    pub fn max_response_bytes(mut self, max: usize) -> Self {
+        self.max_response_bytes = Some(max);
+        self
+    }
+
+    //设置是否合并相同的进行中的get快照请求
+    pub fn coalesce(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
+    //设置每个主机的最大并发请求数和超过上限时的最大等待时长，max为0表示不限制，wait单位毫秒，默认不限制
+    //超过上限的请求不占用工作线程，在许可释放后重新派发，许可在请求回调前释放
+    pub fn per_host_limit(mut self, max: usize, wait: u64) -> Self {
+        self.max_per_host = Some(max);
+        self.per_host_wait = Some(wait);
+        self
+    }
+
+    //设置是否在发送前校验请求，校验失败时返回描述错误原因的InvalidInput错误，不发送请求
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.validate = Some(validate);
+        self
+    }
+
+    //判断是否在发送前校验请求
+    fn is_validate(&self) -> bool {
+        self.validate.unwrap_or(cfg!(debug_assertions))
+    }
+
+    //设置允许的响应头最大字节数，底层客户端已完整接收响应头，只能在读取响应体前拒绝
+    pub fn max_response_header_bytes(mut self, limit: usize) -> Self {
+        self.max_response_header_bytes = Some(limit);
+        self
+    }
+
+    //设置请求签名回调
+    pub fn signer(mut self, signer: RequestSigner) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    //增加禁止访问规则
+    pub fn deny(mut self, pattern: Atom) -> Self {
+        self.deny_rules.push(DenyRule {
+            pattern: pattern,
+        });
+        self
+    }
+
+    //设置是否拒绝主机解析为私有、回环或链路本地地址的请求
+    pub fn block_private(mut self, block: bool) -> Self {
+        self.block_private = block;
+        self
+    }
+
+    //检查请求url是否被禁止访问，主机在检查时解析，不能防止解析结果在发送请求前改变
+    fn check_blocked(&self, url: &Atom) -> Result<()> {
+        if self.deny_rules.is_empty() && !self.block_private {
+            return Ok(());
+        }
+
+        let blocked = |reason: String| -> Result<()> {
+            Err(Error::new(ErrorKind::PermissionDenied, Blocked {
+                url: url.clone(),
+                reason: reason,
+            }))
+        };
+        let parsed = match Url::parse(url.as_str()) {
+            Err(e) => return blocked(format!("invalid url, {}", e)),
+            Ok(parsed) => parsed,
+        };
+        let host = match parsed.host_str() {
+            None => return blocked("no host".to_string()),
+            Some(host) => host.trim_left_matches('[').trim_right_matches(']').to_string(),
+        };
+
+        let addrs = if let Ok(ip) = host.parse::<IpAddr>() {
+            vec![ip]
+        } else if self.block_private || self.deny_rules.iter().any(|rule| rule.is_addr()) {
+            let port = parsed.port_or_known_default().unwrap_or(0);
+            match (host.as_str(), port).to_socket_addrs() {
+                Err(e) => return blocked(format!("resolve host failed, {}", e)),
+                Ok(addrs) => addrs.map(|addr| addr.ip()).collect(),
+            }
+        } else {
+            Vec::new()
+        };
+
+        for rule in self.deny_rules.iter() {
+            if rule.is_match(&host, &addrs) {
+                return blocked(format!("deny rule, {}", *rule.pattern));
+            }
+        }
+        if self.block_private {
+            if let Some(addr) = addrs.iter().find(|addr| is_private_addr(addr)) {
+                return blocked(format!("private address, {}", addr));
+            }
+        }
+        Ok(())
+    }
+
+    //设置没有匹配规则时的代理路由目标
+    pub fn proxy_fallback(mut self, target: ProxyTarget) -> Self {
+        self.proxy_fallback = Some(target);
+        self
+    }
+
+    //设置基础选项中代理的用户名和密码，只有通过代理转发的http请求携带代理认证
+    pub fn proxy_auth(mut self, user: Atom, password: Atom) -> Self {
+        self.proxy_auth = Some((user, password));
+        self
+    }
+
+    //将扩展选项附加到构建器上
+    fn apply(&self, builder: &mut ClientBuilder, refusals: &RedirectRefusals) -> Result<()> {
+        if let Some(ref hook) = self.redirect_hook {
+            let hook = hook.clone();
+            let refusals = refusals.clone();
+            builder.redirect(RedirectPolicy::custom(move |attempt| {
+                let action = hook(attempt.url(), attempt.previous());
+                match action {
+                    RedirectAction::Follow => attempt.follow(),
+                    RedirectAction::Stop => attempt.stop(),
+                    RedirectAction::Refuse(reason) => {
+                        refusals.lock().unwrap().entry(attempt.url().as_str().to_string()).or_insert_with(Vec::new).push(reason);
+                        attempt.stop()
+                    },
+                }
+            }));
+        }
+        if !self.proxy_rules.is_empty() || self.proxy_fallback.is_some() {
+            builder.proxy(load_proxy_rules(&self.proxy_rules, &self.proxy_fallback)?);
+        }
+        Ok(())
+    }
+}
+
+/*
+* 请求使用的连接状态
+* 底层客户端reqwest 0.8没有公开连接信息，无法确定请求新建了连接还是复用了连接池中的连接，所以总是未知
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Unknown,    //未知，可能是新建的连接，也可能复用了连接池中的连接
+}
+
+/*
+* 连接状态回调，参数为请求url和连接状态，每个发送的请求在得到结果后回调一次，包括所有重试
+*/
+pub type ConnectionHook = Arc<Fn(Atom, ConnectionState) + Send + Sync>;
+
+/*
+* 请求统计，每个请求完成时提供给统计回调，包括所有重试
+*/
+#[derive(Debug, Clone)]
+pub struct RequestMetrics {
+    pub operation: Option<Atom>,    //请求的逻辑操作名，用于分组统计，避免按url分组导致基数过高
+    pub method: Atom,               //请求方法
+    pub url: String,                //请求url
+    pub status: Option<u16>,        //响应状态，请求失败则为None
+    pub elapsed: Duration,          //从开始请求到收到响应头或失败的时长
+    pub queue_wait: Duration,       //请求任务从派发到开始执行的排队时长，不经过任务派发的请求为0
+}
+
+/*
+* 请求统计回调
+*/
+pub type MetricsSink = Arc<Fn(&RequestMetrics) + Send + Sync>;
+
+/*
+* 请求拦截器，客户端按注册顺序在发送请求前调用before，请求完成时按相反顺序只调用最后一次尝试中已调用before的拦截器的after
+* 用于认证、跟踪、统计等横切逻辑，before在请求签名之后、发送之前调用，每次重试都会调用
+*/
+pub trait Interceptor: Send + Sync {
+    //发送请求前调用，可以修改请求，返回Some则不发送请求，直接以返回的结果完成请求，之后的拦截器不再调用
+    fn before(&self, _req: &mut Request) -> Option<Result<HttpClientResponse>> {
+        None
+    }
+
+    //请求完成时调用，包括被拦截器直接完成的请求，可以修改或替换请求结果，请求在调用before前失败则不调用
+    fn after(&self, result: Result<HttpClientResponse>) -> Result<HttpClientResponse> {
+        result
+    }
+}
+
+/*
+* 响应快照回调，合并的请求会在完成请求的线程中依次回调
+*/
+pub type SnapshotCallback = Box<FnBox(SharedHttpClient, Result<ResponseSnapshot>) + Send>;
+
+/*
+* 重试预算选项
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryBudgetOptions {
+    pub max_tokens: f64,    //令牌桶容量，也是初始令牌数
+    pub ratio: f64,         //每个成功的请求存入的令牌数，例如0.1表示持续的重试最多为成功请求的10%
+}
+
+impl Default for RetryBudgetOptions {
+    fn default() -> Self {
+        RetryBudgetOptions {
+            max_tokens: 10.0,
+            ratio: 0.1,
+        }
+    }
+}
+
+/*
+* 等待主机请求许可的请求，许可释放时通过任务系统派发，超过截止时间则以TimedOut错误派发
+*/
+struct HostWaiter {
+    reason: String,                             //等待超时的原因
+    deadline: Instant,                          //等待的截止时间
+    func: Box<FnBox(Result<Option<HostPermit>>)>,   //获得许可或等待超时后继续请求
+}
+
+/*
+* 每个主机的并发请求限制，请求回调前释放，超过上限的请求排队等待，不阻塞工作线程
+*/
+struct HostLimiter {
+    max: usize,                         //每个主机的最大并发请求数，为0表示不限制
+    wait: Duration,                     //超过上限时的最大等待时长
+    hosts: Mutex<HashMap<String, (usize, VecDeque<HostWaiter>)>>,  //每个主机进行中的请求数和等待的请求
+}
+
+impl HostLimiter {
+    fn new(max: usize, wait: u64) -> Self {
+        HostLimiter {
+            max: max,
+            wait: Duration::from_millis(wait),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    //获取指定url的主机的请求许可，获得许可时立即调用func，否则在许可释放后通过任务系统调用
+    //等待超时在主机的许可释放或有新请求时检查，超时的请求以TimedOut错误调用，不限制时以None调用
+    fn acquire(limiter: &Arc<HostLimiter>, url: &str, func: Box<FnBox(Result<Option<HostPermit>>)>) {
+        if limiter.max == 0 {
+            return func(Ok(None));
+        }
+        let host = match Url::parse(url) {
+            Err(_) => return func(Ok(None)),
+            Ok(url) => format!("{}://{}:{}", url.scheme(), url.host_str().unwrap_or(""), url.port_or_known_default().unwrap_or(0)),
+        };
+
+        let expired = {
+            let mut hosts = limiter.hosts.lock().unwrap();
+            let entry = hosts.entry(host.clone()).or_insert_with(|| (0, VecDeque::new()));
+            if entry.0 < limiter.max {
+                entry.0 += 1;
+                None
+            } else {
+                entry.1.push_back(HostWaiter {
+                    reason: format!("Httpc per host limit wait timeout, url: {}, max: {}", url, limiter.max),
+                    deadline: Instant::now() + limiter.wait,
+                    func: func,
+                });
+                Some(HostLimiter::expire(&mut entry.1))
+            }
+        };
+        match expired {
+            None => func(Ok(Some(HostPermit {
+                limiter: limiter.clone(),
+                host: host,
+            }))),
+            Some(expired) => HostLimiter::dispatch(expired, None),
+        }
+    }
+
+    //移除已超过截止时间的等待请求
+    fn expire(waiters: &mut VecDeque<HostWaiter>) -> Vec<HostWaiter> {
+        let now = Instant::now();
+        let (expired, waiting): (Vec<HostWaiter>, Vec<HostWaiter>) = waiters.drain(..).partition(|waiter| waiter.deadline <= now);
+        waiters.extend(waiting);
+        expired
+    }
+
+    //通过任务系统派发等待超时的请求和获得许可的请求
+    fn dispatch(expired: Vec<HostWaiter>, next: Option<(HostWaiter, HostPermit)>) {
+        for waiter in expired {
+            let HostWaiter { reason, func, .. } = waiter;
+            cast_ext_task(TaskType::Sync, 10000000, Box::new(move || {
+                func(Err(Error::from(HttpcError::Timeout(reason))));
+            }), Atom::from("httpc per host limit timeout task"));
+        }
+        if let Some((waiter, permit)) = next {
+            let func = waiter.func;
+            cast_ext_task(TaskType::Sync, 10000000, Box::new(move || {
+                func(Ok(Some(permit)));
+            }), Atom::from("httpc per host limit request task"));
+        }
+    }
+}
+
+/*
+* 主机的请求许可，释放时将许可转交给第一个未超时的等待请求
+*/
+struct HostPermit {
+    limiter: Arc<HostLimiter>,
+    host: String,
+}
+
+impl Drop for HostPermit {
+    fn drop(&mut self) {
+        let (expired, next) = {
+            let mut hosts = self.limiter.hosts.lock().unwrap();
+            let (expired, next, empty) = match hosts.get_mut(&self.host) {
+                None => (Vec::new(), None, false),
+                Some(entry) => {
+                    let expired = HostLimiter::expire(&mut entry.1);
+                    match entry.1.pop_front() {
+                        //许可直接转交，进行中的请求数不变
+                        Some(waiter) => (expired, Some(waiter), false),
+                        None => {
+                            entry.0 -= 1;
+                            (expired, None, entry.0 == 0)
+                        },
+                    }
+                },
+            };
+            if empty {
+                hosts.remove(&self.host);
+            }
+            (expired, next)
+        };
+        let next = next.map(|waiter| (waiter, HostPermit {
+            limiter: self.limiter.clone(),
+            host: self.host.clone(),
+        }));
+        HostLimiter::dispatch(expired, next);
+    }
+}
+
+/*
+* 缓冲响应体的内存预算，所有复制的客户端共享
+*/
+struct BufferBudget {
+    max: usize,         //最大总字节数
+    used: AtomicUsize,  //已占用的字节数
+}
+
+impl BufferBudget {
+    fn new(max: usize) -> Self {
+        BufferBudget {
+            max: max,
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    //占用指定字节数，超过上限则不占用并返回false
+    fn acquire(&self, size: usize) -> bool {
+        let mut used = self.used.load(Ordering::SeqCst);
+        loop {
+            if size > self.max || used > self.max - size {
+                return false;
+            }
+            let current = self.used.compare_and_swap(used, used + size, Ordering::SeqCst);
+            if current == used {
+                return true;
+            }
+            used = current;
+        }
+    }
+
+    //归还指定字节数
+    fn release(&self, size: usize) {
+        self.used.fetch_sub(size, Ordering::SeqCst);
+    }
+}
+
+/*
+* 响应占用的缓冲预算，响应释放时归还
+*/
+struct BufferLease {
+    budget: Arc<BufferBudget>,
+    size: usize,    //已占用的字节数
+}
+
+impl BufferLease {
+    //为响应占用更多字节，超过上限则返回错误
+    fn acquire(&mut self, url: &str, size: usize) -> Result<()> {
+        if !self.budget.acquire(size) {
+            return Err(Error::from(HttpcError::Request(format!("Httpc buffer budget exceeded, url: {}, size: {}, used: {}, max: {}",
+                url, self.size + size, self.budget.used.load(Ordering::SeqCst), self.budget.max))));
+        }
+        self.size += size;
+        Ok(())
+    }
+}
+
+impl Drop for BufferLease {
+    fn drop(&mut self) {
+        self.budget.release(self.size);
+    }
+}
+
+/*
+* 重试预算，令牌桶实现，成功的请求存入令牌，每次重试消耗一个令牌，令牌不足时请求立即失败而不再重试，避免重试风暴
+*/
+#[derive(Debug)]
+pub struct RetryBudget {
+    options: RetryBudgetOptions,    //选项
+    tokens: Mutex<f64>,             //当前令牌数
+}
+
+impl RetryBudget {
+    //创建重试预算
+    pub fn new(options: RetryBudgetOptions) -> Self {
+        RetryBudget {
+            options: options,
+            tokens: Mutex::new(options.max_tokens),
+        }
+    }
+
+    //获取选项
+    pub fn options(&self) -> RetryBudgetOptions {
+        self.options
+    }
+
+    //获取当前令牌数
+    pub fn tokens(&self) -> f64 {
+        *self.tokens.lock().unwrap()
+    }
+
+    //请求成功，存入令牌
+    fn deposit(&self) {
+        let tokens = &mut *self.tokens.lock().unwrap();
+        *tokens = (*tokens + self.options.ratio).min(self.options.max_tokens);
+    }
+
+    //尝试为一次重试取出令牌，令牌不足返回false
+    fn withdraw(&self) -> bool {
+        let tokens = &mut *self.tokens.lock().unwrap();
+        if *tokens < 1.0 {
+            return false;
+        }
+        *tokens -= 1.0;
+        true
+    }
+}
+
+/*
+* 通用Body
+*/
+pub trait GenHttpClientBody: Into<Body> + Send + Sync + 'static {
+    //尝试复制Body，用于重试，无法复制则返回None
+    fn try_clone(&self) -> Option<Self> where Self: Sized {
+        None
+    }
+
+    //获取Body的字节，用于请求签名，无法在发送前获取则返回None
+    fn as_bytes(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+impl GenHttpClientBody for &'static str {
+    fn try_clone(&self) -> Option<Self> {
+        Some(*self)
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(str::as_bytes(self))
+    }
+}
+impl GenHttpClientBody for String {
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(String::as_bytes(self))
+    }
+}
+impl GenHttpClientBody for Vec<u8> {
+    fn try_clone(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        Some(self.as_slice())
+    }
+}
+impl GenHttpClientBody for File {}
+
+/*
+* http的Body
+*/
+pub enum HttpClientBody<T: GenHttpClientBody> {
+    Body(T),                        //块
+    Json(HashMap<String, String>),  //json
+    Form(Form),                     //表单
+    SizedForm(SizedForm),           //可计算长度的表单，发送时设置Content-Length
+    File(File, Atom, u64),          //文件，包括文件、MIME类型和文件长度
+    Stream(Box<Read + Send>, Option<Atom>, Option<u64>),  //流，包括读取器、MIME类型和长度，未知的MIME类型和长度为None
+    MergePatch(JsonValue),          //json合并补丁，见RFC 7386
+    JsonPatch(JsonValue),           //json补丁，见RFC 6902
+    JsonBody(JsonValue),            //任意可序列化值的json
+    UrlEncoded(HashMap<String, String>),    //urlencoded表单，以application/x-www-form-urlencoded发送
+}
+
+impl<T: GenHttpClientBody> HttpClientBody<T> {
+    //创建body
+    pub fn body(body: T) -> Self {
+        HttpClientBody::Body(body)
+    }
+
+    //通过文件路径创建文件body，根据扩展名推断MIME类型
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mime = guess_mime_type(path);
+        Ok(HttpClientBody::File(file, Atom::from(mime.as_ref()), len))
+    }
+
+    //创建json
+    pub fn json(key: Atom, value: T) -> Self where T: ToString {
+        let mut map = HashMap::new();
+        map.insert((*key).clone(), value.to_string());
+        HttpClientBody::Json(map)
+    }
+
+    //由json值创建json，支持嵌套的对象、数组、数字和布尔值
+    pub fn json_value(value: JsonValue) -> Self {
+        HttpClientBody::JsonBody(value)
+    }
+
+    //获取由json_value或json_body创建的json值，字符串键值对的json返回None
+    pub fn get_json_value(&self) -> Option<&JsonValue> {
+        match self {
+            HttpClientBody::JsonBody(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    //由任意可序列化的值创建json，序列化失败则返回InvalidInput错误
+    pub fn json_body<S: Serialize>(value: &S) -> Result<Self> {
+        serde_json::to_value(value).map(HttpClientBody::JsonBody).or_else(|e| {
+            Err(Error::new(ErrorKind::InvalidInput, format!("Httpc invalid json body, reason: {}", e)))
+        })
+    }
+
+    //创建可计算长度的表单，用于拒绝分块编码的服务器
+    pub fn sized_form(key: Atom, value: T) -> Self where T: ToString {
+        HttpClientBody::SizedForm(SizedForm::new().text(key, value.to_string()))
+    }
+
+    //创建json合并补丁
+    pub fn merge_patch(patch: JsonValue) -> Self {
+        HttpClientBody::MergePatch(patch)
+    }
+
+    //创建json补丁，补丁文档应该是操作数组
+    pub fn json_patch(patch: JsonValue) -> Self {
+        HttpClientBody::JsonPatch(patch)
+    }
+
+    //创建表单
+    pub fn form(key: Atom, value: T) -> Self where T: ToString {
+        HttpClientBody::Form(Form::new().text((*key).clone(), value.to_string()))
+    }
+
+    //创建urlencoded表单
+    pub fn urlencoded(key: Atom, value: T) -> Self where T: ToString {
+        let mut map = HashMap::new();
+        map.insert((*key).clone(), value.to_string());
+        HttpClientBody::UrlEncoded(map)
+    }
+
+    //尝试复制body，用于重试，表单、文件和流无法复制，返回None
+    pub fn try_clone(&self) -> Option<Self> {
+        match self {
+            HttpClientBody::Body(body) => body.try_clone().map(HttpClientBody::Body),
+            HttpClientBody::Json(map) => Some(HttpClientBody::Json(map.clone())),
+            HttpClientBody::MergePatch(patch) => Some(HttpClientBody::MergePatch(patch.clone())),
+            HttpClientBody::JsonPatch(patch) => Some(HttpClientBody::JsonPatch(patch.clone())),
+            HttpClientBody::JsonBody(json) => Some(HttpClientBody::JsonBody(json.clone())),
+            HttpClientBody::UrlEncoded(map) => Some(HttpClientBody::UrlEncoded(map.clone())),
+            _ => None,
+        }
+    }
+
+    //获取将要发送的请求体字节，表单、文件和流无法在发送前获取，返回None
+    fn payload(&self) -> Option<Vec<u8>> {
+        match self {
+            HttpClientBody::Body(body) => body.as_bytes().map(|bytes| bytes.to_vec()),
+            HttpClientBody::Json(map) => serde_json::to_vec(map).ok(),
+            HttpClientBody::MergePatch(patch) | HttpClientBody::JsonPatch(patch) => serde_json::to_vec(patch).ok(),
+            HttpClientBody::JsonBody(json) => serde_json::to_vec(json).ok(),
+            HttpClientBody::UrlEncoded(map) => Some(encode_urlencoded(map).into_bytes()),
+            _ => None,
+        }
+    }
+
+    //获取指定关键字的json值
+    pub fn get_json_val(&self, key: Atom) -> Option<&String> {
+        match self {
+            HttpClientBody::Json(map) => {
+                map.get(&*key)
+            },
+            _ => None,
+        }
+    }
+
+    //增加json键值对，返回键值对数量
+    pub fn add_json_kv(&mut self, key: Atom, value: String) -> usize {
+        match self {
+            HttpClientBody::Json(map) => {
+                map.insert((*key).clone(), value);
+                map.len()
+            },
+            _ => 0,
+        }
+    }
+
+    //移除指定关键字的json键值对，返回被移除的值
+    pub fn remove_json_kv(&mut self, key: Atom) -> Option<String> {
+        match self {
+            HttpClientBody::Json(map) => {
+                map.remove(&*key)
+            },
+            _ => None,
+        }
+    }
+
+    //清空所有json键值对
+    pub fn clear_json_kvs(&mut self) {
+        match self {
+            HttpClientBody::Json(map) => {
+                map.clear()
+            },
+            _ => (),
+        }
+    }
+
+    //增加表单键值对
+    pub fn add_form_kv(self, key: Atom, value: String) -> Self {
+        match self {
+            HttpClientBody::Form(form) => {
+                HttpClientBody::Form(form.text((*key).clone(), value))
+            },
+            HttpClientBody::SizedForm(form) => {
+                HttpClientBody::SizedForm(form.text(key, value))
+            },
+            _ => self,
+        }
+    }
+
+    //增加urlencoded表单键值对
+    pub fn add_urlencoded_kv(self, key: Atom, value: String) -> Self {
+        match self {
+            HttpClientBody::UrlEncoded(mut map) => {
+                map.insert((*key).clone(), value);
+                HttpClientBody::UrlEncoded(map)
+            },
+            _ => self,
+        }
+    }
+
+    //获取指定关键字的urlencoded表单值
+    pub fn get_urlencoded_val(&self, key: Atom) -> Option<&String> {
+        match self {
+            HttpClientBody::UrlEncoded(map) => map.get(&*key),
+            _ => None,
+        }
+    }
+
+    //增加表单文件
+    pub fn add_form_file<P: AsRef<Path>>(self, key: Atom, file: P) -> Result<Self> {
+        match self {
+            HttpClientBody::Form(form) => {
+                form.file((*key).clone(), file).or_else(|e| {
+                    Err(Error::new(ErrorKind::Other, e.description().to_string()))
+                }).and_then(|f| {
+                    Ok(HttpClientBody::Form(f))
+                })
+            },
+            HttpClientBody::SizedForm(form) => {
+                form.file(key, file).and_then(|f| {
+                    Ok(HttpClientBody::SizedForm(f))
+                })
+            },
+            _ => Ok(self),
+        }
+    }
+}
+
+/*
+* 可计算长度的表单分隔符计数器，保证同一进程内分隔符唯一
+*/
+static BOUNDARY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/*
+* 可计算长度的表单条目
+*/
+enum SizedPart {
+    Text(String, String),                   //文本，包括关键字和值
+    File(String, String, Atom, File, u64),  //文件，包括关键字、文件名、MIME类型、文件和文件长度
+}
+
+/*
+* 可计算长度的multipart表单，在发送前计算总长度并设置Content-Length，流式发送所有条目
+*/
+pub struct SizedForm {
+    boundary: String,       //分隔符
+    parts: Vec<SizedPart>,  //条目
+}
+
+impl SizedForm {
+    //创建空表单
+    pub fn new() -> Self {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        SizedForm {
+            boundary: format!("{:016x}{:08x}{:08x}", now.as_secs(), now.subsec_nanos(), BOUNDARY_COUNT.fetch_add(1, Ordering::Relaxed)),
+            parts: Vec::new(),
+        }
+    }
+
+    //获取分隔符
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    //增加文本条目
+    pub fn text(mut self, key: Atom, value: String) -> Self {
+        self.parts.push(SizedPart::Text((*key).clone(), value));
+        self
+    }
+
+    //增加文件条目，根据扩展名推断MIME类型
+    pub fn file<P: AsRef<Path>>(mut self, key: Atom, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let mime = guess_mime_type(path);
+        self.parts.push(SizedPart::File((*key).clone(), name, Atom::from(mime.as_ref()), file, len));
+        Ok(self)
+    }
+
+    //获取表单的Content-Type
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    //获取表单总长度，包括所有条目头、分隔符和条目内容
+    pub fn content_length(&self) -> u64 {
+        let mut len = 0;
+        for part in &self.parts {
+            len += self.part_header(part).len() as u64;
+            len += match *part {
+                SizedPart::Text(_, ref value) => value.len() as u64,
+                SizedPart::File(_, _, _, _, size) => size,
+            };
+            len += 2;
+        }
+        len + self.end().len() as u64
+    }
+
+    //获取条目头
+    fn part_header(&self, part: &SizedPart) -> String {
+        match *part {
+            SizedPart::Text(ref key, _) => {
+                format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n", self.boundary, key)
+            },
+            SizedPart::File(ref key, ref name, ref mime, _, _) => {
+                format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n", self.boundary, key, name, **mime)
+            },
+        }
+    }
+
+    //获取表单结束符
+    fn end(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+
+    //转换为流式读取器，文件条目只读取声明的长度
+    fn into_reader(self) -> Box<Read + Send> {
+        let end = self.end();
+        let headers: Vec<String> = self.parts.iter().map(|part| self.part_header(part)).collect();
+        let mut reader: Box<Read + Send> = Box::new(Cursor::new(Vec::new()));
+        for (header, part) in headers.into_iter().zip(self.parts.into_iter()) {
+            reader = Box::new(reader.chain(Cursor::new(header.into_bytes())));
+            reader = match part {
+                SizedPart::Text(_, value) => Box::new(reader.chain(Cursor::new(value.into_bytes()))),
+                SizedPart::File(_, _, _, file, size) => Box::new(reader.chain(file.take(size))),
+            };
+            reader = Box::new(reader.chain(Cursor::new(b"\r\n".to_vec())));
+        }
+        Box::new(reader.chain(Cursor::new(end.into_bytes())))
+    }
+}
+
+/*
+* W3C跟踪上下文头
+*/
+const TRACE_PARENT: &str = "traceparent";
+const TRACE_STATE: &str = "tracestate";
+
+/*
+* 预热连接的初始重试间隔，单位毫秒
+*/
+const WARMUP_RETRY_DELAY: u64 = 100;
+
+/*
+* 预热连接的最大重试间隔，单位毫秒
+*/
+const WARMUP_MAX_RETRY_DELAY: u64 = 5000;
+
+/*
+* PEM证书的开始和结束标记
+*/
+const PEM_CERT_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_CERT_END: &str = "-----END CERTIFICATE-----";
+
+/*
+* 请求重试的最大退避时长，单位毫秒
+*/
+const MAX_RETRY_BACKOFF: u64 = 30000;
+
+/*
+* 共享http客户端
+*/
+pub trait SharedHttpc {
+    //构建http客户端
+    fn create(options: HttpClientOptions) -> Result<Arc<Self>>;
+    //使用扩展选项构建http客户端
+    fn create_ext(options: HttpClientOptions, ext: HttpClientExtOptions) -> Result<Arc<Self>>;
+    //构建http客户端，并通过预热url验证连接，失败则按指数退避重试，直到连接成功或重试次数用尽
+    fn create_and_connect(options: HttpClientOptions, warmup_url: Atom, retries: usize) -> Result<Arc<Self>>;
+    //同create_and_connect，但所有尝试的总时长不超过指定的截止时间，超过截止时间则立即失败，无论是否还有剩余重试次数
+    fn create_and_connect_until(options: HttpClientOptions, warmup_url: Atom, retries: usize, deadline: Instant) -> Result<Arc<Self>>;
+    //增加指定关键字的http头条目，返回头条目数量，一个关键字可以有多个条目，Host除外，Host会替换已有的条目
+    fn add_header(client: &mut SharedHttpClient, key: Atom, value: Atom) -> usize;
+    //设置独立于url的Host头，用于访问共享ip后的指定虚拟主机，注意tls的SNI和证书验证仍然使用url中的主机，重定向后的请求也会继续使用此Host
+    fn set_host(client: &mut SharedHttpClient, host: Atom);
+    //设置Basic认证的Authorization头，内部完成user:password的base64编码，替换已设置的Authorization头
+    fn basic_auth(client: &mut SharedHttpClient, user: Atom, password: Option<Atom>);
+    //设置Bearer认证的Authorization头，替换已设置的Authorization头
+    fn bearer_auth(client: &mut SharedHttpClient, token: Atom);
+    //移除指定关键字的http头条目，返回头条目数量
+    fn remove_header(client: &mut SharedHttpClient, key: Atom) -> usize;
+    //移除关键字以指定前缀开始的所有http头条目，不区分大小写，返回被移除的关键字数量
+    fn remove_headers_by_prefix(client: &mut SharedHttpClient, prefix: &str) -> usize;
+    //清空http头条目
+    fn clear_headers(client: &mut SharedHttpClient);
+    //同时设置W3C跟踪上下文的traceparent和tracestate头，traceparent格式无效则返回错误且不修改任何头，state为None则移除tracestate头
+    fn set_trace_context(client: &mut SharedHttpClient, parent: Atom, state: Option<Atom>) -> Result<()>;
+    //设置异步请求的任务名，用于在任务统计中区分请求的用途，None表示使用默认的任务名
+    fn set_task_name(client: &mut SharedHttpClient, name: Option<Atom>);
+    //获取设置的异步请求任务名
+    fn get_task_name(&self) -> Option<Atom>;
+    //设置请求的截止时间，包括所有重试和退避，每次尝试和退避前检查，超过则以HttpcError::Timeout错误失败，None表示不限制
+    //单次尝试仍然只受超时时长限制，截止时间不能中断正在进行的尝试
+    fn set_deadline(client: &mut SharedHttpClient, deadline: Option<Instant>);
+    //获取设置的请求截止时间
+    fn get_deadline(&self) -> Option<Instant>;
+    //注册客户端身份，使用相同的构建选项和指定的pkcs12身份文件构建独立的内部客户端，已注册的同名身份会被替换，所有复制的客户端共享
+    fn register_identity(client: &SharedHttpClient, name: Atom, identity_file: PathBuf, pk: String) -> Result<()>;
+    //移除已注册的客户端身份，返回身份是否存在
+    fn remove_identity(client: &SharedHttpClient, name: Atom) -> bool;
+    //选择请求使用的客户端身份，None表示使用构建选项中的身份，选择的身份未注册时请求返回NotFound错误
+    fn select_identity(client: &mut SharedHttpClient, name: Option<Atom>);
+    //获取请求使用的客户端身份
+    fn get_identity(&self) -> Option<Atom>;
+    //异步发送get请求，返回可以取消请求的句柄
+    fn get<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) -> RequestHandle;
+    //异步发送get请求，并指定请求的逻辑操作名，用于统计和日志
+    fn get_with_operation<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, operation: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //异步发送post请求，并指定请求的逻辑操作名，用于统计和日志
+    fn post_with_operation<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, operation: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //异步发送post请求，返回可以取消请求的句柄
+    fn post<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) -> RequestHandle;
+    //异步发送get请求，查询参数按顺序编码后追加到url已有的查询参数之后，支持重复的关键字
+    fn get_with_query<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, query: Vec<(String, String)>, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //异步发送post请求，查询参数按顺序编码后追加到url已有的查询参数之后，支持重复的关键字，请求体不受影响
+    fn post_with_query<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, query: Vec<(String, String)>, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //异步发送get请求，并指定本次请求的超时时长，None表示使用构建选项中的超时时长，超时返回TimedOut错误，每个超时时长使用单独的连接池
+    fn get_with_timeout<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, timeout: Option<Duration>, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //异步发送post请求，并指定本次请求的超时时长，None表示使用构建选项中的超时时长，超时返回TimedOut错误，每个超时时长使用单独的连接池
+    fn post_with_timeout<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, timeout: Option<Duration>, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //异步发送patch请求
+    fn patch<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //异步发送put请求
+    fn put<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //异步发送delete请求
+    fn delete<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //异步发送head请求，响应没有响应体，读取响应体时返回空
+    fn head(client: &SharedHttpClient, url: Atom, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //在当前线程中同步发送get请求并返回响应，不派发异步任务，使用相同的内部客户端和连接池
+    fn get_sync<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>) -> Result<HttpClientResponse>;
+    //在当前线程中同步发送post请求并返回响应，不派发异步任务，使用相同的内部客户端和连接池
+    fn post_sync<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>) -> Result<HttpClientResponse>;
+    //异步发送get请求，并将响应体流式写入文件，每次写入后回调已写入的字节数和Content-Length，没有Content-Length时总字节数为None，回调写入的总字节数
+    //响应状态不是2xx时返回错误，不写入文件
+    fn get_to_file<T: GenHttpClientBody, P: AsRef<Path>>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, path: P, on_progress: Box<Fn(u64, Option<u64>) + Send>, callback: Box<FnBox(Arc<Self>, Result<u64>)>);
+    //异步发送get请求，并读取全部响应为快照，开启合并时，相同的进行中的请求只发送一次，所有回调获得复制的结果
+    fn get_snapshot(client: &SharedHttpClient, url: Atom, callback: SnapshotCallback);
+    //异步从get_url获取响应体，并以流的方式作为请求体put到put_url，不缓冲整个响应体，回调put请求的响应
+    fn pipe(client: &SharedHttpClient, get_url: Atom, put_url: Atom, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>);
+    //获取当前http头条目数量
+    fn headers_size(&self) -> usize;
+    //获取所有http头条目关键字
+    fn headers_keys(&self) -> Option<Vec<Atom>>;
+    //获取指定关键字的http头条目，一个关键字可以有多个条目
+    fn get_header(&self, key: Atom) -> Option<Vec<Atom>>;
+}
+
+/*
+* 共享http客户端
+*/
+pub type SharedHttpClient = Arc<HttpClient>;
+
+/*
+* http客户端
+*/
+#[derive(Clone)]
+pub struct HttpClient {
+    inner: Client,                      //内部客户端，因为Client依赖的mio有一个在windows下无法正常关闭socket的bug，至今未解决，所以尽量复用同一个Client，详见https://github.com/seanmonstar/reqwest/issues?utf8=%E2%9C%93&q=close 和 https://github.com/carllerche/mio/issues/776
+    options: Arc<HttpClientOptions>,    //构建选项，用于构建客户端身份和超时时长的内部客户端
+    ext: Arc<HttpClientExtOptions>,     //扩展构建选项，用于构建客户端身份和超时时长的内部客户端
+    budget: Option<Arc<RetryBudget>>,   //重试预算，所有复制的客户端共享
+    limiter: Arc<HostLimiter>,          //每个主机的并发请求限制，所有复制的客户端共享
+    delays: Arc<DelayQueue>,            //重试退避的延迟任务队列，所有复制的客户端共享
+    buffers: Option<Arc<BufferBudget>>, //缓冲响应体的内存预算，所有复制的客户端共享
+    in_flight: Arc<Mutex<HashMap<String, Vec<SnapshotCallback>>>>,  //进行中的合并请求，值为等待结果的回调
+    headers: Headers,                   //请求头
+    task_name: Option<Atom>,            //异步请求的任务名，None表示使用默认的任务名
+    identities: Arc<RwLock<HashMap<Atom, IdentityClient>>>, //已注册的客户端身份，所有复制的客户端共享
+    identity: Option<Atom>,             //请求使用的客户端身份，None表示使用构建选项中的身份
+    timeout: Option<Duration>,          //本次请求的超时时长，None表示使用构建选项中的超时时长
+    deadline: Option<Instant>,          //请求的截止时间，包括所有重试和退避，None表示不限制
+    timeout_clients: Arc<Mutex<HashMap<(Option<Atom>, Duration), Client>>>,  //指定超时时长的内部客户端，键为客户端身份和超时时长，所有复制的客户端共享
+    refusals: RedirectRefusals,         //被重定向策略拒绝的重定向，所有内部客户端共享
+}
+
+/*
+* 使用指定客户端身份的内部客户端
+*/
+struct IdentityClient {
+    identity_file: PathBuf, //身份文件，用于构建指定超时时长的内部客户端
+    pk: String,             //身份文件的密码
+    inner: Client,          //内部客户端
+}
+
+impl HttpClient {
+    //获取可用的内部客户端
+    fn inner(&self) -> Result<Client> {
+        if let Some(timeout) = self.timeout {
+            return self.timeout_inner(timeout);
+        }
+        match self.identity {
+            None => Ok(self.inner.clone()),
+            Some(ref name) => match self.identities.read().unwrap().get(name) {
+                None => Err(Error::new(ErrorKind::NotFound, format!("Httpc identity not registered, identity: {}", **name))),
+                Some(client) => Ok(client.inner.clone()),
+            },
+        }
+    }
+
+    //获取指定超时时长的内部客户端，底层客户端只能在构建时设置超时时长，所以每个客户端身份和超时时长使用单独的内部客户端和连接池
+    //第一次使用时构建，之后复用，超时时长应该只取少数固定的值
+    fn timeout_inner(&self, timeout: Duration) -> Result<Client> {
+        let key = (self.identity.clone(), timeout);
+        if let Some(inner) = self.timeout_clients.lock().unwrap().get(&key) {
+            return Ok(inner.clone());
+        }
+
+        let options = self.options.with_timeout_duration(Some(timeout));
+        let inner = match self.identity {
+            None => new_inner(&options, &self.ext, None, &self.refusals)?,
+            Some(ref name) => match self.identities.read().unwrap().get(name) {
+                None => return Err(Error::new(ErrorKind::NotFound, format!("Httpc identity not registered, identity: {}", **name))),
+                Some(client) => new_inner(&options, &self.ext, Some((&client.identity_file, client.pk.as_str())), &self.refusals)?,
+            },
+        };
+        //并发构建时只保留先完成的内部客户端
+        Ok(self.timeout_clients.lock().unwrap().entry(key).or_insert(inner).clone())
+    }
+
+    //取出停止的重定向响应被重定向策略拒绝的原因，不是被拒绝的重定向则返回None
+    fn take_refusal(&self, resp: &HttpClientResponse) -> Option<String> {
+        let location = match resp.inner.headers().get_raw("Location").and_then(|val| val.one()) {
+            None => return None,
+            Some(location) => String::from_utf8_lossy(location).into_owned(),
+        };
+        let next = match resp.inner.url().join(location.trim()) {
+            Err(_) => return None,
+            Ok(next) => next,
+        };
+
+        let mut refusals = self.refusals.lock().unwrap();
+        let (reason, empty) = match refusals.get_mut(next.as_str()) {
+            None => return None,
+            Some(reasons) => (reasons.remove(0), reasons.len() == 0),
+        };
+        if empty {
+            refusals.remove(next.as_str());
+        }
+        Some(reason)
+    }
+
+    //获取发送给基础选项中代理的认证头，只有http请求由代理转发，https请求通过隧道发送，不能携带代理认证，也不能发送给目标服务器
+    fn proxy_authorization(&self, url: &Url) -> Option<ProxyAuthorization<Basic>> {
+        if !url.scheme().eq_ignore_ascii_case("http") {
+            return None;
+        }
+        let proxy_url = match self.options.parts().1 {
+            None => return None,
+            Some(proxy_url) => proxy_url,
+        };
+        proxy_credentials(&proxy_url, &self.ext.proxy_auth).map(|(user, password)| ProxyAuthorization(Basic {
+            username: user,
+            password: Some(password),
+        }))
+    }
+
+    //判断请求是否可能通过代理
+    fn is_proxied(&self) -> bool {
+        self.options.parts().1.is_some() || !self.ext.proxy_rules.is_empty() || self.ext.proxy_fallback.is_some()
+    }
+
+    //获取合并请求的关键字，由方法、url、客户端身份、超时时长和请求头组成，未开启合并则返回None
+    //使用不同身份或超时时长的请求结果可能不同，不会被合并
+    fn coalesce_key(&self, method: &Method, url: &Atom) -> Option<String> {
+        if !self.ext.coalesce {
+            return None;
+        }
+
+        let identity = self.identity.as_ref().map(|identity| identity.as_str()).unwrap_or("");
+        let mut key = format!("{} {}\r\nidentity: {}\r\ntimeout: {:?}", method, **url, identity, self.timeout);
+        for header in self.headers.iter() {
+            key.push_str(&format!("\r\n{}: {}", header.name().to_lowercase(), header.value_string()));
+        }
+        Some(key)
+    }
+
+    //获取异步请求的任务名，未设置则使用指定的默认任务名
+    fn task_name(&self, default: &str) -> Atom {
+        match self.task_name {
+            Some(ref name) => name.clone(),
+            None => Atom::from(default),
+        }
+    }
+
+    //创建绑定基础url的客户端，复制的客户端共享连接池、重试预算和并发限制
+    pub fn with_base_url(&self, base: Atom) -> BaseUrlClient {
+        BaseUrlClient::new(Arc::new(self.clone()), base)
+    }
+
+    //获取重试预算，未设置则返回None
+    pub fn retry_budget(&self) -> Option<Arc<RetryBudget>> {
+        self.budget.clone()
+    }
+
+    //获取所有复制的客户端缓冲响应体已占用的字节数，未设置缓冲预算则返回None
+    pub fn buffered_bytes(&self) -> Option<usize> {
+        self.buffers.as_ref().map(|budget| budget.used.load(Ordering::SeqCst))
+    }
+}
+
+//构建内部客户端，指定客户端身份时替换构建选项中的身份
+fn new_inner(options: &HttpClientOptions, ext: &HttpClientExtOptions, identity: Option<(&PathBuf, &str)>, refusals: &RedirectRefusals) -> Result<Client> {
+    let mut builder = ClientBuilder::new();
+    match *options {
+        HttpClientOptions::Default => {
+            builder.danger_disable_hostname_verification();
+        },
+        HttpClientOptions::Normal(gzip, referer, count, timeout) => {
+            builder.danger_disable_hostname_verification()
+                    .gzip(gzip)
+                    .referer(referer)
+                    .redirect(redirect_policy(count))
+                    .timeout(to_timeout(timeout));
+        },
+        HttpClientOptions::VaildHost(ref cert_file, ref identity_file, ref pk, gzip, referer, count, timeout) => {
+            for cert in load_certs(cert_file)? {
+                builder.add_root_certificate(cert);
+            }
+            builder.identity(load_identity(identity_file, pk)?)
+                    .gzip(gzip)
+                    .referer(referer)
+                    .redirect(redirect_policy(count))
+                    .timeout(to_timeout(timeout));
+        },
+        HttpClientOptions::Proxy(ref proxy_url, gzip, referer, count, timeout) => {
+            builder.danger_disable_hostname_verification()
+                    .proxy(load_proxy(proxy_url)?)
+                    .gzip(gzip)
+                    .referer(referer)
+                    .redirect(redirect_policy(count))
+                    .timeout(to_timeout(timeout));
+        },
+        HttpClientOptions::ValidHostProxy(ref cert_file, ref identity_file, ref pk, ref proxy_url, gzip, referer, count, timeout) => {
+            for cert in load_certs(cert_file)? {
+                builder.add_root_certificate(cert);
+            }
+            builder.identity(load_identity(identity_file, pk)?)
+                    .proxy(load_proxy(proxy_url)?)
+                    .gzip(gzip)
+                    .referer(referer)
+                    .redirect(redirect_policy(count))
+                    .timeout(to_timeout(timeout));
+        },
+    }
+
+    if let Some((identity_file, pk)) = identity {
+        builder.identity(load_identity(identity_file, pk)?);
+    }
+    ext.apply(&mut builder, refusals)?;
+    builder.build().or_else(|e| {
+        Err(Error::from(HttpcError::Request(format!("Httpc build client failed, reason: {}", e.description()))))
+    })
+}
+
+//通过预热url验证连接，失败则按指数退避重试，指定截止时间则所有尝试和等待都不会超过截止时间
+fn warmup(client: SharedHttpClient, warmup_url: &Atom, retries: usize, deadline: Option<Instant>) -> Result<SharedHttpClient> {
+    let mut delay = WARMUP_RETRY_DELAY;
+    let mut retry = 0;
+    loop {
+        if let Some(deadline) = deadline {
+            if Instant::now() >= deadline {
+                return Err(Error::new(ErrorKind::TimedOut, format!("Httpc warmup deadline exceeded, url: {}, retry: {}", **warmup_url, retry)));
+            }
+        }
+
+        match client.inner()?.head((**warmup_url).as_str()).send() {
+            Ok(_) => return Ok(client),
+            Err(e) => {
+                if retry >= retries {
+                    return Err(Error::from(classify_error(&e, format!("Httpc warmup failed, url: {}, retries: {}, reason: {}", **warmup_url, retries, e.description()))));
+                }
+                println!("!!!> Httpc Warmup Error, url: {}, retry: {}, reason: {:?}", **warmup_url, retry, e.description());
+            }
+        }
+
+        let mut wait = Duration::from_millis(delay);
+        if let Some(deadline) = deadline {
+            //等待不能超过截止时间
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::new(ErrorKind::TimedOut, format!("Httpc warmup deadline exceeded, url: {}, retry: {}", **warmup_url, retry)));
+            }
+            wait = wait.min(deadline - now);
+        }
+        thread::sleep(wait);
+        delay = (delay * 2).min(WARMUP_MAX_RETRY_DELAY);
+        retry += 1;
+    }
+}
+
+//将毫秒转换为超时时长，为0或超过上限表示不超时
+fn to_timeout(timeout: u64) -> Option<Duration> {
+    if timeout == 0 || timeout > MAX_TIMEOUT {
+        None
+    } else {
+        Some(Duration::from_millis(timeout))
+    }
+}
+
+//获取重定向策略，小于0表示不允许重定向
+fn redirect_policy(count: isize) -> RedirectPolicy {
+    if count < 0 {
+        RedirectPolicy::none()
+    } else {
+        RedirectPolicy::limited(count as usize)
+    }
+}
+
+//加载根证书，文件内容是PEM时加载其中的所有证书，否则作为一个DER证书加载
+fn load_certs(cert_file: &PathBuf) -> Result<Vec<Certificate>> {
+    let mut cert_buf = Vec::new();
+    File::open(cert_file)?.read_to_end(&mut cert_buf)?;
+    let text = String::from_utf8_lossy(&cert_buf).into_owned();
+    if !text.contains(PEM_CERT_BEGIN) {
+        return Certificate::from_der(&cert_buf).map(|cert| vec![cert]).or_else(|e| {
+            Err(Error::from(HttpcError::Tls(format!("Httpc invalid der cert, file: {:?}, reason: {}", cert_file, e.description()))))
+        });
+    }
+
+    let mut certs = Vec::new();
+    for block in pem_certs(&text) {
+        match Certificate::from_pem(block.as_bytes()) {
+            Err(e) => return Err(Error::new(ErrorKind::InvalidData, format!("Httpc invalid pem cert, file: {:?}, index: {}, reason: {}", cert_file, certs.len(), e.description()))),
+            Ok(cert) => certs.push(cert),
+        }
+    }
+    if certs.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Httpc invalid pem cert, file: {:?}, reason: no complete cert", cert_file)));
+    }
+    Ok(certs)
+}
+
+//拆分PEM证书包，返回其中每个完整的证书，忽略证书之间的注释和其它类型的块
+pub fn pem_certs(text: &str) -> Vec<String> {
+    let mut certs = Vec::new();
+    let mut rest = text;
+    while let Some(begin) = rest.find(PEM_CERT_BEGIN) {
+        rest = &rest[begin..];
+        match rest.find(PEM_CERT_END) {
+            None => break,
+            Some(end) => {
+                let end = end + PEM_CERT_END.len();
+                certs.push(format!("{}\n", &rest[..end]));
+                rest = &rest[end..];
+            },
+        }
+    }
+    certs
+}
+
+//加载客户端身份
+fn load_identity(identity_file: &PathBuf, pk: &str) -> Result<Identity> {
+    let mut identity_buf = Vec::new();
+    File::open(identity_file)?.read_to_end(&mut identity_buf)?;
+    Identity::from_pkcs12_der(&identity_buf, pk).or_else(|e| {
+        Err(Error::from(HttpcError::Tls(format!("Httpc invalid identity, file: {:?}, reason: {}", identity_file, e.description()))))
+    })
+}
+
+//加载代理，http代理只代理http请求，底层客户端不支持socks5代理
+//代理url中的用户信息不作为代理url的一部分，认证由请求的Proxy-Authorization头发送
+fn load_proxy(proxy_url: &Atom) -> Result<Proxy> {
+    let mut url = Url::parse(proxy_url.as_str()).or_else(|e| {
+        Err(Error::new(ErrorKind::InvalidInput, format!("Proxy, invalid url, reason: {}", e)))
+    })?;
+
+    match url.scheme().to_lowercase().as_str() {
+        "http" | "https" => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            Proxy::http(url.as_str()).or_else(|e| {
+                Err(Error::from(HttpcError::Request(format!("Proxy, invalid url, reason: {}", e.description()))))
+            })
+        },
+        scheme => Err(Error::new(ErrorKind::InvalidInput, format!("Proxy, unsupported scheme: {}", scheme))),
+    }
+}
+
+//获取代理的用户名和密码，指定认证时替换代理url中的用户信息，都没有则返回None
+fn proxy_credentials(proxy_url: &Atom, auth: &Option<(Atom, Atom)>) -> Option<(String, String)> {
+    if let Some((ref user, ref password)) = *auth {
+        return Some(((**user).clone(), (**password).clone()));
+    }
+    match Url::parse(proxy_url.as_str()) {
+        Ok(ref url) if !url.username().is_empty() => Some((percent_decode(url.username()), url.password().map(percent_decode).unwrap_or_default())),
+        _ => None,
+    }
+}
+
+//解码url中百分号编码的用户信息，无效的编码保持不变
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut buf = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 2 < bytes.len() && (bytes[index + 1] as char).is_digit(16) && (bytes[index + 2] as char).is_digit(16) {
+            buf.push(u8::from_str_radix(&s[index + 1..index + 3], 16).unwrap());
+            index += 3;
+            continue;
+        }
+        buf.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+//加载代理路由规则，构建时解析所有代理url，请求时按主机选择代理
+fn load_proxy_rules(rules: &Vec<ProxyRule>, fallback: &Option<ProxyTarget>) -> Result<Proxy> {
+    let parse = |target: &ProxyTarget| -> Result<Option<Url>> {
+        match *target {
+            ProxyTarget::Direct => Ok(None),
+            ProxyTarget::Proxy(ref url) => {
+                Url::parse(url.as_str()).and_then(|url| Ok(Some(url))).or_else(|e| {
+                    Err(Error::new(ErrorKind::InvalidInput, format!("Proxy, invalid url: {}, reason: {}", **url, e)))
+                })
+            },
+        }
+    };
+
+    let mut routes = Vec::with_capacity(rules.len());
+    for rule in rules {
+        routes.push((rule.clone(), parse(&rule.target)?));
+    }
+    let fallback = match *fallback {
+        None => None,
+        Some(ref target) => parse(target)?,
+    };
+
+    Ok(Proxy::custom(move |url: &Url| {
+        let host = match url.host_str() {
+            None => return fallback.clone(),
+            Some(host) => host,
+        };
+        for &(ref rule, ref proxy) in routes.iter() {
+            if rule.is_match(host) {
+                return proxy.clone();
+            }
+        }
+        fallback.clone()
+    }))
+}
+
+impl SharedHttpc for HttpClient {
+    fn create(options: HttpClientOptions) -> Result<Arc<Self>> {
+        Self::create_ext(options, HttpClientExtOptions::default())
+    }
+
+    fn create_ext(options: HttpClientOptions, ext: HttpClientExtOptions) -> Result<Arc<Self>> {
+        let refusals: RedirectRefusals = Arc::new(Mutex::new(HashMap::new()));
+        new_inner(&options, &ext, None, &refusals).and_then(|inner| {
+            let budget = ext.retry_budget.map(|options| Arc::new(RetryBudget::new(options)));
+            let limiter = HostLimiter::new(ext.max_per_host.unwrap_or(0), ext.per_host_wait.unwrap_or(DEFAULT_PER_HOST_WAIT));
+            let buffers = ext.max_buffered_bytes.map(|max| Arc::new(BufferBudget::new(max)));
+            Ok(Arc::new(HttpClient {
+                inner: inner,
+                options: Arc::new(options),
+                ext: Arc::new(ext),
+                budget: budget,
+                limiter: Arc::new(limiter),
+                delays: Arc::new(DelayQueue::new()),
+                buffers: buffers,
+                in_flight: Arc::new(Mutex::new(HashMap::new())),
+                headers: Headers::new(),
+                task_name: None,
+                identities: Arc::new(RwLock::new(HashMap::new())),
+                identity: None,
+                timeout: None,
+                deadline: None,
+                timeout_clients: Arc::new(Mutex::new(HashMap::new())),
+                refusals: refusals,
+            }))
+        })
+    }
+
+    fn create_and_connect(options: HttpClientOptions, warmup_url: Atom, retries: usize) -> Result<Arc<Self>> {
+        let client = Self::create(options)?;
+        warmup(client, &warmup_url, retries, None)
+    }
+
+    fn create_and_connect_until(options: HttpClientOptions, warmup_url: Atom, retries: usize, deadline: Instant) -> Result<Arc<Self>> {
+        let client = Self::create(options)?;
+        warmup(client, &warmup_url, retries, Some(deadline))
+    }
+
+    fn add_header(client: &mut Arc<HttpClient>, key: Atom, value: Atom) -> usize {
+        if key.eq_ignore_ascii_case("Host") {
+            //Host只能有一个条目
+            Self::set_host(client, value);
+            return client.headers.len();
+        }
+
+        Arc::make_mut(client).headers.append_raw((*key).clone(), (*value).as_str());
+        client.headers.len()
+    }
+
+    fn set_host(client: &mut Arc<HttpClient>, host: Atom) {
+        //已设置Host头时，底层客户端不会再用url的主机覆盖
+        Arc::make_mut(client).headers.set_raw("Host", (*host).as_str());
+    }
+
+    fn basic_auth(client: &mut Arc<HttpClient>, user: Atom, password: Option<Atom>) {
+        Arc::make_mut(client).headers.set(Authorization(Basic {
+            username: (*user).clone(),
+            password: password.map(|password| (*password).clone()),
+        }));
+    }
+
+    fn bearer_auth(client: &mut Arc<HttpClient>, token: Atom) {
+        Arc::make_mut(client).headers.set(Authorization(Bearer {
+            token: (*token).clone(),
+        }));
+    }
+
+    fn remove_header(client: &mut Arc<HttpClient>, key: Atom) -> usize {
+        Arc::make_mut(client).headers.remove_raw((*key).as_str());
+        client.headers.len()
+    }
+
+    fn remove_headers_by_prefix(client: &mut Arc<HttpClient>, prefix: &str) -> usize {
+        let prefix = prefix.to_lowercase();
+        let keys: Vec<String> = client.headers.iter()
+            .map(|header| header.name().to_string())
+            .filter(|name| name.to_lowercase().starts_with(&prefix))
+            .collect();
+        if keys.is_empty() {
+            return 0;
+        }
+
+        let headers = &mut Arc::make_mut(client).headers;
+        for key in &keys {
+            headers.remove_raw(key);
+        }
+        keys.len()
+    }
+
+    fn clear_headers(client: &mut Arc<HttpClient>) {
+        Arc::make_mut(client).headers.clear();
+    }
+
+    fn set_trace_context(client: &mut Arc<HttpClient>, parent: Atom, state: Option<Atom>) -> Result<()> {
+        if !is_valid_traceparent(&parent) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Trace context, invalid traceparent: {}", *parent)));
+        }
+
+        let headers = &mut Arc::make_mut(client).headers;
+        headers.set_raw(TRACE_PARENT, (*parent).as_str());
+        match state {
+            Some(state) => headers.set_raw(TRACE_STATE, (*state).as_str()),
+            None => headers.remove_raw(TRACE_STATE),
+        }
+        Ok(())
+    }
+
+    fn set_task_name(client: &mut SharedHttpClient, name: Option<Atom>) {
+        Arc::make_mut(client).task_name = name;
+    }
+
+    fn get_task_name(&self) -> Option<Atom> {
+        self.task_name.clone()
+    }
+
+    fn set_deadline(client: &mut SharedHttpClient, deadline: Option<Instant>) {
+        Arc::make_mut(client).deadline = deadline;
+    }
+
+    fn get_deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn register_identity(client: &SharedHttpClient, name: Atom, identity_file: PathBuf, pk: String) -> Result<()> {
+        let inner = new_inner(&client.options, &client.ext, Some((&identity_file, pk.as_str())), &client.refusals)?;
+        client.identities.write().unwrap().insert(name, IdentityClient {
+            identity_file: identity_file,
+            pk: pk,
+            inner: inner,
+        });
+        Ok(())
+    }
+
+    fn remove_identity(client: &SharedHttpClient, name: Atom) -> bool {
+        client.identities.write().unwrap().remove(&name).is_some()
+    }
+
+    fn select_identity(client: &mut SharedHttpClient, name: Option<Atom>) {
+        Arc::make_mut(client).identity = name;
+    }
+
+    fn get_identity(&self) -> Option<Atom> {
+        self.identity.clone()
+    }
+
+    fn get<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) -> RequestHandle {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let handle = RequestHandle::new();
+        let cancelled = handle.clone();
+        let func = move || {
+            if cancelled.is_cancelled() {
+                //执行前已取消，不发送请求
+                return callback(copy, Err(cancelled_error(&url)));
+            }
+            let callback = cancelled.wrap(&url, callback);
+            request(copy, Method::Get, &url, None, dispatched.elapsed(), body, callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal get request task"));
+        handle
+    }
+
+    fn get_with_operation<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, operation: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            request(copy, Method::Get, &url, Some(operation), dispatched.elapsed(), body, callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal get request task"));
+    }
+
+    fn post_with_operation<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, operation: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            request(copy, Method::Post, &url, Some(operation), dispatched.elapsed(), body, callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal post request task"));
+    }
+
+    fn post<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) -> RequestHandle {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let handle = RequestHandle::new();
+        let cancelled = handle.clone();
+        let func = move || {
+            if cancelled.is_cancelled() {
+                //执行前已取消，不发送请求
+                return callback(copy, Err(cancelled_error(&url)));
+            }
+            let callback = cancelled.wrap(&url, callback);
+            request(copy, Method::Post, &url, None, dispatched.elapsed(), body, callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal post request task"));
+        handle
+    }
+
+    fn get_with_query<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, query: Vec<(String, String)>, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            match append_query(&url, &query) {
+                Err(e) => callback(copy, Err(e)),
+                Ok(url) => request(copy, Method::Get, &url, None, dispatched.elapsed(), body, callback),
+            }
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal get request task"));
+    }
+
+    fn post_with_query<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, query: Vec<(String, String)>, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            match append_query(&url, &query) {
+                Err(e) => callback(copy, Err(e)),
+                Ok(url) => request(copy, Method::Post, &url, None, dispatched.elapsed(), body, callback),
+            }
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal post request task"));
+    }
+
+    fn get_with_timeout<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, timeout: Option<Duration>, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let mut copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            if timeout.is_some() {
+                Arc::make_mut(&mut copy).timeout = timeout;
+            }
+            request(copy, Method::Get, &url, None, dispatched.elapsed(), body, callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal get request task"));
+    }
+
+    fn post_with_timeout<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, timeout: Option<Duration>, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let mut copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            if timeout.is_some() {
+                Arc::make_mut(&mut copy).timeout = timeout;
+            }
+            request(copy, Method::Post, &url, None, dispatched.elapsed(), body, callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal post request task"));
+    }
+
+    fn patch<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            request(copy, Method::Patch, &url, None, dispatched.elapsed(), body, callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal patch request task"));
+    }
+
+    fn put<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            request(copy, Method::Put, &url, None, dispatched.elapsed(), body, callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal put request task"));
+    }
+
+    fn delete<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            request(copy, Method::Delete, &url, None, dispatched.elapsed(), body, callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal delete request task"));
+    }
+
+    fn head(client: &SharedHttpClient, url: Atom, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            request(copy, Method::Head, &url, None, dispatched.elapsed(), HttpClientBody::body(""), callback);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal head request task"));
+    }
+
+    fn get_sync<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>) -> Result<HttpClientResponse> {
+        request_sync(client.clone(), Method::Get, &url, body)
+    }
+
+    fn post_sync<T: GenHttpClientBody>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>) -> Result<HttpClientResponse> {
+        request_sync(client.clone(), Method::Post, &url, body)
+    }
+
+    fn get_to_file<T: GenHttpClientBody, P: AsRef<Path>>(client: &SharedHttpClient, url: Atom, body: HttpClientBody<T>, path: P, on_progress: Box<Fn(u64, Option<u64>) + Send>, callback: Box<FnBox(Arc<Self>, Result<u64>)>) {
+        let copy = client.clone();
+        let path = path.as_ref().to_path_buf();
+        let dispatched = Instant::now();
+        let func = move || {
+            request(copy, Method::Get, &url, None, dispatched.elapsed(), body, Box::new(move |client: SharedHttpClient, result: Result<HttpClientResponse>| {
+                let r = result.and_then(|mut resp| {
+                    if resp.status() < 200 || resp.status() > 299 {
+                        return Err(Error::from(HttpcError::Status(resp.status())));
+                    }
+                    resp.save_with_progress(&path, Some(&*on_progress))
+                });
+                callback(client, r);
+            }));
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc normal get to file task"));
+    }
+
+    fn get_snapshot(client: &SharedHttpClient, url: Atom, callback: SnapshotCallback) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            let queue_wait = dispatched.elapsed();
+            let key = match copy.coalesce_key(&Method::Get, &url) {
+                None => {
+                    return request(copy, Method::Get, &url, None, queue_wait, HttpClientBody::body(""), Box::new(move |client: SharedHttpClient, result: Result<HttpClientResponse>| {
+                        callback(client, result.and_then(|resp| resp.into_snapshot()));
+                    }));
+                },
+                Some(key) => key,
+            };
+
+            match copy.in_flight.lock().unwrap().entry(key.clone()) {
+                Entry::Occupied(mut waiters) => {
+                    //已有相同的请求在进行中，等待其结果
+                    waiters.get_mut().push(callback);
+                    return;
+                },
+                Entry::Vacant(waiters) => {
+                    waiters.insert(vec![callback]);
+                },
+            }
+            request(copy, Method::Get, &url, None, queue_wait, HttpClientBody::body(""), Box::new(move |client: SharedHttpClient, result: Result<HttpClientResponse>| {
+                let result = result.and_then(|resp| resp.into_snapshot());
+                //先移除再回调，回调中发起的相同请求会发送新的请求
+                let waiters = client.in_flight.lock().unwrap().remove(&key).unwrap_or_default();
+                for waiter in waiters {
+                    let result = match result {
+                        Ok(ref snapshot) => Ok(snapshot.clone()),
+                        //每个等待的回调都得到相同的请求失败原因
+                        Err(ref e) => Err(match HttpcError::from_io(e) {
+                            Some(reason) => Error::from(reason.clone()),
+                            None => Error::new(e.kind(), e.to_string()),
+                        }),
+                    };
+                    waiter(client.clone(), result);
+                }
+            }));
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc snapshot get request task"));
+    }
+
+    fn pipe(client: &SharedHttpClient, get_url: Atom, put_url: Atom, callback: Box<FnBox(Arc<Self>, Result<HttpClientResponse>)>) {
+        let copy = client.clone();
+        let dispatched = Instant::now();
+        let func = move || {
+            request(copy, Method::Get, &get_url, None, dispatched.elapsed(), HttpClientBody::body(""), Box::new(move |client: SharedHttpClient, result: Result<HttpClientResponse>| {
+                match result {
+                    Err(e) => callback(client, Err(e)),
+                    Ok(ref resp) if !resp.is_ok() => {
+                        callback(client, Err(Error::from(HttpcError::Status(resp.status()))));
+                    },
+                    Ok(resp) => {
+                        request(client.clone(), Method::Put, &put_url, None, Duration::from_millis(0), resp.into_body::<&'static str>(), callback);
+                    },
+                }
+            }));
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc pipe request task"));
+    }
+
+    fn headers_size(&self) -> usize {
+        self.headers.len()
+    }
+
+    fn headers_keys(&self) -> Option<Vec<Atom>> {
+        let len = self.headers_size();
+        if len == 0 {
+            return None;
+        }
+
+        let mut vec = Vec::with_capacity(len);
+        for header in self.headers.iter() {
+            vec.push(Atom::from(header.name()))
+        }
+        Some(vec)
+    }
+
+    fn get_header(&self, key: Atom) -> Option<Vec<Atom>> {
+        self.headers.get_raw(&*key).and_then(|val: &Raw| {
+            let len = val.len();
+            let mut vec = Vec::with_capacity(len);
+            for index in 0..len {
+                vec.push(Atom::from(&val[index]))
+            }
+            Some(vec)
+        })
+    }
+}
+
+/*
+* 响应摘要中记录的响应头
+*/
+const SUMMARY_HEADERS: &[&str] = &["Content-Type", "Content-Encoding", "Connection", "Location", "Server"];
+
+/*
+* http响应摘要，用于访问日志
+*/
+#[derive(Debug, Clone, Serialize)]
+pub struct ResponseSummary {
+    pub url: String,                        //响应url
+    pub status: u16,                        //响应状态
+    pub headers: Vec<(String, String)>,     //选定的响应头条目
+    pub body_len: Option<u64>,              //响应体长度，未知则为None
+    pub elapsed: u64,                       //从发送请求到收到响应头的时长，单位毫秒
+}
+
+/*
+* 请求句柄，用于取消已派发的请求，丢弃句柄不会取消请求
+* 取消后以HttpcError::Cancelled错误回调，未执行的请求不再发送，已发送的请求丢弃响应并关闭连接，但无法中断正在等待的响应
+*/
+#[derive(Debug, Clone)]
+pub struct RequestHandle {
+    cancelled: Arc<AtomicBool>,   //是否已取消
+}
+
+impl RequestHandle {
+    //创建未取消的请求句柄
+    fn new() -> Self {
+        RequestHandle {
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    //取消请求，重复取消没有影响
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    //判断请求是否已取消
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    //包装回调，请求完成时已取消则丢弃结果，以取消错误回调
+    fn wrap(&self, url: &Atom, callback: Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)>) -> Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)> {
+        let handle = self.clone();
+        let url = url.clone();
+        Box::new(move |client: SharedHttpClient, result: Result<HttpClientResponse>| {
+            if handle.is_cancelled() {
+                return callback(client, Err(cancelled_error(&url)));
+            }
+            callback(client, result);
+        })
+    }
+}
+
+/*
+* http响应快照，已读取全部响应，不再持有网络连接
+*/
+#[derive(Debug, Clone)]
+pub struct ResponseSnapshot {
+    pub url: String,                    //响应url
+    pub status: u16,                    //响应状态
+    pub headers: Vec<(Atom, Atom)>,     //响应头条目，一个关键字可以有多个条目
+    pub body: Vec<u8>,                  //响应体，已解码传输编码
+    pub elapsed: Duration,              //从发送请求到收到响应头的时长
+}
+
+impl ResponseSnapshot {
+    //获取指定关键字的响应头条目，关键字不区分大小写
+    pub fn get_header(&self, key: &str) -> Option<Vec<Atom>> {
+        let vec: Vec<Atom> = self.headers.iter().filter(|&&(ref k, _)| k.eq_ignore_ascii_case(key)).map(|&(_, ref v)| v.clone()).collect();
+        if vec.is_empty() {
+            None
+        } else {
+            Some(vec)
+        }
+    }
+
+    //获取文本格式的响应体
+    pub fn text(&self) -> Result<String> {
+        String::from_utf8(self.body.clone()).or_else(|e| {
+            Err(Error::new(ErrorKind::InvalidData, e.description().to_string()))
+        })
+    }
+}
+
+/*
+* http响应
+*/
+pub struct HttpClientResponse {
+    inner: Response,
+    elapsed: Duration,      //从发送请求到收到响应头的时长
+    queue_wait: Duration,   //请求任务从派发到开始执行的排队时长
+    lease: Option<BufferLease>, //缓冲响应体占用的缓冲预算，None表示不限制
+    max_bytes: Option<usize>,   //通过bin和text读取的响应体最大字节数，None表示不限制
+    head: bool,             //是否是head请求的响应，没有响应体
+}
+
+impl HttpClientResponse{
+    //获取响应url，会加入全局Atom表，大量不同url时应使用url_str
+    pub fn url(&self) -> Atom {
+        Atom::from(self.inner.url().as_str())
+    }
+
+    //获取响应url，不加入全局Atom表
+    pub fn url_str(&self) -> String {
+        self.inner.url().as_str().to_string()
+    }
+
+    //判断响应是否通过tls接收，根据重定向后的最终url判断，通过代理访问http url时与代理之间的tls不计算在内
+    pub fn is_tls(&self) -> bool {
+        self.inner.url().scheme().eq_ignore_ascii_case("https")
+    }
+
+    //判断是否是信息响应，只有底层客户端将1xx响应作为最终响应返回时才为true
+    //不支持观察最终响应之前的1xx响应，如100 Continue和103 Early Hints，底层客户端读取最终响应时会丢弃这些响应
+    pub fn is_info(&self) -> bool {
+        self.inner.status().is_informational()
+    }
+
+    //判断是否成功
+    pub fn is_ok(&self) -> bool {
+        self.inner.status().is_success()
+    }
+
+    //判断是否是重定向
+    pub fn is_redirect(self) -> bool {
+        self.inner.status().is_redirection()
+    }
+
+    //判断是否是客户端错误
+    pub fn is_client_error(self) -> bool {
+        self.inner.status().is_client_error()
+    }
+
+    //判断是否是服务器端错误
+    pub fn is_server_error(self) -> bool {
+        self.inner.status().is_server_error()
+    }
+
+    //判断是否是未知状态
+    pub fn is_undefined(self) -> bool {
+        self.inner.status().is_strange_status()
+    }
+
+    //获取响应状态
+    pub fn status(&self) -> u16 {
+        self.inner.status().as_u16()
+    }
+
+    //获取响应状态描述
+    pub fn status_info(&self) -> Option<Atom> {
+        self.inner.status().canonical_reason().and_then(|reason| {
+            Some(Atom::from(reason))
+        })
     }
 
     //获取响应头条目数量
@@ -396,82 +2468,1243 @@ impl HttpClientResponse{
         self.inner.headers().len()
     }
 
-    //获取响应头所有条目关键字
-    pub fn headers_keys(&self) -> Option<Vec<Atom>> {
-        let len = self.headers_size();
-        if len == 0 {
-            return None;
+    //获取响应头所有条目关键字
+    pub fn headers_keys(&self) -> Option<Vec<Atom>> {
+        let len = self.headers_size();
+        if len == 0 {
+            return None;
+        }
+
+        let mut vec = Vec::with_capacity(len);
+        for header in self.inner.headers().iter() {
+            vec.push(Atom::from(header.name()))
+        }
+        Some(vec)
+    }
+
+    //获取指定关键字的响应头条目，一个关键字可以有多个条目，按接收顺序返回
+    //解析时同名条目按接收顺序追加到同一个Raw中，不会合并或重排，即使与其它响应头交错
+    pub fn get_header(&self, key: Atom) -> Option<Vec<Atom>> {
+        self.inner.headers().get_raw(&*key).and_then(|val: &Raw| {
+            let len = val.len();
+            let mut vec = Vec::with_capacity(len);
+            for index in 0..len {
+                vec.push(Atom::from(&val[index]))
+            }
+            Some(vec)
+        })
+    }
+
+    //获取响应头的字节数，按解析后的条目估算，每个条目包括关键字、值、分隔符和换行
+    pub fn headers_bytes(&self) -> usize {
+        let mut size = 0;
+        for header in self.inner.headers().iter() {
+            if let Some(val) = self.inner.headers().get_raw(header.name()) {
+                for index in 0..val.len() {
+                    size += header.name().len() + val[index].len() + 4;
+                }
+            }
+        }
+        size
+    }
+
+    //获取所有响应头条目，一个关键字可以有多个条目
+    pub fn headers(&self) -> Vec<(Atom, Atom)> {
+        let mut vec = Vec::with_capacity(self.headers_size());
+        for header in self.inner.headers().iter() {
+            let key = Atom::from(header.name());
+            if let Some(val) = self.inner.headers().get_raw(header.name()) {
+                for index in 0..val.len() {
+                    vec.push((key.clone(), Atom::from(&val[index])));
+                }
+            }
+        }
+        vec
+    }
+
+    //获取所有响应头条目的原始字节，保留接收时关键字的大小写，不加入全局Atom表，值不做任何转换，适合转发响应头
+    //条目按关键字第一次出现的顺序排列，同名条目按接收顺序排在一起
+    pub fn headers_raw(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut vec = Vec::with_capacity(self.headers_size());
+        for header in self.inner.headers().iter() {
+            if let Some(val) = self.inner.headers().get_raw(header.name()) {
+                for index in 0..val.len() {
+                    vec.push((header.name().as_bytes().to_vec(), val[index].to_vec()));
+                }
+            }
+        }
+        vec
+    }
+
+    //获取W3C跟踪上下文，返回traceparent和tracestate，traceparent不存在或格式无效则返回None
+    pub fn trace_context(&self) -> Option<(Atom, Option<Atom>)> {
+        let parent = match self.inner.headers().get_raw(TRACE_PARENT) {
+            Some(val) if val.len() > 0 => String::from_utf8_lossy(&val[0]).trim().to_string(),
+            _ => return None,
+        };
+        if !is_valid_traceparent(&parent) {
+            return None;
+        }
+
+        let state = self.inner.headers().get_raw(TRACE_STATE).and_then(|val: &Raw| {
+            //多个tracestate条目按规范以逗号合并
+            let mut vec = Vec::with_capacity(val.len());
+            for index in 0..val.len() {
+                vec.push(String::from_utf8_lossy(&val[index]).trim().to_string());
+            }
+            Some(Atom::from(vec.join(",")))
+        });
+        Some((Atom::from(parent), state))
+    }
+
+    //判断服务器是否要求关闭当前连接
+    pub fn is_close(&self) -> bool {
+        match self.inner.headers().get::<Connection>() {
+            Some(conn) => conn.iter().any(|opt| *opt == ConnectionOption::Close),
+            None => false,
+        }
+    }
+
+    //获取响应的MIME类型，小写且不包括参数，没有Content-Type则返回None
+    pub fn content_type(&self) -> Option<Atom> {
+        self.get_header(Atom::from("Content-Type")).and_then(|vec| vec.into_iter().next()).and_then(|val| {
+            let mime = media_type(val.as_str());
+            if mime.is_empty() {
+                None
+            } else {
+                Some(Atom::from(mime))
+            }
+        })
+    }
+
+    //按Content-Type查找注册的解码器解码响应体，没有Content-Type时按application/octet-stream查找
+    pub fn decode_with(&mut self, registry: &BodyDecoderRegistry) -> Result<Box<Any>> {
+        let content_type = self.get_header(Atom::from("Content-Type"))
+            .and_then(|vec| vec.into_iter().next())
+            .map(|val| (*val).clone())
+            .unwrap_or("application/octet-stream".to_string());
+        let decoder = match registry.get(&content_type) {
+            None => return Err(Error::new(ErrorKind::InvalidData, format!("Body decoder not found, content type: {}", content_type))),
+            Some(decoder) => decoder,
+        };
+        decoder(&content_type, self.bin()?)
+    }
+
+    //获取响应在缓存中已存在的时长，Age不存在或格式无效则返回None，有多个条目时使用第一个
+    pub fn age(&self) -> Option<Duration> {
+        match self.inner.headers().get_raw("Age") {
+            Some(val) if val.len() > 0 => {
+                String::from_utf8_lossy(&val[0]).trim().parse::<u64>().ok().map(Duration::from_secs)
+            },
+            _ => None,
+        }
+    }
+
+    //获取服务器的Date头表示的时间，支持RFC 1123及兼容的旧格式，没有Date头或格式无效则返回None
+    pub fn server_date(&self) -> Option<SystemTime> {
+        self.inner.headers().get::<Date>().map(|date| SystemTime::from(date.0))
+    }
+
+    //获取服务器时钟相对本地时钟的偏差，单位毫秒，服务器时钟较快为正，Date头只精确到秒，没有Date头则返回None
+    pub fn clock_skew(&self) -> Option<i64> {
+        self.server_date().map(|date| clock_skew(date, SystemTime::now()))
+    }
+
+    //获取所有警告，多个Warning条目和单个条目中以逗号分隔的多个警告会被拆分
+    pub fn warnings(&self) -> Vec<String> {
+        let mut vec = Vec::new();
+        if let Some(val) = self.inner.headers().get_raw("Warning") {
+            for index in 0..val.len() {
+                split_warnings(&String::from_utf8_lossy(&val[index]), &mut vec);
+            }
+        }
+        vec
+    }
+
+    //获取Link头中的所有链接，返回关系类型到url的映射，关系类型为小写，相对url按响应url解析
+    //多个Link条目和单个条目中以逗号分隔的多个链接会被合并，同一关系类型只保留第一个链接
+    pub fn links(&self) -> HashMap<String, Atom> {
+        let mut map = HashMap::new();
+        if let Some(val) = self.inner.headers().get_raw("Link") {
+            for index in 0..val.len() {
+                parse_links(self.inner.url(), &String::from_utf8_lossy(&val[index]), &mut map);
+            }
+        }
+        map
+    }
+
+    //获取响应的Set-Cookie中设置的所有cookie，返回名称和值，忽略属性，按出现顺序排列
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        let mut vec = Vec::new();
+        if let Some(val) = self.inner.headers().get_raw("Set-Cookie") {
+            for index in 0..val.len() {
+                let cookie = String::from_utf8_lossy(&val[index]).into_owned();
+                let pair = cookie.split(';').next().unwrap_or("");
+                if let Some(pos) = pair.find('=') {
+                    let name = pair[..pos].trim();
+                    if !name.is_empty() {
+                        vec.push((name.to_string(), pair[pos + 1..].trim().to_string()));
+                    }
+                }
+            }
+        }
+        vec
+    }
+
+    //获取从发送请求到收到响应头的时长
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    //获取请求任务从派发到开始执行的排队时长，不经过任务派发的请求为0，排队时长较长说明工作线程池已饱和
+    pub fn queue_wait(&self) -> Duration {
+        self.queue_wait
+    }
+
+    //获取响应摘要，不会读取响应体
+    pub fn summary(&self) -> ResponseSummary {
+        let mut headers = Vec::new();
+        for key in SUMMARY_HEADERS {
+            if let Some(val) = self.inner.headers().get_raw(key) {
+                for index in 0..val.len() {
+                    headers.push((key.to_string(), String::from_utf8_lossy(&val[index]).into_owned()));
+                }
+            }
         }
 
-        let mut vec = Vec::with_capacity(len);
-        for header in self.inner.headers().iter() {
-            vec.push(Atom::from(header.name()))
+        ResponseSummary {
+            url: self.url_str(),
+            status: self.status(),
+            headers: headers,
+            body_len: self.inner.headers().get::<ContentLength>().map(|len| **len),
+            elapsed: self.elapsed.as_secs() * 1000 + (self.elapsed.subsec_nanos() / 1000000) as u64,
         }
-        Some(vec)
     }
 
-    //获取指定关键字的响应头条目，一个关键字可以有多个条目
-    pub fn get_header(&self, key: Atom) -> Option<Vec<Atom>> {
-        self.inner.headers().get_raw(&*key).and_then(|val: &Raw| {
-            let len = val.len();
-            let mut vec = Vec::with_capacity(len);
-            for index in 0..len {
-                vec.push(Atom::from(&val[index]))
+    //获取响应体的传输编码，不包括chunked和identity，按编码的应用顺序排列
+    pub fn transfer_encodings(&self) -> Vec<Atom> {
+        let mut vec = Vec::new();
+        if let Some(val) = self.inner.headers().get_raw("Transfer-Encoding") {
+            for index in 0..val.len() {
+                for encoding in String::from_utf8_lossy(&val[index]).split(',') {
+                    let encoding = encoding.trim().to_lowercase();
+                    if encoding.is_empty() || encoding == "chunked" || encoding == "identity" {
+                        continue;
+                    }
+                    vec.push(Atom::from(encoding));
+                }
             }
-            Some(vec)
-        })
+        }
+        vec
     }
 
-    //获取文本格式的响应体
+    //获取文本格式的响应体，head请求的响应返回空
     pub fn text(&mut self) -> Result<String> {
+        if self.head {
+            return Ok(String::new());
+        }
+        if self.lease.is_some() || self.max_bytes.is_some() || !self.transfer_encodings().is_empty() {
+            //有缓冲预算、响应体限制或传输编码，读取后按utf8处理
+            return self.bin().and_then(|bin| {
+                String::from_utf8(bin).or_else(|e| {
+                    Err(Error::from(HttpcError::Decode(e.description().to_string())))
+                })
+            });
+        }
+
         self.inner.text().or_else(|e| {
-            Err(Error::new(ErrorKind::Other, e.description().to_string()))
+            Err(Error::from(classify_error(&e, e.description().to_string())))
         }).and_then(|text| {
             Ok(text)
         })
     }
 
-    //获取二进制的响应体
+    //获取文本格式的响应体，读取中途失败时返回PartialBody错误，包括失败前已读取的数据，用于区分没有收到数据和在第N字节中断
+    //完整读取后按utf8处理
+    pub fn text_partial(&mut self) -> Result<String> {
+        if self.head {
+            return Ok(String::new());
+        }
+
+        let url = self.url_str();
+        let status = self.status();
+        let encodings = self.transfer_encodings();
+        let mut vec = Vec::new();
+        let max_bytes = self.max_bytes;
+        let r = {
+            let lease = &mut self.lease;
+            decode_transfer(&mut self.inner, &encodings).and_then(|mut reader| {
+                let mut buf = vec![0; BUFFER_READ_SIZE];
+                loop {
+                    let n = match reader.read(&mut buf) {
+                        Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e),
+                        Ok(n) => n,
+                    };
+                    if n == 0 {
+                        return Ok(());
+                    }
+                    if let Some(limit) = max_bytes {
+                        if vec.len() + n > limit {
+                            return Err(body_too_large(&url, (vec.len() + n) as u64, limit));
+                        }
+                    }
+                    if let Some(ref mut lease) = *lease {
+                        lease.acquire(&url, n)?;
+                    }
+                    vec.extend_from_slice(&buf[..n]);
+                }
+            })
+        };
+
+        match r {
+            Err(e) => Err(Error::new(e.kind(), PartialBody {
+                url: url,
+                status: status,
+                data: vec,
+                reason: e.to_string(),
+            })),
+            Ok(_) => String::from_utf8(vec).or_else(|e| {
+                Err(Error::from(HttpcError::Decode(e.description().to_string())))
+            }),
+        }
+    }
+
+    //将响应体反序列化为D，Content-Type不是json或响应体不是有效的json时返回InvalidData错误
+    //通过bin读取响应体，支持传输编码和缓冲预算
+    pub fn json<D: DeserializeOwned>(&mut self) -> Result<D> {
+        let url = self.url_str();
+        match self.content_type() {
+            Some(ref mime) if mime.as_str() == "application/json" || mime.ends_with("+json") => (),
+            mime => {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Httpc response not json, url: {}, content type: {}",
+                    url, mime.map(|mime| (*mime).clone()).unwrap_or_default())));
+            },
+        }
+
+        let bin = self.bin()?;
+        serde_json::from_slice(&bin).or_else(|e| {
+            Err(Error::new(ErrorKind::InvalidData, format!("Httpc response invalid json, url: {}, reason: {}", url, e)))
+        })
+    }
+
+    //以json lines格式流式读取响应体，每行反序列化为一个D，单行解析失败会以Err回调并继续读取，读取失败则回调后结束
+    pub fn json_lines<D: DeserializeOwned>(&mut self, mut on_item: Box<FnMut(Result<D>)>) {
+        let encodings = self.transfer_encodings();
+        let reader = match decode_transfer(&mut self.inner, &encodings) {
+            Err(e) => return on_item(Err(e)),
+            Ok(reader) => BufReader::new(reader),
+        };
+        for line in reader.lines() {
+            match line {
+                Err(e) => {
+                    on_item(Err(e));
+                    return;
+                },
+                Ok(line) => {
+                    let line = line.trim();
+                    if line.is_empty() {
+                        //忽略空行
+                        continue;
+                    }
+
+                    on_item(serde_json::from_str(line).or_else(|e| {
+                        Err(Error::new(ErrorKind::InvalidData, e.description().to_string()))
+                    }));
+                },
+            }
+        }
+    }
+
+    //流式读取multipart响应体，每个部分的头和内容读取器依次回调，不缓冲整个响应体，回调返回错误则停止读取
+    //回调未读完的部分内容会被丢弃
+    pub fn for_each_part(&mut self, mut on_part: Box<FnMut(Headers, &mut Read) -> Result<()>>) -> Result<()> {
+        let boundary = match self.get_header(Atom::from("Content-Type")).and_then(|vec| vec.into_iter().next()).and_then(|val| boundary(val.as_str())) {
+            None => return Err(Error::new(ErrorKind::InvalidData, format!("Httpc response not multipart, url: {}", self.url_str()))),
+            Some(boundary) => boundary,
+        };
+        let encodings = self.transfer_encodings();
+        let mut reader = MultipartReader::new(decode_transfer(&mut self.inner, &encodings)?, &boundary);
+        while let Some(headers) = reader.next_part()? {
+            on_part(headers, &mut reader)?;
+        }
+        Ok(())
+    }
+
+    //转换为流式的请求体，保留响应的MIME类型和长度，有传输编码时先解码，解码后长度未知，不支持的传输编码则保留原始数据
+    pub fn into_body<T: GenHttpClientBody>(self) -> HttpClientBody<T> {
+        let mime = self.get_header(Atom::from("Content-Type")).and_then(|vec| vec.into_iter().next());
+        let encodings = self.transfer_encodings();
+        if !encodings.is_empty() && is_supported_transfer(&encodings) {
+            //已确认支持所有传输编码，解码不会失败
+            return HttpClientBody::Stream(decode_transfer(self.inner, &encodings).unwrap(), mime, None);
+        }
+
+        let len = self.inner.headers().get::<ContentLength>().map(|len| **len);
+        HttpClientBody::Stream(Box::new(self.inner), mime, len)
+    }
+
+    //转换为流式的响应体读取器，按需从连接读取，内存占用与响应体长度无关，有传输编码时先解码，head请求的响应读取为空
+    //不占用缓冲预算
+    pub fn into_reader(self) -> Result<Box<Read + Send>> {
+        if self.head {
+            return Ok(Box::new(Cursor::new(Vec::new())));
+        }
+
+        let encodings = self.transfer_encodings();
+        decode_transfer(self.inner, &encodings)
+    }
+
+    //将响应体流式写入文件，创建或截断文件，成功后同步到磁盘，返回写入的字节数，有传输编码时写入解码后的响应体
+    //head请求的响应写入空文件，不占用缓冲预算
+    pub fn save_to<P: AsRef<Path>>(&mut self, path: P) -> Result<u64> {
+        self.save_with_progress(path.as_ref(), None)
+    }
+
+    //同save_to，每次写入后回调已写入的字节数和Content-Length，没有Content-Length或有传输编码时总字节数为None
+    fn save_with_progress(&mut self, path: &Path, on_progress: Option<&Fn(u64, Option<u64>)>) -> Result<u64> {
+        let mut file = File::create(path)?;
+        let mut len = 0;
+        if !self.head {
+            let encodings = self.transfer_encodings();
+            let total = if encodings.is_empty() {
+                self.inner.headers().get::<ContentLength>().map(|size| **size)
+            } else {
+                None
+            };
+            let mut reader = decode_transfer(&mut self.inner, &encodings)?;
+            let mut buf = vec![0; BUFFER_READ_SIZE];
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                file.write_all(&buf[..n])?;
+                len += n as u64;
+                if let Some(on_progress) = on_progress {
+                    on_progress(len, total);
+                }
+            }
+        }
+        file.sync_all()?;
+        Ok(len)
+    }
+
+    //读取全部响应，转换为快照
+    pub fn into_snapshot(mut self) -> Result<ResponseSnapshot> {
+        let headers = self.headers();
+        let body = self.bin()?;
+
+        Ok(ResponseSnapshot {
+            url: self.url_str(),
+            status: self.status(),
+            headers: headers,
+            body: body,
+            elapsed: self.elapsed,
+        })
+    }
+
+    //获取文本格式的响应体，响应体为空则返回EmptyBody错误
+    pub fn text_required(&mut self) -> Result<String> {
+        let text = self.text()?;
+        if text.is_empty() {
+            return Err(self.empty_body());
+        }
+        Ok(text)
+    }
+
+    //获取二进制的响应体，响应体为空则返回EmptyBody错误
+    pub fn bin_required(&mut self) -> Result<Vec<u8>> {
+        let bin = self.bin()?;
+        if bin.is_empty() {
+            return Err(self.empty_body());
+        }
+        Ok(bin)
+    }
+
+    //构建响应体为空的错误
+    fn empty_body(&self) -> Error {
+        Error::new(ErrorKind::UnexpectedEof, EmptyBody {
+            url: self.url_str(),
+            status: self.status(),
+        })
+    }
+
+    //获取二进制的响应体，head请求的响应返回空，设置了缓冲预算时，超过预算则返回错误
     pub fn bin(&mut self) -> Result<Vec<u8>> {
+        if self.head {
+            //Content-Length和Transfer-Encoding描述的是get请求的响应体，不读取
+            return Ok(Vec::new());
+        }
+        let encodings = self.transfer_encodings();
+        if self.lease.is_some() || self.max_bytes.is_some() {
+            return self.bin_limited(&encodings);
+        }
+        if !encodings.is_empty() {
+            //有传输编码，解码后读取
+            let mut vec = Vec::new();
+            decode_transfer(&mut self.inner, &encodings)?.read_to_end(&mut vec)?;
+            return Ok(vec);
+        }
+
         let mut vec = Vec::new();
         self.inner.copy_to(&mut vec).or_else(|e| {
-            Err(Error::new(ErrorKind::Other, e.description().to_string()))
+            Err(Error::from(classify_error(&e, e.description().to_string())))
         }).and(Ok(vec))
     }
+
+    //在缓冲预算和响应体限制内读取响应体，已知长度时先检查限制并占用全部长度，否则按读取的字节数逐次检查和占用
+    fn bin_limited(&mut self, encodings: &[Atom]) -> Result<Vec<u8>> {
+        let url = self.url_str();
+        let len = if encodings.is_empty() {
+            self.inner.headers().get::<ContentLength>().map(|len| **len as usize)
+        } else {
+            None
+        };
+        let max_bytes = self.max_bytes;
+        if let (Some(limit), Some(len)) = (max_bytes, len) {
+            if len > limit {
+                //不读取响应体，直接拒绝
+                return Err(body_too_large(&url, len as u64, limit));
+            }
+        }
+        let mut lease = self.lease.as_mut();
+        if let (Some(lease), Some(len)) = (lease.as_mut(), len) {
+            lease.acquire(&url, len)?;
+        }
+
+        let mut reader = decode_transfer(&mut self.inner, encodings)?;
+        let mut acquired = len.unwrap_or(0);
+        let mut vec = Vec::with_capacity(acquired);
+        let mut buf = vec![0; BUFFER_READ_SIZE];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            if let Some(limit) = max_bytes {
+                if vec.len() + n > limit {
+                    //长度未知或与声明的长度不符
+                    return Err(body_too_large(&url, (vec.len() + n) as u64, limit));
+                }
+            }
+            if vec.len() + n > acquired {
+                //长度未知或超过声明的长度，占用超出的部分
+                if let Some(lease) = lease.as_mut() {
+                    lease.acquire(&url, vec.len() + n - acquired)?;
+                }
+                acquired = vec.len() + n;
+            }
+            vec.extend_from_slice(&buf[..n]);
+        }
+        Ok(vec)
+    }
+}
+
+//在当前线程中同步发送http请求，request一般在当前线程中完成并回调，超过主机的并发请求数时等待许可释放后由任务完成
+fn request_sync<T: GenHttpClientBody>(client: SharedHttpClient, method: Method, url: &Atom, body: HttpClientBody<T>) -> Result<HttpClientResponse> {
+    let (sender, receiver) = channel();
+    request(client, method, url, None, Duration::from_millis(0), body, Box::new(move |_client: SharedHttpClient, result: Result<HttpClientResponse>| {
+        let _ = sender.send(result);
+    }));
+    receiver.recv().unwrap_or_else(|_| Err(Error::new(ErrorKind::Other, format!("Httpc request not completed, url: {}", **url))))
 }
 
-//发送http请求
+//发送http请求，检查url、访问规则和tls版本后，在主机的并发请求限制内发送
 fn request<T: GenHttpClientBody>(client: SharedHttpClient, 
-                                request: &mut RequestBuilder, 
+                                method: Method, 
+                                url: &Atom, 
+                                operation: Option<Atom>, 
+                                queue_wait: Duration, 
                                 body: HttpClientBody<T>, 
                                 callback: Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)>) {
-    match 
-        match body {
-            HttpClientBody::Body(body) => {
-                //发送普通请求
-                request.headers(client.headers.clone())
-                    .body(body)
-                    .send()
+    let callback = match client.ext.metrics.clone() {
+        None => callback,
+        Some(sink) => {
+            //请求完成时统计
+            let start = Instant::now();
+            let method = Atom::from(method.as_ref());
+            let url = (**url).clone();
+            let operation = operation.clone();
+            Box::new(move |client: SharedHttpClient, result: Result<HttpClientResponse>| {
+                sink(&RequestMetrics {
+                    operation: operation,
+                    method: method,
+                    url: url,
+                    status: result.as_ref().ok().map(|resp| resp.status()),
+                    elapsed: start.elapsed(),
+                    queue_wait: queue_wait,
+                });
+                callback(client, result);
+            }) as Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)>
+        },
+    };
+    //最后一次尝试中已调用before的拦截器数量
+    let entered = Arc::new(AtomicUsize::new(0));
+    let callback = if client.ext.interceptors.is_empty() {
+        callback
+    } else {
+        //在统计前按相反顺序调用拦截器，统计的是拦截器处理后的结果
+        let entered = entered.clone();
+        Box::new(move |client: SharedHttpClient, result: Result<HttpClientResponse>| {
+            let count = entered.load(Ordering::SeqCst);
+            let result = client.ext.interceptors[..count].iter().rev().fold(result, |result, interceptor| interceptor.after(result));
+            callback(client, result);
+        }) as Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)>
+    };
+    if let Err(e) = validate_url(url.as_str()) {
+        //url无效，不发送请求
+        return callback(client, Err(e));
+    }
+    if let Err(e) = client.ext.check_blocked(url) {
+        return callback(client, Err(e));
+    }
+    if client.timeout == Some(Duration::from_millis(0)) {
+        //超时时长为0，已经超时
+        return callback(client, Err(Error::from(HttpcError::Timeout(format!("Httpc request timeout, url: {}, timeout: 0ms", **url)))));
+    }
+    //超过主机的并发请求数时排队等待，不占用工作线程，许可在回调前释放，回调中可以继续请求同一主机
+    let limiter = client.limiter.clone();
+    let queued = Instant::now();
+    let target = url.clone();
+    HostLimiter::acquire(&limiter, url.as_str(), Box::new(move |permit: Result<Option<HostPermit>>| {
+        let callback = match permit {
+            Err(e) => return callback(client, Err(e)),
+            Ok(None) => callback,
+            Ok(Some(permit)) => Box::new(move |client: SharedHttpClient, result: Result<HttpClientResponse>| {
+                drop(permit);
+                callback(client, result);
+            }) as Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)>,
+        };
+        let callback = match client.ext.on_connection.clone() {
+            None => callback,
+            Some(hook) => {
+                //得到请求结果后回调一次连接状态
+                let url = target.clone();
+                Box::new(move |client: SharedHttpClient, result: Result<HttpClientResponse>| {
+                    hook(url, ConnectionState::Unknown);
+                    callback(client, result);
+                }) as Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)>
             },
-            HttpClientBody::Json(json) => {
-                //发送json请求
-                request.headers(client.headers.clone())
-                    .json(&json)
-                    .send()
+        };
+        send_request(client, method, &target, operation, queue_wait + queued.elapsed(), body, callback, entered, 0);
+    }));
+}
+
+//发送已通过检查的http请求，发生传输错误时，在重试次数和重试预算内使用复制的body重试
+fn send_request<T: GenHttpClientBody>(client: SharedHttpClient,
+                                     method: Method,
+                                     url: &Atom,
+                                     operation: Option<Atom>,
+                                     queue_wait: Duration,
+                                     body: HttpClientBody<T>,
+                                     callback: Box<FnBox(SharedHttpClient, Result<HttpClientResponse>)>,
+                                     entered: Arc<AtomicUsize>,
+                                     retry: usize) {
+    let op = operation.as_ref().map(|operation| (**operation).clone()).unwrap_or(String::new());
+    let mut body = body;
+    let mut retry = retry;
+    loop {
+        let start = Instant::now();
+        if client.deadline.map_or(false, |deadline| start >= deadline) {
+            return callback(client, Err(deadline_exceeded(url, retry)));
+        }
+        let payload_len = if client.ext.is_validate() { body.payload().map(|payload| payload.len()) } else { None };
+        let copy = if retry < client.ext.retries && (client.ext.retry_all_methods || is_idempotent(&method)) { body.try_clone() } else { None };
+        let inner = match client.inner() {
+            Err(e) => return callback(client, Err(e)),
+            Ok(inner) => inner,
+        };
+        let builder = inner.request(method.clone(), (**url).as_str());
+        let mut req = build(&client, builder, body);
+        entered.store(0, Ordering::SeqCst);
+        if let Ok(ref mut req) = req {
+            let intercepted = {
+                let mut intercepted = None;
+                for (index, interceptor) in client.ext.interceptors.iter().enumerate() {
+                    entered.store(index + 1, Ordering::SeqCst);
+                    intercepted = interceptor.before(req);
+                    if intercepted.is_some() {
+                        break;
+                    }
+                }
+                intercepted
+            };
+            if let Some(result) = intercepted {
+                //拦截器直接完成请求，不发送
+                return callback(client, result);
+            }
+            if client.ext.is_validate() {
+                if let Err(e) = validate_request(req, payload_len) {
+                    //请求无效，重试也不会成功
+                    return callback(client, Err(e));
+                }
+            }
+        }
+        match req.and_then(|req| inner.execute(req)) {
+            Err(e) => {
+                let reason = e.description().to_string();
+                if let Some(copy) = copy {
+                    let exhausted = match client.budget {
+                        Some(ref budget) => !budget.withdraw(),
+                        None => false,
+                    };
+                    if exhausted {
+                        //重试预算不足，立即失败
+                        return callback(client.clone(), Err(Error::from(classify_error(&e, format!("Httpc retry budget exhausted, url: {}, reason: {}", **url, reason)))));
+                    }
+
+                    println!("!!!> Httpc Request Error, operation: {}, url: {}, retry: {}, reason: {:?}", op, **url, retry, reason);
+                    if client.ext.retry_backoff > Duration::from_millis(0) {
+                        let wait = retry_backoff(client.ext.retry_backoff, retry);
+                        if client.deadline.map_or(false, |deadline| Instant::now() + wait >= deadline) {
+                            //退避后已超过截止时间，不再等待
+                            return callback(client.clone(), Err(deadline_exceeded(url, retry + 1)));
+                        }
+                        //退避期间不占用工作线程，到期后通过任务系统重试
+                        let delays = client.delays.clone();
+                        let name = client.task_name("httpc retry request task");
+                        let url = url.clone();
+                        return DelayQueue::delay(&delays, wait, name, Box::new(move || {
+                            send_request(client, method, &url, operation, queue_wait, copy, callback, entered, retry + 1);
+                        }));
+                    }
+                    body = copy;
+                    retry += 1;
+                    continue;
+                }
+
+                //错误中包括已尝试的次数
+                let attempts = retry + 1;
+                let err = if client.is_proxied() && is_proxy_auth_error(&e) {
+                    //https请求建立隧道时代理认证失败
+                    Error::new(ErrorKind::PermissionDenied, ProxyAuthRequired {
+                        url: (**url).clone(),
+                    })
+                } else {
+                    Error::from(classify_error(&e, format!("{}, attempts: {}", reason, attempts)))
+                };
+                return callback(client, Err(err));
             },
-            HttpClientBody::Form(form) => {
-                //发送表单请求
-                request.headers(client.headers.clone())
-                    .multipart(form)
-                    .send()
+            Ok(inner) => {
+                if let Some(ref budget) = client.budget {
+                    budget.deposit();
+                }
+                let resp = HttpClientResponse {
+                    inner: inner,
+                    elapsed: start.elapsed(),
+                    queue_wait: queue_wait,
+                    lease: client.buffers.clone().map(|budget| BufferLease {
+                        budget: budget,
+                        size: 0,
+                    }),
+                    max_bytes: client.ext.max_response_bytes,
+                    head: method == Method::Head,
+                };
+                if let Some(limit) = client.ext.max_response_header_bytes {
+                    let size = resp.headers_bytes();
+                    if size > limit {
+                        //不读取响应体，直接丢弃响应
+                        return callback(client, Err(Error::new(ErrorKind::InvalidData, HeadersTooLarge {
+                            url: resp.url_str(),
+                            size: size,
+                            limit: limit,
+                        })));
+                    }
+                }
+                if client.ext.redirect_hook.is_some() && resp.inner.status().is_redirection() {
+                    if let Some(reason) = client.take_refusal(&resp) {
+                        //重定向策略拒绝重定向，不作为请求结果返回
+                        return callback(client, Err(Error::from(HttpcError::Redirect(format!("Httpc redirect refused, url: {}, reason: {}", resp.url_str(), reason)))));
+                    }
+                }
+                if resp.status() == 407 && client.is_proxied() {
+                    //代理认证失败，不作为目标服务器的响应返回
+                    return callback(client, Err(Error::new(ErrorKind::PermissionDenied, ProxyAuthRequired {
+                        url: resp.url_str(),
+                    })));
+                }
+                return callback(client, Ok(resp));
             },
         }
-    {
-        Err(e) => callback(client, Err(Error::new(ErrorKind::Other, e.description().to_string()))),
-        Ok(inner) => {
-            callback(client, Ok(HttpClientResponse {
-                inner: inner,
-            }));
+    }
+}
+
+//根据body类型构建http请求，构建器只在这里使用，保证请求头和请求体都被设置
+fn build<T: GenHttpClientBody>(client: &SharedHttpClient, mut builder: RequestBuilder, body: HttpClientBody<T>) -> reqwest::Result<Request> {
+    let payload = if client.ext.signer.is_some() { body.payload() } else { None };
+    builder.headers(client.headers.clone());
+    match body {
+        HttpClientBody::Body(body) => {
+            //普通请求
+            builder.body(body);
+        },
+        HttpClientBody::Json(json) => {
+            //json请求
+            builder.json(&json);
+        },
+        HttpClientBody::Form(form) => {
+            //表单请求
+            builder.multipart(form);
+        },
+        HttpClientBody::File(file, mime, len) => {
+            //文件请求
+            builder.headers(body_headers(Some(mime.as_str()), Some(len)));
+            builder.body(Body::sized(file, len));
+        },
+        HttpClientBody::Stream(reader, mime, len) => {
+            //流请求
+            builder.headers(body_headers(mime.as_ref().map(|mime| mime.as_str()), len));
+            builder.body(match len {
+                Some(len) => Body::sized(reader, len),
+                None => Body::new(reader),
+            });
+        },
+        HttpClientBody::SizedForm(form) => {
+            //可计算长度的表单请求，避免分块编码
+            let len = form.content_length();
+            builder.headers(body_headers(Some(&form.content_type()), Some(len)));
+            builder.body(Body::sized(form.into_reader(), len));
+        },
+        HttpClientBody::MergePatch(patch) => {
+            //json合并补丁请求
+            builder.headers(body_headers(Some("application/merge-patch+json"), None));
+            builder.body(serde_json::to_vec(&patch).unwrap_or_default());
         },
+        HttpClientBody::JsonPatch(patch) => {
+            //json补丁请求
+            builder.headers(body_headers(Some("application/json-patch+json"), None));
+            builder.body(serde_json::to_vec(&patch).unwrap_or_default());
+        },
+        HttpClientBody::JsonBody(json) => {
+            //任意值的json请求
+            builder.json(&json);
+        },
+        HttpClientBody::UrlEncoded(map) => {
+            //urlencoded表单请求，设置Content-Type并百分号编码键值
+            builder.form(&map);
+        },
+    }
+
+    let mut req = builder.build()?;
+    if let Some(ref signer) = client.ext.signer {
+        //签名只能修改请求头
+        let mut parts = RequestParts {
+            method: Atom::from(req.method().as_ref().to_uppercase()),
+            url: req.url().as_str().to_string(),
+            headers: req.headers().clone(),
+            body: payload,
+        };
+        signer(&mut parts);
+        *req.headers_mut() = parts.headers;
+    }
+    if let Some(auth) = client.proxy_authorization(req.url()) {
+        //代理认证不参与签名
+        req.headers_mut().set(auth);
+    }
+    Ok(req)
+}
+
+//获取请求体相关的请求头
+fn body_headers(mime: Option<&str>, len: Option<u64>) -> Headers {
+    let mut headers = Headers::new();
+    if let Some(mime) = mime {
+        headers.set_raw("Content-Type", mime.to_string());
+    }
+    if let Some(len) = len {
+        headers.set(ContentLength(len));
+    }
+    headers
+}
+
+//判断是否是http标记字符，方法和头的关键字只能由标记字符组成
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+//校验将要发送的请求，payload_len为内存中的请求体长度，无法获取则为None
+fn validate_request(req: &Request, payload_len: Option<usize>) -> Result<()> {
+    let invalid = |reason: String| -> Result<()> {
+        Err(Error::new(ErrorKind::InvalidInput, format!("Httpc invalid request, url: {}, reason: {}", req.url().as_str(), reason)))
+    };
+
+    let method = req.method().as_ref();
+    if method.is_empty() || !method.chars().all(is_token_char) {
+        return invalid(format!("invalid method, {:?}", method));
+    }
+    let scheme = req.url().scheme();
+    if scheme != "http" && scheme != "https" {
+        return invalid(format!("unsupported scheme, {}", scheme));
+    }
+    if req.url().host_str().map(|host| host.is_empty()).unwrap_or(true) {
+        return invalid("no host".to_string());
+    }
+
+    for header in req.headers().iter() {
+        let name = header.name();
+        if name.is_empty() || !name.chars().all(is_token_char) {
+            return invalid(format!("invalid header name, {:?}", name));
+        }
+        if let Some(val) = req.headers().get_raw(name) {
+            for index in 0..val.len() {
+                if val[index].iter().any(|b| *b == b'\r' || *b == b'\n' || *b == 0) {
+                    return invalid(format!("invalid header value, {}", name));
+                }
+            }
+        }
+    }
+
+    if let Some(val) = req.headers().get_raw("Content-Length") {
+        if req.headers().get_raw("Transfer-Encoding").is_some() {
+            return invalid("both Content-Length and Transfer-Encoding".to_string());
+        }
+        let mut len = None;
+        for index in 0..val.len() {
+            let value = String::from_utf8_lossy(&val[index]).trim().to_string();
+            match value.parse::<u64>() {
+                Err(_) => return invalid(format!("invalid Content-Length, {}", value)),
+                Ok(value) if len.is_some() && len != Some(value) => return invalid("conflicting Content-Length".to_string()),
+                Ok(value) => len = Some(value),
+            }
+        }
+        if let (Some(len), Some(payload_len)) = (len, payload_len) {
+            if len != payload_len as u64 {
+                return invalid(format!("Content-Length mismatch, header: {}, body: {}", len, payload_len));
+            }
+        }
+    }
+    Ok(())
+}
+
+//判断traceparent格式是否有效，格式为version-trace_id-parent_id-flags，均为小写十六进制
+fn is_valid_traceparent(parent: &str) -> bool {
+    let parts: Vec<&str> = parent.split('-').collect();
+    if parts.len() < 4 {
+        return false;
+    }
+
+    let is_hex = |s: &str, len: usize| s.len() == len && s.chars().all(|c| c.is_digit(16) && !c.is_uppercase());
+    let is_zero = |s: &str| s.chars().all(|c| c == '0');
+    if !is_hex(parts[0], 2) || parts[0] == "ff" {
+        return false;
+    }
+    if parts[0] == "00" && parts.len() != 4 {
+        //当前版本只允许4个字段
+        return false;
+    }
+    is_hex(parts[1], 32) && !is_zero(parts[1]) && is_hex(parts[2], 16) && !is_zero(parts[2]) && is_hex(parts[3], 2)
+}
+
+//拆分Warning条目中的多个警告，忽略引号中的逗号
+fn split_warnings(val: &str, vec: &mut Vec<String>) {
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, c) in val.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                let warning = val[start..index].trim();
+                if !warning.is_empty() {
+                    vec.push(warning.to_string());
+                }
+                start = index + 1;
+            },
+            _ => (),
+        }
+    }
+    let warning = val[start..].trim();
+    if !warning.is_empty() {
+        vec.push(warning.to_string());
+    }
+}
+
+//校验请求url，只支持有主机的http和https url，错误中包括无效的原因
+pub fn validate_url(url: &str) -> Result<()> {
+    let invalid = |reason: String| -> Result<()> {
+        Err(Error::new(ErrorKind::InvalidInput, format!("Httpc invalid url, url: {}, reason: {}", url, reason)))
+    };
+
+    let parsed = match Url::parse(url) {
+        Err(e) => {
+            if url.contains("://") {
+                return invalid(format!("{}", e));
+            }
+            return invalid(format!("missing scheme, {}", e));
+        },
+        Ok(parsed) => parsed,
+    };
+    let scheme = parsed.scheme();
+    if scheme != "http" && scheme != "https" {
+        return invalid(format!("unsupported scheme, {}", scheme));
+    }
+    if parsed.host_str().map(|host| host.is_empty()).unwrap_or(true) {
+        return invalid("no host".to_string());
+    }
+    Ok(())
+}
+
+//将查询参数按顺序编码后追加到url已有的查询参数之后，没有查询参数则返回原url
+fn append_query(url: &Atom, query: &[(String, String)]) -> Result<Atom> {
+    if query.is_empty() {
+        return Ok(url.clone());
+    }
+
+    let mut parsed = match Url::parse(url.as_str()) {
+        Err(e) => return Err(Error::new(ErrorKind::InvalidInput, format!("Httpc invalid url, url: {}, reason: {}", **url, e))),
+        Ok(parsed) => parsed,
+    };
+    parsed.query_pairs_mut().extend_pairs(query.iter());
+    Ok(Atom::from(parsed.as_str()))
+}
+
+//计算服务器时间相对本地时间的偏差，单位毫秒，服务器时间较晚为正
+pub fn clock_skew(server: SystemTime, local: SystemTime) -> i64 {
+    match server.duration_since(local) {
+        Ok(dur) => (dur.as_secs() * 1000 + (dur.subsec_nanos() / 1000000) as u64) as i64,
+        Err(e) => {
+            let dur = e.duration();
+            -((dur.as_secs() * 1000 + (dur.subsec_nanos() / 1000000) as u64) as i64)
+        },
+    }
+}
+
+//按分隔符拆分，忽略引号和尖括号中的分隔符
+fn split_unquoted(val: &str, sep: char) -> Vec<&str> {
+    let mut vec = Vec::new();
+    let mut quoted = false;
+    let mut bracketed = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, c) in val.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if quoted => escaped = true,
+            '"' if !bracketed => quoted = !quoted,
+            '<' if !quoted => bracketed = true,
+            '>' if !quoted => bracketed = false,
+            c if c == sep && !quoted && !bracketed => {
+                vec.push(val[start..index].trim());
+                start = index + 1;
+            },
+            _ => (),
+        }
+    }
+    vec.push(val[start..].trim());
+    vec
+}
+
+//去掉参数值的引号并处理转义，没有引号则原样返回
+fn unquote(val: &str) -> String {
+    let val = val.trim();
+    if val.len() < 2 || !val.starts_with('"') || !val.ends_with('"') {
+        return val.to_string();
+    }
+
+    let mut s = String::with_capacity(val.len());
+    let mut escaped = false;
+    for c in val[1..val.len() - 1].chars() {
+        if !escaped && c == '\\' {
+            escaped = true;
+            continue;
+        }
+        escaped = false;
+        s.push(c);
+    }
+    s
+}
+
+//解析Link条目中的链接，格式为<url>; rel="next"; ...，rel可以包含以空格分隔的多个关系类型，没有rel的链接被忽略
+fn parse_links(base: &Url, val: &str, map: &mut HashMap<String, Atom>) {
+    for link in split_unquoted(val, ',') {
+        if !link.starts_with('<') {
+            continue;
+        }
+        let end = match link.find('>') {
+            None => continue,
+            Some(end) => end,
+        };
+        let url = match base.join(link[1..end].trim()) {
+            Err(_) => continue,
+            Ok(url) => Atom::from(url.as_str()),
+        };
+
+        //只使用第一个rel参数
+        let rel = split_unquoted(&link[end + 1..], ';').into_iter().filter_map(|param| {
+            let mut kv = param.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case("rel") => Some(unquote(value)),
+                _ => None,
+            }
+        }).next();
+        if let Some(rel) = rel {
+            for r in rel.split_whitespace() {
+                map.entry(r.to_lowercase()).or_insert_with(|| url.clone());
+            }
+        }
+    }
+}
+
+//判断地址是否在CIDR内，地址族不同则不匹配
+fn is_in_cidr(addr: &IpAddr, net: &IpAddr, prefix: u8) -> bool {
+    match (*addr, *net) {
+        (IpAddr::V4(addr), IpAddr::V4(net)) if prefix <= 32 => {
+            let mask = if prefix == 0 { 0 } else { !0u32 << (32 - prefix) };
+            (u32::from(addr) & mask) == (u32::from(net) & mask)
+        },
+        (IpAddr::V6(addr), IpAddr::V6(net)) if prefix <= 128 => {
+            let (addr, net) = (addr.octets(), net.octets());
+            let (bytes, bits) = ((prefix / 8) as usize, prefix % 8);
+            if addr[..bytes] != net[..bytes] {
+                return false;
+            }
+            bits == 0 || (addr[bytes] >> (8 - bits)) == (net[bytes] >> (8 - bits))
+        },
+        _ => false,
+    }
+}
+
+//判断是否是私有、回环、链路本地或未指定地址，ipv4映射的ipv6地址按ipv4判断
+fn is_private_addr(addr: &IpAddr) -> bool {
+    match *addr {
+        IpAddr::V4(addr) => addr.is_private() || addr.is_loopback() || addr.is_link_local() || addr.is_unspecified(),
+        IpAddr::V6(addr) => {
+            if let Some(v4) = addr.to_ipv4() {
+                if addr.segments()[5] == 0xffff {
+                    return is_private_addr(&IpAddr::V4(v4));
+                }
+            }
+            let first = addr.segments()[0];
+            addr.is_loopback() || addr.is_unspecified()
+                || (first & 0xfe00) == 0xfc00     //唯一本地地址 fc00::/7
+                || (first & 0xffc0) == 0xfe80     //链路本地地址 fe80::/10
+        },
+    }
+}
+
+//判断是否支持所有传输编码
+fn is_supported_transfer(encodings: &[Atom]) -> bool {
+    encodings.iter().all(|encoding| {
+        match encoding.as_str() {
+            "gzip" | "x-gzip" | "deflate" => true,
+            _ => false,
+        }
+    })
+}
+
+//按传输编码的应用顺序逆序解码
+fn decode_transfer<'a, R: Read + Send + 'a>(reader: R, encodings: &[Atom]) -> Result<Box<Read + Send + 'a>> {
+    let mut reader: Box<Read + Send + 'a> = Box::new(reader);
+    for encoding in encodings.iter().rev() {
+        reader = match encoding.as_str() {
+            "gzip" | "x-gzip" => Box::new(GzDecoder::new(reader)),
+            "deflate" => Box::new(ZlibDecoder::new(reader)),
+            _ => return Err(Error::from(HttpcError::Decode(format!("Httpc transfer encoding not support, encoding: {}", **encoding)))),
+        };
+    }
+    Ok(reader)
+}
+
+//按application/x-www-form-urlencoded编码表单，与发送时的编码一致
+fn encode_urlencoded(map: &HashMap<String, String>) -> String {
+    let mut url = Url::parse("http://localhost/").unwrap();
+    url.query_pairs_mut().extend_pairs(map.iter());
+    url.query().unwrap_or("").to_string()
+}
+
+//构建超过请求截止时间的错误，包括已尝试的次数
+fn deadline_exceeded(url: &Atom, attempts: usize) -> Error {
+    Error::from(HttpcError::Timeout(format!("Httpc request deadline exceeded, url: {}, attempts: {}", **url, attempts)))
+}
+
+//构建请求已取消的错误
+fn cancelled_error(url: &Atom) -> Error {
+    Error::from(HttpcError::Cancelled(format!("Httpc request cancelled, url: {}", **url)))
+}
+
+//构建响应体超过限制的错误
+fn body_too_large(url: &str, size: u64, limit: usize) -> Error {
+    Error::new(ErrorKind::InvalidData, BodyTooLarge {
+        url: url.to_string(),
+        size: size,
+        limit: limit,
+    })
+}
+
+//判断是否是幂等的请求方法，幂等的请求才可以安全的重试
+fn is_idempotent(method: &Method) -> bool {
+    match *method {
+        Method::Get | Method::Head | Method::Put | Method::Delete | Method::Options | Method::Trace => true,
+        _ => false,
     }
-}
\ No newline at end of file
+}
+
+//获取第retry次重试前的退避时长，按基础退避时长指数增长，并在后一半范围内随机抖动，避免多个客户端同时重试
+fn retry_backoff(base: Duration, retry: usize) -> Duration {
+    let base = base.as_secs() * 1000 + (base.subsec_nanos() / 1000000) as u64;
+    let max = base.saturating_mul(1 << retry.min(16)).min(MAX_RETRY_BACKOFF);
+    let seed = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|now| now.subsec_nanos() as u64).unwrap_or(0);
+    Duration::from_millis(max - seed % (max / 2 + 1))
+}
+
+//按reqwest错误的结构获取请求失败原因，不根据错误描述判断，reason为失败原因的描述
+fn classify_error(e: &reqwest::Error, reason: String) -> HttpcError {
+    if e.is_redirect() {
+        //重定向次数超过限制或重定向循环
+        return HttpcError::Redirect(reason);
+    }
+    let inner = match e.get_ref() {
+        None => return HttpcError::Request(reason),
+        Some(inner) => inner,
+    };
+    if inner.is::<native_tls::Error>() {
+        return HttpcError::Tls(reason);
+    }
+    match inner.downcast_ref::<hyper::Error>() {
+        Some(&hyper::Error::Timeout) => HttpcError::Timeout(reason),
+        Some(&hyper::Error::Io(ref io)) => classify_io_error(io, reason),
+        Some(_) => HttpcError::Request(reason),
+        None => match inner.downcast_ref::<Error>() {
+            Some(io) => classify_io_error(io, reason),
+            None => HttpcError::Request(reason),
+        },
+    }
+}
+
+//按io错误的类型获取请求失败原因，tls握手失败时io错误中是tls错误
+fn classify_io_error(e: &Error, reason: String) -> HttpcError {
+    if e.get_ref().map_or(false, |inner| inner.is::<native_tls::Error>()) {
+        return HttpcError::Tls(reason);
+    }
+    match e.kind() {
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => HttpcError::Timeout(reason),
+        ErrorKind::ConnectionRefused |
+        ErrorKind::ConnectionReset |
+        ErrorKind::ConnectionAborted |
+        ErrorKind::NotConnected |
+        ErrorKind::AddrNotAvailable |
+        ErrorKind::BrokenPipe => HttpcError::Connect(reason),
+        ErrorKind::InvalidData => HttpcError::Decode(reason),
+        _ => HttpcError::Request(reason),
+    }
+}
+
+//判断是否是https请求建立隧道时代理认证失败的错误，隧道失败时底层返回固定描述的io错误
+fn is_proxy_auth_error(e: &reqwest::Error) -> bool {
+    let io = match e.get_ref().and_then(|inner| inner.downcast_ref::<hyper::Error>()) {
+        Some(&hyper::Error::Io(ref io)) => Some(io),
+        _ => e.get_ref().and_then(|inner| inner.downcast_ref::<Error>()),
+    };
+    match io.and_then(|io| io.get_ref()) {
+        Some(inner) => inner.to_string() == "proxy authentication required",
+        None => false,
+    }
+}
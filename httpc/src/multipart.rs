@@ -0,0 +1,206 @@
+use std::io::{Read, Error, ErrorKind, Result};
+
+use reqwest::header::Headers;
+
+/*
+* 每次从底层读取的字节数
+*/
+const READ_SIZE: usize = 8192;
+
+/*
+* 单个部分的头的最大字节数
+*/
+const MAX_PART_HEADER_SIZE: usize = 64 * 1024;
+
+/*
+* 读取状态
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Body,       //正在读取部分的内容，开始时的前言也按内容读取并丢弃
+    Delimited,  //已读取分隔符，下一步判断是否是结束分隔符
+    Finished,   //已读取结束分隔符
+}
+
+/*
+* 流式的multipart读取器，按需从底层读取，不缓冲整个响应体
+* 通过next_part获取下一个部分的头，之后通过Read读取该部分的内容，未读完的内容在获取下一个部分时丢弃
+*/
+pub struct MultipartReader<R: Read> {
+    inner: R,           //底层读取器
+    delimiter: Vec<u8>, //分隔符，包括前导的CRLF
+    buf: Vec<u8>,       //已从底层读取但还没有消费的字节
+    pos: usize,         //缓冲区中已消费的位置
+    eof: bool,          //底层是否已读完
+    state: State,       //读取状态
+}
+
+impl<R: Read> MultipartReader<R> {
+    //创建multipart读取器
+    pub fn new(inner: R, boundary: &str) -> Self {
+        let mut delimiter = b"\r\n--".to_vec();
+        delimiter.extend_from_slice(boundary.as_bytes());
+        MultipartReader {
+            inner: inner,
+            delimiter: delimiter,
+            //第一个分隔符前可能没有CRLF，预先补充
+            buf: b"\r\n".to_vec(),
+            pos: 0,
+            eof: false,
+            state: State::Body,
+        }
+    }
+
+    //读取下一个部分的头，没有更多部分则返回None
+    pub fn next_part(&mut self) -> Result<Option<Headers>> {
+        if self.state == State::Body {
+            //丢弃当前部分未读取的内容
+            let mut sink = [0u8; READ_SIZE];
+            while self.read(&mut sink)? > 0 {}
+        }
+        if self.state == State::Finished {
+            return Ok(None);
+        }
+
+        //分隔符后为"--"则是结束分隔符
+        while self.buf.len() - self.pos < 2 && !self.eof {
+            self.fill()?;
+        }
+        if self.buf[self.pos..].starts_with(b"--") {
+            self.state = State::Finished;
+            return Ok(None);
+        }
+        //忽略分隔符所在行的剩余部分
+        self.read_line()?;
+
+        let mut headers = Headers::new();
+        let mut size = 0;
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                break;
+            }
+            size += line.len();
+            if size > MAX_PART_HEADER_SIZE {
+                return Err(Error::new(ErrorKind::InvalidData, "Multipart part header too large"));
+            }
+
+            let line = String::from_utf8_lossy(&line).into_owned();
+            match line.find(':') {
+                None => return Err(Error::new(ErrorKind::InvalidData, format!("Multipart invalid part header: {}", line))),
+                Some(index) => {
+                    let value = line[index + 1..].trim().as_bytes().to_vec();
+                    headers.append_raw(line[..index].trim().to_string(), value);
+                },
+            }
+        }
+        self.state = State::Body;
+        Ok(Some(headers))
+    }
+
+    //读取一行，不包括CRLF
+    fn read_line(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(index) = find(&self.buf[self.pos..], b"\r\n") {
+                let line = self.buf[self.pos..self.pos + index].to_vec();
+                self.pos += index + 2;
+                return Ok(line);
+            }
+            if self.eof {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "Multipart unexpected end of part header"));
+            }
+            if self.buf.len() - self.pos > MAX_PART_HEADER_SIZE {
+                return Err(Error::new(ErrorKind::InvalidData, "Multipart part header too large"));
+            }
+            self.fill()?;
+        }
+    }
+
+    //从底层读取更多字节，会丢弃已消费的字节
+    fn fill(&mut self) -> Result<()> {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+
+        let len = self.buf.len();
+        self.buf.resize(len + READ_SIZE, 0);
+        match self.inner.read(&mut self.buf[len..]) {
+            Err(e) => {
+                self.buf.truncate(len);
+                Err(e)
+            },
+            Ok(n) => {
+                self.buf.truncate(len + n);
+                if n == 0 {
+                    self.eof = true;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl<R: Read> Read for MultipartReader<R> {
+    //读取当前部分的内容，读到分隔符时返回0
+    fn read(&mut self, out: &mut [u8]) -> Result<usize> {
+        if self.state != State::Body || out.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let avail = self.buf.len() - self.pos;
+            let len = match find(&self.buf[self.pos..], &self.delimiter) {
+                Some(0) => {
+                    //当前部分结束
+                    self.pos += self.delimiter.len();
+                    self.state = State::Delimited;
+                    return Ok(0);
+                },
+                Some(index) => index,
+                //分隔符可能跨越两次读取，保留可能是分隔符开始的字节
+                None if avail >= self.delimiter.len() => avail - (self.delimiter.len() - 1),
+                None if self.eof => {
+                    return Err(Error::new(ErrorKind::UnexpectedEof, "Multipart delimiter not found"));
+                },
+                None => {
+                    self.fill()?;
+                    continue;
+                },
+            };
+
+            let len = if len > out.len() { out.len() } else { len };
+            out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+            self.pos += len;
+            return Ok(len);
+        }
+    }
+}
+
+//获取Content-Type中的multipart分隔符，不是multipart类型或没有分隔符则返回None
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    if !params.next().unwrap_or("").trim().to_lowercase().starts_with("multipart/") {
+        return None;
+    }
+    for param in params {
+        let param = param.trim();
+        if let Some(index) = param.find('=') {
+            if param[..index].trim().eq_ignore_ascii_case("boundary") {
+                let value = param[index + 1..].trim().trim_matches('"');
+                if !value.is_empty() {
+                    return Some(value.to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+//查找子串的位置
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    if pattern.len() > data.len() {
+        return None;
+    }
+    data.windows(pattern.len()).position(|window| window == pattern)
+}
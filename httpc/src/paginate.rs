@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use std::boxed::FnBox;
+use std::time::Duration;
+use std::collections::HashSet;
+use std::sync::mpsc::channel;
+use std::io::{Error, ErrorKind, Result};
+
+use reqwest::Method;
+
+use pi_lib::atom::Atom;
+use pi_base::task::TaskType;
+use pi_base::pi_base_impl::cast_ext_task;
+
+use super::{SharedHttpClient, HttpClientBody, HttpClientResponse, HttpcError, request};
+
+/*
+* 默认的最大页数
+*/
+const DEFAULT_MAX_PAGES: usize = 1000;
+
+/*
+* 分页回调，参数为页的序号和响应体，返回false则停止获取后续的页
+*/
+pub type PageCallback = Arc<Fn(usize, Vec<u8>) -> bool + Send + Sync>;
+
+/*
+* 分页获取选项
+*/
+#[derive(Clone)]
+pub struct FetchAllOpts {
+    pub max_pages: usize,               //最多获取的页数，超过时返回错误，防止无限翻页
+    pub on_page: Option<PageCallback>,  //分页回调，None表示累积所有页的响应体
+}
+
+impl Default for FetchAllOpts {
+    fn default() -> Self {
+        FetchAllOpts {
+            max_pages: DEFAULT_MAX_PAGES,
+            on_page: None,
+        }
+    }
+}
+
+impl FetchAllOpts {
+    //设置最多获取的页数
+    pub fn max_pages(mut self, max: usize) -> Self {
+        self.max_pages = max;
+        self
+    }
+
+    //设置分页回调
+    pub fn on_page(mut self, on_page: PageCallback) -> Self {
+        self.on_page = Some(on_page);
+        self
+    }
+}
+
+//异步获取所有页，从起始url开始按Link头中rel="next"的链接依次获取，直到没有下一页，回调所有页的响应体
+//设置了分页回调时每页的响应体只传递给分页回调，不会累积，回调的列表为空
+pub fn fetch_all(client: &SharedHttpClient, start_url: Atom, opts: FetchAllOpts, callback: Box<FnBox(SharedHttpClient, Result<Vec<Vec<u8>>>)>) {
+    let copy = client.clone();
+    let func = move || {
+        let r = fetch_pages(&copy, start_url, &opts);
+        callback(copy, r);
+    };
+    cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc fetch all task"));
+}
+
+//同步获取所有页
+fn fetch_pages(client: &SharedHttpClient, start_url: Atom, opts: &FetchAllOpts) -> Result<Vec<Vec<u8>>> {
+    let mut pages = Vec::new();
+    let mut visited = HashSet::new();
+    let mut next = Some(start_url);
+    let mut index = 0;
+    while let Some(url) = next {
+        if index >= opts.max_pages {
+            return Err(Error::new(ErrorKind::Other, format!("Httpc fetch all too many pages, url: {}, max: {}", *url, opts.max_pages)));
+        }
+        if !visited.insert(url.clone()) {
+            //下一页指向已获取的页，继续获取会无限循环
+            return Err(Error::new(ErrorKind::InvalidData, format!("Httpc fetch all page loop, url: {}", *url)));
+        }
+
+        let mut resp = get(client, &url)?;
+        if !resp.is_ok() {
+            return Err(Error::from(HttpcError::Status(resp.status())));
+        }
+        next = resp.links().remove("next");
+        let body = resp.bin()?;
+        match opts.on_page {
+            None => pages.push(body),
+            Some(ref on_page) => {
+                if !on_page(index, body) {
+                    break;
+                }
+            },
+        }
+        index += 1;
+    }
+    Ok(pages)
+}
+
+//同步获取一页
+fn get(client: &SharedHttpClient, url: &Atom) -> Result<HttpClientResponse> {
+    //请求在当前线程中完成并回调
+    let (sender, receiver) = channel();
+    request(client.clone(), Method::Get, url, None, Duration::from_millis(0), HttpClientBody::body(""), Box::new(move |_client: SharedHttpClient, result: Result<HttpClientResponse>| {
+        let _ = sender.send(result);
+    }));
+    receiver.recv().unwrap_or_else(|_| Err(Error::new(ErrorKind::Other, format!("Httpc fetch all request not completed, url: {}", **url))))
+}
@@ -0,0 +1,60 @@
+use std::boxed::FnBox;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/*
+* 竞争操作，多个进行中的操作只有第一个完成的结果会被回调，之后执行所有注册的取消回调，取消已完成的操作应该没有影响
+* 例如rpc请求可以通过RPCClient::cancel取消，http请求一旦发出则无法中断，只会忽略其结果
+*/
+pub struct Race<T> {
+    done: AtomicBool,                               //是否已完成
+    callback: Mutex<Option<Box<FnBox(T) + Send>>>,  //第一个完成的结果的回调
+    cancels: Mutex<Vec<Box<FnBox() + Send>>>,       //取消回调
+}
+
+impl<T> Race<T> {
+    //创建竞争操作
+    pub fn new(callback: Box<FnBox(T) + Send>) -> Arc<Self> {
+        Arc::new(Race {
+            done: AtomicBool::new(false),
+            callback: Mutex::new(Some(callback)),
+            cancels: Mutex::new(Vec::new()),
+        })
+    }
+
+    //判断是否已完成
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::SeqCst)
+    }
+
+    //注册取消回调，已完成则立即取消
+    pub fn on_cancel(&self, cancel: Box<FnBox() + Send>) {
+        {
+            let mut cancels = self.cancels.lock().unwrap();
+            if !self.is_done() {
+                cancels.push(cancel);
+                return;
+            }
+        }
+        cancel();
+    }
+
+    //完成操作，只有第一个完成的结果会被回调，返回结果是否被接受
+    pub fn complete(&self, result: T) -> bool {
+        let cancels = {
+            let mut cancels = self.cancels.lock().unwrap();
+            if self.done.swap(true, Ordering::SeqCst) {
+                return false;
+            }
+            cancels.drain(..).collect::<Vec<_>>()
+        };
+
+        if let Some(callback) = self.callback.lock().unwrap().take() {
+            callback(result);
+        }
+        for cancel in cancels {
+            cancel();
+        }
+        true
+    }
+}
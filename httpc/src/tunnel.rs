@@ -0,0 +1,112 @@
+use std::boxed::FnBox;
+use std::time::Duration;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::io::{Read, Write, Error, ErrorKind, Result};
+
+use reqwest::Url;
+
+use pi_lib::atom::Atom;
+use pi_base::task::TaskType;
+use pi_base::pi_base_impl::cast_ext_task;
+
+use super::{SharedHttpClient, ProxyAuthRequired, HttpcError, validate_url};
+
+/*
+* CONNECT响应头的最大字节数
+*/
+const MAX_TUNNEL_HEADER_SIZE: usize = 64 * 1024;
+
+//异步通过CONNECT向代理服务器建立到目标主机和端口的隧道，成功则回调已建立隧道的双向连接
+//代理服务器只支持http，请求头使用客户端的请求头，例如Proxy-Authorization，超时时长使用客户端选项的超时时长
+pub fn connect_tunnel(client: &SharedHttpClient, proxy: Atom, host_port: Atom, callback: Box<FnBox(SharedHttpClient, Result<TcpStream>)>) {
+    let copy = client.clone();
+    let func = move || {
+        let r = tunnel(&copy, &proxy, &host_port);
+        callback(copy, r);
+    };
+    cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc connect tunnel task"));
+}
+
+//同步建立隧道
+fn tunnel(client: &SharedHttpClient, proxy: &Atom, host_port: &Atom) -> Result<TcpStream> {
+    validate_url(proxy.as_str())?;
+    client.ext.check_blocked(proxy)?;
+    let url = Url::parse(proxy.as_str()).unwrap();
+    if url.scheme() != "http" {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Httpc tunnel proxy not support, proxy: {}, scheme: {}", **proxy, url.scheme())));
+    }
+    //目标必须是主机:端口
+    let valid = match host_port.rfind(':') {
+        Some(index) if index > 0 => host_port[index + 1..].parse::<u16>().map(|port| port > 0).unwrap_or(false),
+        _ => false,
+    };
+    if !valid {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Httpc tunnel invalid target, target: {}", **host_port)));
+    }
+
+    let timeout = client.options.timeout();
+    let addr = format!("{}:{}", url.host_str().unwrap(), url.port_or_known_default().unwrap_or(80));
+    let mut stream = connect(&addr, timeout)?;
+    stream.set_read_timeout(timeout)?;
+    stream.set_write_timeout(timeout)?;
+
+    let mut req = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", **host_port, **host_port);
+    for header in client.headers.iter() {
+        req.push_str(&format!("{}: {}\r\n", header.name(), header.value_string()));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes())?;
+
+    let status = read_status(&mut stream)?;
+    if status == 407 {
+        return Err(Error::new(ErrorKind::PermissionDenied, ProxyAuthRequired {
+            url: (**host_port).clone(),
+        }));
+    }
+    if status < 200 || status > 299 {
+        return Err(Error::from(HttpcError::Status(status)));
+    }
+    //隧道已建立，之后的读写不再超时
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    Ok(stream)
+}
+
+//连接代理服务器，依次尝试解析的所有地址
+fn connect(addr: &str, timeout: Option<Duration>) -> Result<TcpStream> {
+    let mut last = None;
+    for addr in addr.to_socket_addrs()? {
+        let r = match timeout {
+            None => TcpStream::connect(addr),
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+        };
+        match r {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = Some(e),
+        }
+    }
+    Err(last.unwrap_or_else(|| Error::new(ErrorKind::NotFound, format!("Httpc tunnel proxy not resolved, addr: {}", addr))))
+}
+
+//读取CONNECT的响应头并返回状态码，逐字节读取，不会读取隧道中的数据
+fn read_status(stream: &mut TcpStream) -> Result<u16> {
+    let mut head = Vec::new();
+    let mut byte = [0u8; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte)? == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Httpc tunnel unexpected end of response header"));
+        }
+        head.push(byte[0]);
+        if head.len() > MAX_TUNNEL_HEADER_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "Httpc tunnel response header too large"));
+        }
+    }
+
+    let head = String::from_utf8_lossy(&head).into_owned();
+    let line = head.lines().next().unwrap_or("");
+    let mut parts = line.split_whitespace();
+    match (parts.next(), parts.next().map(|status| status.parse::<u16>())) {
+        (Some(version), Some(Ok(status))) if version.starts_with("HTTP/") => Ok(status),
+        _ => Err(Error::new(ErrorKind::InvalidData, format!("Httpc tunnel invalid status line: {}", line))),
+    }
+}
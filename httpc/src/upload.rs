@@ -0,0 +1,232 @@
+use std::fs::File;
+use std::sync::Arc;
+use std::boxed::FnBox;
+use std::time::Duration;
+use std::sync::mpsc::channel;
+use std::path::{Path, PathBuf};
+use std::io::{Read, Seek, SeekFrom, Error, ErrorKind, Result};
+
+use reqwest::{Url, Method};
+
+use pi_lib::atom::Atom;
+use pi_base::task::TaskType;
+use pi_base::pi_base_impl::cast_ext_task;
+
+use super::{SharedHttpClient, HttpClientBody, HttpClientResponse, HttpcError, request};
+
+/*
+* 可续传上传协议的版本
+*/
+const TUS_VERSION: &str = "1.0.0";
+
+/*
+* 默认的分块大小，单位字节
+*/
+const DEFAULT_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/*
+* 上传进度回调，参数为上传url、服务器已确认的偏移和文件总长度，保存上传url即可在进程重启后续传
+*/
+pub type UploadProgress = Arc<Fn(Atom, u64, u64) + Send + Sync>;
+
+/*
+* 可续传上传选项
+*/
+#[derive(Clone)]
+pub struct UploadOpts {
+    pub upload_url: Option<Atom>,       //已创建的上传url，用于续传，None或服务器已不存在该上传时重新创建
+    pub chunk_size: usize,              //每次PATCH的最大字节数
+    pub retries: usize,                 //分块上传失败时的最大重试次数，重试前向服务器查询已确认的偏移
+    pub progress: Option<UploadProgress>,   //上传进度回调，None表示不回调
+}
+
+impl Default for UploadOpts {
+    fn default() -> Self {
+        UploadOpts {
+            upload_url: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            retries: 3,
+            progress: None,
+        }
+    }
+}
+
+impl UploadOpts {
+    //设置已创建的上传url
+    pub fn upload_url(mut self, url: Atom) -> Self {
+        self.upload_url = Some(url);
+        self
+    }
+
+    //设置每次PATCH的最大字节数
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size;
+        self
+    }
+
+    //设置分块上传失败时的最大重试次数
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    //设置上传进度回调
+    pub fn progress(mut self, progress: UploadProgress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+}
+
+//异步可续传上传文件，使用tus协议，没有可续传的上传时通过POST向url创建上传，之后按分块PATCH上传，回调上传url
+//续传时通过HEAD查询服务器已确认的偏移，从该偏移继续上传
+pub fn resumable_upload<P: AsRef<Path>>(client: &SharedHttpClient, url: Atom, file: P, opts: UploadOpts, callback: Box<FnBox(SharedHttpClient, Result<Atom>)>) {
+    let copy = client.clone();
+    let file = file.as_ref().to_path_buf();
+    let func = move || {
+        let r = upload(&copy, &url, &file, &opts);
+        callback(copy, r);
+    };
+    cast_ext_task(TaskType::Sync, 10000000, Box::new(func), client.task_name("httpc resumable upload task"));
+}
+
+//同步上传，返回上传url
+fn upload(client: &SharedHttpClient, url: &Atom, path: &PathBuf, opts: &UploadOpts) -> Result<Atom> {
+    if opts.chunk_size == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "Httpc upload invalid chunk size, size: 0"));
+    }
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let resumed = match opts.upload_url {
+        None => None,
+        Some(ref upload_url) => query_offset(client, upload_url, len)?.map(|offset| (upload_url.clone(), offset)),
+    };
+    let (upload_url, mut offset) = match resumed {
+        Some(resumed) => resumed,
+        None => (create(client, url, len)?, 0),
+    };
+
+    let mut buf = vec![0; opts.chunk_size];
+    let mut retry = 0;
+    loop {
+        if let Some(ref progress) = opts.progress {
+            progress(upload_url.clone(), offset, len);
+        }
+        if offset >= len {
+            return Ok(upload_url);
+        }
+
+        file.seek(SeekFrom::Start(offset))?;
+        let size = read_chunk(&mut file, &mut buf)?;
+        if size == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, format!("Httpc upload file truncated, file: {:?}, offset: {}, len: {}", path, offset, len)));
+        }
+        let headers = vec![
+            ("Upload-Offset", offset.to_string()),
+            ("Content-Type", "application/offset+octet-stream".to_string()),
+        ];
+        let r = send(client, Method::Patch, &upload_url, headers, buf[..size].to_vec()).and_then(|resp| {
+            match resp.status() {
+                200 | 204 => upload_offset(&resp),
+                status => Err(Error::from(HttpcError::Status(status))),
+            }
+        });
+        match r {
+            Ok(next) if next > offset && next <= len => {
+                offset = next;
+                retry = 0;
+            },
+            Ok(next) => {
+                return Err(Error::new(ErrorKind::InvalidData, format!("Httpc upload invalid offset, url: {}, offset: {}, next: {}", *upload_url, offset, next)));
+            },
+            Err(ref e) if retry < opts.retries => {
+                println!("!!!> Httpc Upload Chunk Error, url: {}, offset: {}, retry: {}, reason: {:?}", *upload_url, offset, retry, e);
+                retry += 1;
+                //以服务器已确认的偏移为准继续上传
+                match query_offset(client, &upload_url, len)? {
+                    None => return Err(Error::new(ErrorKind::NotFound, format!("Httpc upload not found, url: {}", *upload_url))),
+                    Some(next) => offset = next,
+                }
+            },
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+//创建上传，返回上传url
+fn create(client: &SharedHttpClient, url: &Atom, len: u64) -> Result<Atom> {
+    let resp = send(client, Method::Post, url, vec![("Upload-Length", len.to_string())], Vec::new())?;
+    if resp.status() != 201 {
+        return Err(Error::from(HttpcError::Status(resp.status())));
+    }
+
+    let location = match resp.get_header(Atom::from("Location")).and_then(|vec| vec.into_iter().next()) {
+        None => return Err(Error::new(ErrorKind::InvalidData, format!("Httpc upload create failed, url: {}, reason: no Location", **url))),
+        Some(location) => location,
+    };
+    //Location可以是相对url
+    match Url::parse(url.as_str()).and_then(|base| base.join(location.as_str())) {
+        Err(e) => Err(Error::new(ErrorKind::InvalidData, format!("Httpc upload invalid Location, url: {}, location: {}, reason: {}", **url, *location, e))),
+        Ok(upload_url) => Ok(Atom::from(upload_url.as_str())),
+    }
+}
+
+//查询服务器已确认的偏移，上传不存在则返回None
+fn query_offset(client: &SharedHttpClient, upload_url: &Atom, len: u64) -> Result<Option<u64>> {
+    let resp = send(client, Method::Head, upload_url, Vec::new(), Vec::new())?;
+    match resp.status() {
+        404 | 410 => Ok(None),
+        200 | 204 => {
+            if let Some(vec) = resp.get_header(Atom::from("Upload-Length")) {
+                if vec[0].as_str().trim() != len.to_string() {
+                    //上传的不是同一个文件
+                    return Err(Error::new(ErrorKind::InvalidData, format!("Httpc upload length mismatch, url: {}, server: {}, local: {}", **upload_url, *vec[0], len)));
+                }
+            }
+            upload_offset(&resp).map(Some)
+        },
+        status => Err(Error::from(HttpcError::Status(status))),
+    }
+}
+
+//获取响应中的Upload-Offset
+fn upload_offset(resp: &HttpClientResponse) -> Result<u64> {
+    match resp.get_header(Atom::from("Upload-Offset")).and_then(|vec| vec.into_iter().next()) {
+        None => Err(Error::new(ErrorKind::InvalidData, format!("Httpc upload no Upload-Offset, url: {}", resp.url_str()))),
+        Some(offset) => offset.as_str().trim().parse::<u64>().or_else(|_| {
+            Err(Error::new(ErrorKind::InvalidData, format!("Httpc upload invalid Upload-Offset, url: {}, offset: {}", resp.url_str(), *offset)))
+        }),
+    }
+}
+
+//读取一个分块，直到填满缓冲区或文件结束
+fn read_chunk(file: &mut File, buf: &mut [u8]) -> Result<usize> {
+    let mut size = 0;
+    while size < buf.len() {
+        let n = file.read(&mut buf[size..])?;
+        if n == 0 {
+            break;
+        }
+        size += n;
+    }
+    Ok(size)
+}
+
+//同步发送上传协议的请求，只修改本次请求使用的请求头
+fn send(client: &SharedHttpClient, method: Method, url: &Atom, headers: Vec<(&'static str, String)>, body: Vec<u8>) -> Result<HttpClientResponse> {
+    let mut copy = client.clone();
+    {
+        let inner = &mut Arc::make_mut(&mut copy).headers;
+        inner.set_raw("Tus-Resumable", TUS_VERSION);
+        for (key, value) in headers {
+            inner.set_raw(key, value);
+        }
+    }
+
+    //请求在当前线程中完成并回调
+    let (sender, receiver) = channel();
+    request(copy, method, url, None, Duration::from_millis(0), HttpClientBody::body(body), Box::new(move |_client: SharedHttpClient, result: Result<HttpClientResponse>| {
+        let _ = sender.send(result);
+    }));
+    receiver.recv().unwrap_or_else(|_| Err(Error::new(ErrorKind::Other, format!("Httpc upload request not completed, url: {}", **url))))
+}
@@ -0,0 +1,183 @@
+use std::thread;
+use std::net::TcpStream;
+use std::time::Duration;
+use std::boxed::FnBox;
+use std::sync::{Arc, Mutex};
+use std::error::Error as StdError;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use websocket::{ClientBuilder, OwnedMessage};
+use websocket::result::WebSocketError;
+use websocket::sync::{Reader, Writer};
+
+use pi_lib::atom::Atom;
+use pi_base::task::TaskType;
+use pi_base::pi_base_impl::cast_ext_task;
+
+/*
+* websocket消息
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum WsMessage {
+    Text(String),   //文本消息
+    Binary(Vec<u8>),//二进制消息
+}
+
+/*
+* 共享websocket客户端
+*/
+pub type SharedWsClient = Arc<WsClient>;
+
+/*
+* websocket消息回调，在连接的接收线程中按接收顺序回调，连接关闭或接收出错时以Err回调，之后不会再有回调
+*/
+pub type WsHandler = Arc<Fn(SharedWsClient, Result<WsMessage>) + Send + Sync>;
+
+/*
+* websocket客户端，只支持ws协议
+*/
+pub struct WsClient {
+    url: Atom,                          //连接的url
+    writer: Mutex<Writer<TcpStream>>,   //写端
+    closed: AtomicBool,                 //是否已关闭
+}
+
+impl WsClient {
+    //异步连接指定url，keep_alive为发送ping的间隔，单位毫秒，为0表示不发送ping，连接成功后通过handler接收消息
+    pub fn connect(url: Atom, keep_alive: u64, handler: WsHandler, callback: Box<FnBox(Result<SharedWsClient>)>) {
+        let func = move || {
+            match open(&url) {
+                Err(e) => callback(Err(e)),
+                Ok((reader, writer)) => {
+                    let client = Arc::new(WsClient {
+                        url: url,
+                        writer: Mutex::new(writer),
+                        closed: AtomicBool::new(false),
+                    });
+                    if keep_alive > 0 {
+                        ping(client.clone(), keep_alive);
+                    }
+                    recv(client.clone(), reader, handler);
+                    callback(Ok(client));
+                },
+            }
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), Atom::from("httpc ws connect task"));
+    }
+
+    //获取连接的url
+    pub fn url(&self) -> Atom {
+        self.url.clone()
+    }
+
+    //判断是否已关闭
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    //异步发送消息
+    pub fn send(client: &SharedWsClient, msg: WsMessage, callback: Box<FnBox(SharedWsClient, Result<()>)>) {
+        let copy = client.clone();
+        let func = move || {
+            let msg = match msg {
+                WsMessage::Text(text) => OwnedMessage::Text(text),
+                WsMessage::Binary(bin) => OwnedMessage::Binary(bin),
+            };
+            let r = copy.send_message(&msg);
+            callback(copy, r);
+        };
+        cast_ext_task(TaskType::Sync, 10000000, Box::new(func), Atom::from("httpc ws send task"));
+    }
+
+    //关闭连接，会通知服务器关闭
+    pub fn close(&self) {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let mut writer = self.writer.lock().unwrap();
+        let _ = writer.send_message(&OwnedMessage::Close(None));
+        let _ = writer.shutdown_all();
+    }
+
+    //同步发送消息
+    fn send_message(&self, msg: &OwnedMessage) -> Result<()> {
+        if self.is_closed() {
+            return Err(Error::new(ErrorKind::NotConnected, format!("Ws closed, url: {}", *self.url)));
+        }
+
+        self.writer.lock().unwrap().send_message(msg).or_else(|e| {
+            Err(ws_error(e))
+        })
+    }
+}
+
+//建立连接，并分离读写端
+fn open(url: &Atom) -> Result<(Reader<TcpStream>, Writer<TcpStream>)> {
+    let mut builder = ClientBuilder::new((*url).as_str()).or_else(|e| {
+        Err(Error::new(ErrorKind::InvalidInput, e.description().to_string()))
+    })?;
+    builder.connect_insecure().or_else(|e| {
+        Err(ws_error(e))
+    })?.split()
+}
+
+//在连接专用的线程中接收消息，直到连接关闭，阻塞接收不占用任务系统的工作线程
+fn recv(client: SharedWsClient, mut reader: Reader<TcpStream>, handler: WsHandler) {
+    thread::spawn(move || {
+        loop {
+            match reader.recv_message() {
+                Ok(OwnedMessage::Text(text)) => handler(client.clone(), Ok(WsMessage::Text(text))),
+                Ok(OwnedMessage::Binary(bin)) => handler(client.clone(), Ok(WsMessage::Binary(bin))),
+                Ok(OwnedMessage::Ping(data)) => {
+                    //回应服务器的ping
+                    let _ = client.send_message(&OwnedMessage::Pong(data));
+                },
+                Ok(OwnedMessage::Pong(_)) => (),
+                Ok(OwnedMessage::Close(_)) => {
+                    client.close();
+                    handler(client.clone(), Err(Error::new(ErrorKind::ConnectionAborted, format!("Ws closed by peer, url: {}", *client.url))));
+                    break;
+                },
+                Err(e) => {
+                    let closed = client.is_closed();
+                    client.close();
+                    if closed {
+                        //主动关闭
+                        handler(client.clone(), Err(Error::new(ErrorKind::ConnectionAborted, format!("Ws closed, url: {}", *client.url))));
+                    } else {
+                        handler(client.clone(), Err(ws_error(e)));
+                    }
+                    break;
+                },
+            }
+        }
+    });
+}
+
+//在连接专用的线程中定时发送ping，直到连接关闭，等待期间不占用任务系统的工作线程
+fn ping(client: SharedWsClient, keep_alive: u64) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(Duration::from_millis(keep_alive));
+            if client.is_closed() {
+                break;
+            }
+
+            if let Err(e) = client.send_message(&OwnedMessage::Ping(Vec::new())) {
+                println!("!!!> Ws Ping Error, url: {}, reason: {:?}", *client.url, e);
+                client.close();
+                break;
+            }
+        }
+    });
+}
+
+//转换websocket错误
+fn ws_error(e: WebSocketError) -> Error {
+    match e {
+        WebSocketError::IoError(e) => e,
+        e => Error::new(ErrorKind::Other, e.description().to_string()),
+    }
+}
@@ -35,12 +35,56 @@ pub struct ClientNodeImpl {
     // 当socket和stream还没准备好时候的缓冲区
     socket_handlers: VecDeque<Box<FnBox(&Socket, Arc<RwLock<Stream>>)>>,
     keep_alive: u16,
+
+    // 已发送但还没有收到回应的ping包数量
+    missed_pongs: usize,
+    ping_policy: PingPolicy,
+    dead_func: Option<DeadCallback>,
+
+    // 没有匹配任何主题回调的消息的回调
+    unhandled_func: Option<UnhandledCallback>,
+}
+
+// 连接失效回调，在关闭连接前调用
+pub type DeadCallback = Arc<Fn() + Send + Sync>;
+
+// 未处理消息回调，参数为消息的主题和负载，与主题回调一样在持有客户端锁时调用
+pub type UnhandledCallback = Arc<Fn(Atom, &[u8]) + Send + Sync>;
+
+// ping策略，连续未收到回应时缩短ping间隔，超过阈值则认为连接失效并关闭连接
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingPolicy {
+    pub max_missed: usize,  // 允许连续未回应的ping包数量，为0表示固定间隔，不检查回应
+    pub min_interval: u64,  // 缩短后的最小ping间隔，单位毫秒
+}
+
+impl Default for PingPolicy {
+    fn default() -> Self {
+        PingPolicy {
+            max_missed: 0,
+            min_interval: 1000,
+        }
+    }
+}
+
+impl PingPolicy {
+    // 获取下次ping的间隔，每个未回应的ping包使间隔减半
+    pub fn interval(&self, keep_alive: u16, missed: usize) -> Duration {
+        let interval = keep_alive as u64 * 1000;
+        if self.max_missed == 0 || missed == 0 {
+            return Duration::from_millis(interval);
+        }
+        let interval = interval.checked_shr(missed as u32).unwrap_or(0);
+        Duration::from_millis(if interval < self.min_interval { self.min_interval } else { interval })
+    }
 }
 
 #[derive(Clone)]
 pub struct ClientNode(pub Arc<Mutex<ClientNodeImpl>>);
 
-unsafe impl Sync for ClientNodeImpl {}
+// ClientNodeImpl只通过ClientNode的Mutex访问，Mutex只要求Send，不需要实现Sync
+// stream中的NetTimer和各种回调没有实现Send，但只在持有客户端锁时访问或从节点中取出，stream本身由RwLock保护，
+// 回调会在网络线程或定时器线程中调用，所以设置回调时不能捕获只能在当前线程使用的数据，例如Rc，由调用者保证
 unsafe impl Send for ClientNodeImpl {}
 
 struct TopicData {
@@ -67,6 +111,11 @@ impl ClientNode {
             topic_patterns: FnvHashMap::default(),
             socket_handlers: VecDeque::new(),
             keep_alive: 0,
+
+            missed_pongs: 0,
+            ping_policy: PingPolicy::default(),
+            dead_func: None,
+            unhandled_func: None,
         })))
     }
     pub fn get_socket(&self) -> Socket {
@@ -74,25 +123,63 @@ impl ClientNode {
         node.socket.clone().unwrap().clone()
     }
 
-    //只有在keep_alive时间内都没有数据包发送才会发送ping包
+    //设置ping策略和连接失效回调
+    pub fn set_ping_policy(&self, policy: PingPolicy, dead_func: Option<DeadCallback>) {
+        let mut node = self.0.lock().unwrap();
+        node.ping_policy = policy;
+        node.dead_func = dead_func;
+    }
+
+    //设置未处理消息回调，没有匹配任何主题回调的消息会回调，包括主题无效的消息，None表示忽略
+    pub fn set_unhandled_handler(&self, handler: Option<UnhandledCallback>) {
+        self.0.lock().unwrap().unhandled_func = handler;
+    }
+
+    //获取已发送但还没有收到回应的ping包数量
+    pub fn get_missed_pongs(&self) -> usize {
+        self.0.lock().unwrap().missed_pongs
+    }
+
+    //只有在keep_alive时间内都没有数据包发送才会发送ping包，连续未收到回应时按ping策略缩短间隔
     pub fn ping(&self) {
         let client = self.clone();
         let keep_alive;
         let socket;
+        let interval;
         {
             let node = self.0.lock().unwrap();
             keep_alive = node.keep_alive;
             socket = node.socket.clone();
+            interval = node.ping_policy.interval(keep_alive, node.missed_pongs);
         }
         if keep_alive > 0 {
             let timers = self.get_timers();
             let mut timers = timers.write().unwrap();
             timers.set_timeout(
                 Atom::from(String::from("client_ping")),
-                Duration::from_secs(keep_alive as u64),
+                interval,
                 Box::new(move |_src: Atom| {
                     println!("keep_alive timeout ping !!!!!!!!!!!!");
                     let socket = socket.unwrap();
+                    let dead_func = {
+                        let mut node = client.0.lock().unwrap();
+                        if node.ping_policy.max_missed > 0 && node.missed_pongs >= node.ping_policy.max_missed {
+                            //连接失效，停止ping
+                            node.keep_alive = 0;
+                            Some(node.dead_func.clone())
+                        } else {
+                            node.missed_pongs += 1;
+                            None
+                        }
+                    };
+                    if let Some(dead_func) = dead_func {
+                        println!("!!!> Mqtt Client Ping Timeout, missed pongs exceeded");
+                        if let Some(func) = dead_func {
+                            func();
+                        }
+                        socket.close(true);
+                        return;
+                    }
                     //发送数据
                     util::send_pingreq(&socket);
                     //递归
@@ -101,6 +188,19 @@ impl ClientNode {
             )
         }
     }
+    //停止ping，取消ping定时器，已触发的定时器也不会再递归设置
+    pub fn stop_ping(&self) {
+        let stream;
+        {
+            let mut node = self.0.lock().unwrap();
+            node.keep_alive = 0;
+            stream = node.stream.clone();
+        }
+        if let Some(stream) = stream {
+            let timers = stream.read().unwrap().net_timers.clone();
+            timers.write().unwrap().cancel_timeout(Atom::from(String::from("client_ping")));
+        }
+    }
     //获取net定时器
     pub fn get_timers(&self) -> Arc<RwLock<NetTimers<TimerCallback>>> {
         let node = self.0.lock().unwrap();
@@ -108,6 +208,18 @@ impl ClientNode {
         let stream = stream.read().unwrap();
         stream.net_timers.clone()
     }
+
+    //分发一个发布消息，与从连接收到的消息一样回调主题回调或未处理消息回调，需要先设置socket
+    pub fn handle_publish(&self, topic: &str, payload: Vec<u8>) {
+        recv_publish(self.0.clone(), mqtt3::Publish {
+            dup: false,
+            qos: mqtt3::QoS::AtMostOnce,
+            retain: false,
+            topic_name: topic.to_string(),
+            pid: None,
+            payload: Arc::new(payload),
+        });
+    }
 }
 
 impl Client for ClientNode {
@@ -135,6 +247,8 @@ impl Client for ClientNode {
             node.close_func = close_func;
             node.connect_func = connect_func;
             node.keep_alive = keep_alive;
+            //重新连接时不计入之前连接未回应的ping包
+            node.missed_pongs = 0;
         }
 
         let node = self.0.clone();
@@ -402,8 +516,8 @@ fn handle_recv(
     }
 }
 
-fn recv_pingresp(_node: Arc<Mutex<ClientNodeImpl>>) {
-    // TODO: impl
+fn recv_pingresp(node: Arc<Mutex<ClientNodeImpl>>) {
+    node.lock().unwrap().missed_pongs = 0;
 }
 
 fn recv_connect_ack(node: Arc<Mutex<ClientNodeImpl>>, ack: mqtt3::Connack) {
@@ -432,7 +546,9 @@ fn recv_connect_ack(node: Arc<Mutex<ClientNodeImpl>>, ack: mqtt3::Connack) {
         )),
     };
 
-    if let Some(func) = node.lock().unwrap().connect_func.take() {
+    //先取出回调再调用，回调中可以调用会锁定客户端的方法
+    let func = node.lock().unwrap().connect_func.take();
+    if let Some(func) = func {
         func.call_box((r,));
     }
 }
@@ -457,20 +573,31 @@ fn recv_unsub_ack(node: Arc<Mutex<ClientNodeImpl>>, id: u16) {
 fn recv_publish(node: Arc<Mutex<ClientNodeImpl>>, publish: mqtt3::Publish) {
     let node = &mut node.lock().unwrap();
 
+    let atom = Atom::from(publish.topic_name.as_str());
     let publish_topic = mqtt3::TopicPath::from_str(&publish.topic_name);
     if let Err(_) = publish_topic {
+        if let Some(ref func) = node.unhandled_func {
+            func(atom, publish.payload.as_slice());
+        }
         return;
     }
 
-    let atom = Atom::from(publish.topic_name.as_str());
+    let mut handled = false;
     let socket = node.socket.clone().unwrap();
     if let Some(data) = node.topics.get(&atom) {
         (data.func)(Ok((socket.clone(), publish.payload.as_slice())));
+        handled = true;
     }
     let publish_topic = publish_topic.unwrap();
     for (_, data) in node.topic_patterns.iter() {
         if data.topic.is_match(&publish_topic) {
             (data.func)(Ok((socket.clone(), publish.payload.as_slice())));
+            handled = true;
+        }
+    }
+    if !handled {
+        if let Some(ref func) = node.unhandled_func {
+            func(atom, publish.payload.as_slice());
         }
     }
 }
@@ -10,6 +10,7 @@ extern crate magnetic;
 extern crate pi_base;
 extern crate pi_lib;
 extern crate rustc_serialize;
+extern crate lz4;
 
 pub mod client;
 pub mod data;
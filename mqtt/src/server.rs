@@ -1,7 +1,7 @@
 use std::boxed::FnBox;
 use std::fmt::{Debug, Formatter, Result as DebugResult};
 use std::io::{Error, ErrorKind, Result};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
@@ -12,7 +12,6 @@ use magnetic::{Consumer, Producer};
 
 use pi_lib::atom::Atom;
 use pi_lib::gray::GrayVersion;
-use pi_base::util::uncompress;
 use data::{Server, SetAttrFun};
 use fnv::FnvHashMap;
 use mqtt3::{self, Packet};
@@ -20,6 +19,9 @@ use net::{CloseFn, Socket, Stream};
 use session;
 use util;
 
+//默认的最大请求大小，解压后，单位字节
+pub const DEFAULT_MAX_REQUEST_SIZE: usize = 16 * 1024 * 1024;
+
 #[derive(Clone)]
 pub struct ServerNode(Arc<Mutex<ServerNodeImpl>>);
 
@@ -65,6 +67,8 @@ pub struct ClientStub {
         MPSCConsumer<Box<FnBox()>, DynamicBuffer<Box<FnBox()>>>,
     )>,
     queue_size: Arc<AtomicUsize>,
+    ext_layout: Arc<AtomicBool>,    //客户端是否发送过扩展布局的rpc消息，发送过才以扩展布局回应
+    cipher: Arc<RwLock<Option<Arc<util::RPCCipher>>>>, //客户端发送过加密的rpc消息后，以同一加解密加密回应
 }
 
 impl GrayVersion for ClientStub {
@@ -98,6 +102,8 @@ struct ServerNodeImpl {
     retain_topics: FnvHashMap<Atom, RetainTopic>,
     metas: FnvHashMap<Atom, Arc<TopicMeta>>,
     set_attr: Option<SetAttrFun>,
+    cipher: Option<Arc<util::RPCCipher>>,   //rpc消息体的加解密，用于解密加密的请求
+    max_request_size: usize,                //rpc请求解压后的最大长度，为0表示不限制
 }
 
 unsafe impl Sync for ServerNodeImpl {}
@@ -129,6 +135,16 @@ impl ClientStub {
         self.socket.clone()
     }
 
+    //判断客户端是否支持扩展布局的rpc消息，旧版本的客户端只支持基础布局
+    pub fn is_ext_layout(&self) -> bool {
+        self.ext_layout.load(Ordering::Relaxed)
+    }
+
+    //获取回应的加解密，客户端发送过加密的rpc消息才有，回应需要加密
+    pub fn get_cipher(&self) -> Option<Arc<util::RPCCipher>> {
+        self.cipher.read().unwrap().clone()
+    }
+
     //修改遗言
     pub fn set_last_will(&mut self, last_will: mqtt3::LastWill) {
         let mut last_will2 = self.last_will.write().unwrap();
@@ -144,8 +160,47 @@ impl ServerNode {
             retain_topics: FnvHashMap::default(),
             metas: FnvHashMap::default(),
             set_attr: None,
+            cipher: None,
+            max_request_size: DEFAULT_MAX_REQUEST_SIZE,
         })))
     }
+
+    //设置rpc消息体的加解密，None表示不支持加密的请求，默认不支持，只影响之后收到的请求
+    pub fn set_rpc_cipher(&self, cipher: Option<Arc<util::RPCCipher>>) {
+        self.0.lock().unwrap().cipher = cipher;
+    }
+
+    //获取rpc消息体的加解密
+    pub fn get_rpc_cipher(&self) -> Option<Arc<util::RPCCipher>> {
+        self.0.lock().unwrap().cipher.clone()
+    }
+
+    //设置rpc请求的最大长度，解压后，单位字节，超限的请求直接丢弃，为0表示不限制，只影响之后收到的请求
+    pub fn set_rpc_max_request_size(&self, size: usize) {
+        self.0.lock().unwrap().max_request_size = size;
+    }
+
+    //获取rpc请求的最大长度
+    pub fn get_rpc_max_request_size(&self) -> usize {
+        self.0.lock().unwrap().max_request_size
+    }
+    //处理一个连接请求，与从连接收到的连接请求一样注册客户端并回应，用于在没有网络连接时处理消息
+    pub fn handle_connect(&self, socket: &Socket, stream: Arc<RwLock<Stream>>, connect: mqtt3::Connect) {
+        recv_connect(self.0.clone(), socket, stream, connect);
+    }
+
+    //分发一个发布消息，与从连接收到的消息一样解压、解密并回调主题回调，需要先处理客户端的连接请求
+    pub fn handle_publish(&self, socket: &Socket, topic: &str, payload: Vec<u8>) {
+        recv_publish(self.0.clone(), mqtt3::Publish {
+            dup: false,
+            qos: mqtt3::QoS::AtMostOnce,
+            retain: false,
+            topic_name: topic.to_string(),
+            pid: None,
+            payload: Arc::new(payload),
+        }, socket);
+    }
+
     //设置连接关闭回调(遗言发布)
     pub fn set_close_callback(&self, stream: &mut Stream, func: CloseFn) {
         let node = self.0.clone();
@@ -336,6 +391,8 @@ fn recv_connect(
             last_will: Arc::new(RwLock::new(connect.last_will)),
             queue: Arc::new(mpsc_queue(DynamicBuffer::new(32).unwrap())),
             queue_size: Arc::new(AtomicUsize::new(0)),
+            ext_layout: Arc::new(AtomicBool::new(false)),
+            cipher: Arc::new(RwLock::new(None)),
         });
         node.clients.insert(socket.socket, client_stub.clone());
         //模拟客户端发送主题消息
@@ -523,6 +580,8 @@ fn recv_publish(node: Arc<Mutex<ServerNodeImpl>>, publish: mqtt3::Publish, socke
     let topic = topic.unwrap();
     //println!("topic = {:?}", topic);
     let mut r = None;
+    let cipher;
+    let max_request_size;
     {
         let node = &mut node.lock().unwrap();
         for (_, meta) in node.metas.iter() {
@@ -531,26 +590,62 @@ fn recv_publish(node: Arc<Mutex<ServerNodeImpl>>, publish: mqtt3::Publish, socke
                 break;
             }
         }
+        cipher = node.cipher.clone();
+        max_request_size = node.max_request_size;
     };
 
     match r {
         Some(v) => {
             let data = &publish.payload;
-            let header = data[0];
-            //压缩版本
-            let compress = (&header >> 6) as u8;
-            //消息版本
-            //let vsn = &header & 0b11111;
-            let r = match compress {
-                util::UNCOMPRESS => Vec::from(&data[1..]),
-                util::LZ4_BLOCK => {
-                    let mut vec_ = Vec::new();
-                    uncompress(&data[1..], &mut vec_).is_ok();
-                    vec_
-                }
-                _ => {println!("Compression mode does not support, topic:{}", &publish.topic_name); return;},
+            let header = match util::parse_rpc_header(data) {
+                Ok(header) => header,
+                Err(e) => {println!("Invalid rpc header, topic:{}, reason:{:?}", &publish.topic_name, e); return;},
+            };
+            if header.ext {
+                //只有握手确认服务器支持扩展布局的客户端才会发送扩展布局的消息
+                v.0.ext_layout.store(true, Ordering::Relaxed);
+            }
+            let body = match util::uncompress_rpc_body(&header, data, max_request_size) {
+                Ok(ref body) if max_request_size > 0 && body.len() > max_request_size => {
+                    println!("Rpc request too large, topic:{}, limit:{}", &publish.topic_name, max_request_size);
+                    return;
+                },
+                Ok(body) => body,
+                Err(e) => {println!("Uncompress rpc body failed, topic:{}, reason:{:?}", &publish.topic_name, e); return;},
+            };
+            if !header.batch {
+                let body = match decrypt_rpc_body(&v.0, &cipher, &header, body) {
+                    Ok(body) => body,
+                    Err(e) => {println!("Decrypt rpc body failed, topic:{}, reason:{:?}", &publish.topic_name, e); return;},
+                };
+                //回调参数为消息ID、超时时长和解压解密后的消息体
+                let mut r = Vec::with_capacity(util::RPC_HEADER_SIZE - 1 + body.len());
+                r.extend_from_slice(&data[1..util::RPC_HEADER_SIZE]);
+                r.extend_from_slice(&body);
+                (v.1.publish_func)((&*v.0).clone(), Ok(Arc::new(r)));
+                return;
+            }
+
+            //批量消息，逐条回调
+            let msgs = match util::decode_rpc_batch(&body) {
+                Ok(msgs) => msgs,
+                Err(e) => {println!("Invalid rpc batch, topic:{}, reason:{:?}", &publish.topic_name, e); return;},
             };
-            (v.1.publish_func)((&*v.0).clone(), Ok(Arc::new(r)));
+            for msg in msgs {
+                let header = match util::parse_rpc_header(msg) {
+                    Ok(header) => header,
+                    Err(e) => {println!("Invalid rpc batch message, topic:{}, reason:{:?}", &publish.topic_name, e); continue;},
+                };
+                //批量中的每条消息都不压缩，加密的消息分别解密
+                let body = match decrypt_rpc_body(&v.0, &cipher, &header, Vec::from(&msg[header.body_offset..])) {
+                    Ok(body) => body,
+                    Err(e) => {println!("Decrypt rpc batch message failed, topic:{}, reason:{:?}", &publish.topic_name, e); continue;},
+                };
+                let mut r = Vec::with_capacity(util::RPC_HEADER_SIZE - 1 + body.len());
+                r.extend_from_slice(&msg[1..util::RPC_HEADER_SIZE]);
+                r.extend_from_slice(&body);
+                (v.1.publish_func)((&*v.0).clone(), Ok(Arc::new(r)));
+            }
         },
         None => {
             println!("Topic is not registered {:?}", &publish.topic_name);
@@ -558,6 +653,24 @@ fn recv_publish(node: Arc<Mutex<ServerNodeImpl>>, publish: mqtt3::Publish, socke
     }
 }
 
+//解密请求的消息体，消息体未加密则原样返回，解密成功后客户端的回应也会加密
+fn decrypt_rpc_body(client: &ClientStub, cipher: &Option<Arc<util::RPCCipher>>, header: &util::RpcHeader, body: Vec<u8>) -> Result<Vec<u8>> {
+    if !header.encrypted {
+        return Ok(body);
+    }
+    match *cipher {
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("rpc request encrypted without cipher, msg_id: {}", header.msg_id),
+        )),
+        Some(ref cipher) => {
+            let body = cipher.decrypt(body)?;
+            *client.cipher.write().unwrap() = Some(cipher.clone());
+            Ok(body)
+        },
+    }
+}
+
 fn recv_pingreq(_node: Arc<Mutex<ServerNodeImpl>>, socket: &Socket) {
     util::send_pingresp(socket);
 }
@@ -15,6 +15,7 @@ pub struct Session {
     pub msg_id: u32,
     pub seq: bool,
     pub timeout: (usize, u8), //(系统当前时间, 超时时长)
+    pub compress_threshold: usize, //回应压缩阈值，消息大于阈值时压缩，为0表示不压缩
 }
 
 unsafe impl Sync for Session {}
@@ -42,6 +43,7 @@ impl Session {
             msg_id,
             seq,
             timeout: (0, 0),
+            compress_threshold: 0,
         }
     }
 
@@ -49,7 +51,22 @@ impl Session {
     pub fn send(&self, topic: Atom, msg: Vec<u8>) {
         let msg_id = self.msg_id;
         let timeout = self.timeout.1;
-        let buff =  util::encode(encode_reps(msg_id, timeout, msg));
+        let is_compress = self.compress_threshold > 0 && msg.len() > self.compress_threshold;
+        //客户端发送过加密的请求时加密回应，加密的回应只使用扩展布局
+        //只以客户端支持的布局回应，旧版本的客户端只支持基础布局
+        let buff = if let Some(cipher) = self.client.get_cipher() {
+            match cipher.encrypt(msg) {
+                Ok(msg) => util::encode_rpc_encrypted_frame(msg_id, timeout, msg, is_compress),
+                Err(e) => {
+                    println!("!!!> Rpc Session Encrypt Error, msg_id: {}, reason: {:?}", msg_id, e);
+                    return;
+                }
+            }
+        } else if self.client.is_ext_layout() {
+            util::encode_rpc_frame(msg_id, timeout, msg, is_compress)
+        } else {
+            util::encode_rpc_legacy_frame(msg_id, timeout, msg, is_compress)
+        };
         let t = mqtt3::TopicPath::from_str((*topic).as_str());
         //发送数据
         util::send_publish(
@@ -82,6 +99,11 @@ impl Session {
     pub fn set_timeout(&mut self, systime: usize, timeout: u8) {
         self.timeout = (systime, timeout);
     }
+
+    //设置回应压缩阈值，消息大于阈值时以LZ4_BLOCK压缩，为0表示不压缩
+    pub fn set_compress_threshold(&mut self, threshold: usize) {
+        self.compress_threshold = threshold;
+    }
 }
 
 impl GrayVersion for Session {
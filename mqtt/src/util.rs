@@ -1,4 +1,4 @@
-use std::io::{Cursor, Error, ErrorKind, Result};
+use std::io::{self, Cursor, Error, ErrorKind, Read, Write, Result};
 use std::sync::{Arc, RwLock};
 
 use rand::{self, Rng};
@@ -8,10 +8,67 @@ use mqtt3::{self, MqttRead, MqttWrite, Packet, PacketIdentifier, QoS};
 use net::{Socket, Stream};
 use net::net::recv;
 
+use lz4::{Decoder, EncoderBuilder};
+use pi_base::util::{compress, uncompress, CompressLevel};
+
 //LZ4_BLOCK 压缩
 pub const LZ4_BLOCK: u8 = 1;
 //不压缩
 pub const UNCOMPRESS: u8 = 0;
+//LZ4_FRAME 压缩
+pub const LZ4_FRAME: u8 = 2;
+
+//LZ4_BLOCK的最大压缩比，解压后的长度不会超过压缩后长度的倍数
+const LZ4_BLOCK_MAX_RATIO: usize = 255;
+
+//支持的压缩算法集合，每个压缩算法占一位，握手时交换，不压缩总是支持
+pub const RPC_COMPRESS_MODES: u8 = (1 << UNCOMPRESS) | (1 << LZ4_BLOCK) | (1 << LZ4_FRAME);
+
+//rpc消息固定头长度：1字节压缩和版本，4字节消息ID，1字节超时时长
+pub const RPC_HEADER_SIZE: usize = 6;
+//第一字节的扩展布局标记，位于压缩算法和版本之间，旧版本的对端总是为0
+pub const RPC_EXT: u8 = 0b100000;
+//扩展布局在固定头后用1字节表示消息标记
+pub const RPC_FLAGS_LEN: usize = 1;
+//扩展布局LZ4_BLOCK压缩时，消息标记后用4字节大端表示消息体解压后的长度
+pub const RPC_RAW_SIZE_LEN: usize = 4;
+//消息标记的批量标记，批量消息的压缩作用于整个批量
+pub const RPC_FLAG_BATCH: u8 = 0b1;
+//握手时与压缩算法集合一起交换的扩展布局标记，双方都支持时才发送扩展布局的消息
+pub const RPC_EXT_LAYOUT: u8 = 0b10000000;
+//消息标记的加密标记，加密消息只使用扩展布局，消息体在压缩前加密，解压后解密
+pub const RPC_FLAG_ENCRYPT: u8 = 0b10;
+//批量消息中每条消息前用4字节大端表示消息长度
+pub const RPC_BATCH_LEN_SIZE: usize = 4;
+
+/*
+* rpc消息头，由第一字节的扩展布局标记决定布局，不根据消息体内容推断
+* 基础布局：固定头 + 消息体，LZ4_BLOCK的消息体直接是压缩结果，与旧版本的对端兼容
+* 扩展布局：固定头 + 1字节消息标记 + 消息体，LZ4_BLOCK时消息标记后有4字节解压后长度
+* 批量消息：只使用扩展布局，消息ID和超时时长为0，解压后的消息体由多条不压缩的rpc消息组成，每条消息前有4字节长度
+* 加密消息：只使用扩展布局，由消息标记的加密标记表示，第一字节的5位消息版本不变
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RpcHeader {
+    pub compress: u8,           //压缩算法
+    pub vsn: u8,                //消息版本
+    pub msg_id: u32,            //消息ID
+    pub timeout: u8,            //超时时长（秒）
+    pub raw_size: Option<usize>,//消息体解压后的长度，只有扩展布局的LZ4_BLOCK有
+    pub body_offset: usize,     //消息体在消息中的偏移
+    pub ext: bool,              //是否是扩展布局
+    pub batch: bool,            //是否是批量消息
+    pub encrypted: bool,        //消息体是否已加密
+}
+
+// rpc消息体的加解密，用于不依赖传输层tls的端到端加密，密钥由实现者管理，客户端和服务器需要设置相同的加解密
+pub trait RPCCipher: Send + Sync {
+    // 加密消息体，在压缩前调用
+    fn encrypt(&self, body: Vec<u8>) -> Result<Vec<u8>>;
+
+    // 解密消息体，在解压后调用
+    fn decrypt(&self, body: Vec<u8>) -> Result<Vec<u8>>;
+}
 
 type MqttRecvCallback = Box<FnMut(Result<Packet>)>;
 
@@ -202,6 +259,266 @@ fn recv_pack(
     }
 }
 
+//解析rpc消息头，按第一字节的扩展布局标记解析基础布局或扩展布局
+pub fn parse_rpc_header(data: &[u8]) -> Result<RpcHeader> {
+    if data.len() < RPC_HEADER_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "rpc header too short"));
+    }
+
+    let header = data[0];
+    let msg_id = ((data[1] as u32) << 24) | ((data[2] as u32) << 16) | ((data[3] as u32) << 8) | (data[4] as u32);
+    let mut rpc_header = RpcHeader {
+        compress: header >> 6,
+        vsn: header & 0b11111,
+        msg_id,
+        timeout: data[5],
+        raw_size: None,
+        body_offset: RPC_HEADER_SIZE,
+        ext: header & RPC_EXT != 0,
+        batch: false,
+        encrypted: false,
+    };
+    if rpc_header.ext {
+        if data.len() < RPC_HEADER_SIZE + RPC_FLAGS_LEN {
+            return Err(Error::new(ErrorKind::InvalidData, "rpc ext header too short"));
+        }
+        rpc_header.batch = data[RPC_HEADER_SIZE] & RPC_FLAG_BATCH != 0;
+        rpc_header.encrypted = data[RPC_HEADER_SIZE] & RPC_FLAG_ENCRYPT != 0;
+        rpc_header.body_offset += RPC_FLAGS_LEN;
+    }
+    match rpc_header.compress {
+        UNCOMPRESS | LZ4_FRAME => (),
+        //基础布局的LZ4_BLOCK没有解压后长度
+        LZ4_BLOCK if !rpc_header.ext => (),
+        LZ4_BLOCK => {
+            let offset = rpc_header.body_offset;
+            if data.len() < offset + RPC_RAW_SIZE_LEN {
+                return Err(Error::new(ErrorKind::InvalidData, "rpc lz4 block header too short"));
+            }
+            let size = ((data[offset] as usize) << 24) | ((data[offset + 1] as usize) << 16) | ((data[offset + 2] as usize) << 8) | (data[offset + 3] as usize);
+            rpc_header.raw_size = Some(size);
+            rpc_header.body_offset = offset + RPC_RAW_SIZE_LEN;
+        }
+        c => return Err(Error::new(ErrorKind::InvalidData, format!("rpc compress not support, compress: {}", c))),
+    }
+    Ok(rpc_header)
+}
+
+//编码扩展布局的rpc消息头，raw_size只在LZ4_BLOCK时写入，只能发送给握手确认支持扩展布局的对端
+pub fn encode_rpc_header(compress: u8, msg_id: u32, timeout: u8, raw_size: usize) -> Vec<u8> {
+    let mut buff = encode_rpc_legacy_header(compress, msg_id, timeout);
+    buff[0] |= RPC_EXT;
+    //1字节消息标记
+    buff.push(0);
+    if compress == LZ4_BLOCK {
+        //4字节解压后长度
+        buff.extend_from_slice(&[(raw_size >> 24) as u8, (raw_size >> 16) as u8, (raw_size >> 8) as u8, raw_size as u8]);
+    }
+    buff
+}
+
+//编码基础布局的rpc消息头，与旧版本的对端兼容
+pub fn encode_rpc_legacy_header(compress: u8, msg_id: u32, timeout: u8) -> Vec<u8> {
+    let mut buff = Vec::with_capacity(RPC_HEADER_SIZE + RPC_FLAGS_LEN + RPC_RAW_SIZE_LEN);
+    //第一字节：2位压缩版本、1位扩展布局标记、5位消息版本 TODO 消息版本以后定义
+    buff.push(((compress << 6) | 0) as u8);
+    //4字节消息ID
+    buff.extend_from_slice(&[(msg_id >> 24) as u8, (msg_id >> 16) as u8, (msg_id >> 8) as u8, msg_id as u8]);
+    //一字节超时时长（秒）
+    buff.push(timeout);
+    buff
+}
+
+//编码扩展布局的完整rpc消息，is_compress为true时以LZ4_BLOCK压缩消息体，压缩失败则不压缩
+pub fn encode_rpc_frame(msg_id: u32, timeout: u8, msg: Vec<u8>, is_compress: bool) -> Vec<u8> {
+    encode_frame(true, msg_id, timeout, msg, is_compress)
+}
+
+//编码基础布局的完整rpc消息，与旧版本的对端兼容，is_compress为true时以LZ4_BLOCK压缩消息体，压缩失败则不压缩
+pub fn encode_rpc_legacy_frame(msg_id: u32, timeout: u8, msg: Vec<u8>, is_compress: bool) -> Vec<u8> {
+    encode_frame(false, msg_id, timeout, msg, is_compress)
+}
+
+//编码消息体已加密的完整rpc消息，加密的消息只使用扩展布局，is_compress为true时以LZ4_BLOCK压缩加密后的消息体
+pub fn encode_rpc_encrypted_frame(msg_id: u32, timeout: u8, msg: Vec<u8>, is_compress: bool) -> Vec<u8> {
+    let mut buff = encode_frame(true, msg_id, timeout, msg, is_compress);
+    buff[RPC_HEADER_SIZE] |= RPC_FLAG_ENCRYPT;
+    buff
+}
+
+fn encode_frame(ext: bool, msg_id: u32, timeout: u8, msg: Vec<u8>, is_compress: bool) -> Vec<u8> {
+    let header = |mode: u8| if ext {
+        encode_rpc_header(mode, msg_id, timeout, msg.len())
+    } else {
+        encode_rpc_legacy_header(mode, msg_id, timeout)
+    };
+    if is_compress {
+        let mut body = vec![];
+        if compress(msg.as_slice(), &mut body, CompressLevel::High).is_ok() {
+            let mut buff = header(LZ4_BLOCK);
+            buff.extend_from_slice(body.as_slice());
+            return buff;
+        }
+    }
+    let mut buff = header(UNCOMPRESS);
+    buff.extend_from_slice(msg.as_slice());
+    buff
+}
+
+//编码批量rpc消息头，批量消息只使用扩展布局，raw_size只在LZ4_BLOCK时写入
+pub fn encode_rpc_batch_header(compress: u8, raw_size: usize) -> Vec<u8> {
+    let mut buff = encode_rpc_header(compress, 0, 0, raw_size);
+    buff[RPC_HEADER_SIZE] |= RPC_FLAG_BATCH;
+    buff
+}
+
+//编码批量rpc消息的消息体，每条消息都是不压缩的完整rpc消息
+pub fn encode_rpc_batch(msgs: &[Vec<u8>]) -> Vec<u8> {
+    let mut buff = Vec::with_capacity(msgs.iter().fold(0, |acc, msg| acc + RPC_BATCH_LEN_SIZE + msg.len()));
+    for msg in msgs {
+        let len = msg.len();
+        buff.extend_from_slice(&[(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8]);
+        buff.extend_from_slice(msg);
+    }
+    buff
+}
+
+//解码解压后的批量rpc消息体，返回每条rpc消息
+pub fn decode_rpc_batch(body: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut msgs = Vec::new();
+    let mut offset = 0;
+    while offset < body.len() {
+        if body.len() - offset < RPC_BATCH_LEN_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, "rpc batch length too short"));
+        }
+        let len = ((body[offset] as usize) << 24) | ((body[offset + 1] as usize) << 16) | ((body[offset + 2] as usize) << 8) | (body[offset + 3] as usize);
+        offset += RPC_BATCH_LEN_SIZE;
+        if body.len() - offset < len {
+            return Err(Error::new(ErrorKind::InvalidData, format!("rpc batch message too short, expect: {}, real: {}", len, body.len() - offset)));
+        }
+        msgs.push(&body[offset..offset + len]);
+        offset += len;
+    }
+    Ok(msgs)
+}
+
+//转储rpc消息，包括消息头各字段的注释和全部字节的十六进制，用于调试
+pub fn dump_rpc_frame(data: &[u8]) -> String {
+    let mut dump = String::new();
+    match parse_rpc_header(data) {
+        Err(e) => dump.push_str(&format!("invalid header: {}\n", e)),
+        Ok(header) => {
+            let compress = match header.compress {
+                UNCOMPRESS => "uncompress",
+                LZ4_BLOCK => "lz4 block",
+                LZ4_FRAME => "lz4 frame",
+                _ => "unknown",
+            };
+            dump.push_str(&format!("[0] {:02x}: compress: {} ({}), ext: {}, vsn: {}\n", data[0], header.compress, compress, header.ext, header.vsn));
+            dump.push_str(&format!("[1..5] {}: msg_id: {}\n", hex(&data[1..5]), header.msg_id));
+            dump.push_str(&format!("[5] {:02x}: timeout: {}\n", data[5], header.timeout));
+            if header.ext {
+                dump.push_str(&format!("[6] {:02x}: batch: {}, encrypted: {}\n", data[6], header.batch, header.encrypted));
+            }
+            if let Some(size) = header.raw_size {
+                let offset = header.body_offset - RPC_RAW_SIZE_LEN;
+                dump.push_str(&format!("[{}..{}] {}: raw_size: {}\n", offset, header.body_offset, hex(&data[offset..header.body_offset]), size));
+            }
+            dump.push_str(&format!("[{}..{}] body: {} bytes\n", header.body_offset, data.len(), data.len() - header.body_offset));
+        }
+    }
+    //每行16字节
+    for (index, line) in data.chunks(16).enumerate() {
+        dump.push_str(&format!("{:08x}  {}\n", index * 16, hex(line)));
+    }
+    dump
+}
+
+//转换为以空格分隔的十六进制
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+}
+
+//以LZ4帧流式压缩消息体，压缩结果按块直接追加到buff的消息头之后，不缓冲完整的压缩结果
+pub fn compress_rpc_frame(buff: &mut Vec<u8>, msg: &[u8]) -> Result<()> {
+    let mut encoder = EncoderBuilder::new().build(buff)?;
+    encoder.write_all(msg)?;
+    let (_, r) = encoder.finish();
+    r
+}
+
+//解压rpc消息体，limit大于0时，LZ4_BLOCK解压后长度超限直接失败，LZ4_FRAME最多只解压limit + 1字节，调用者可通过返回长度判断是否超限
+pub fn uncompress_rpc_body(header: &RpcHeader, data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let body = &data[header.body_offset..];
+    match header.compress {
+        UNCOMPRESS => Ok(Vec::from(body)),
+        LZ4_BLOCK => {
+            //解压后长度由对端提供，分配缓冲区前检查，避免按伪造的长度分配内存
+            if let Some(size) = header.raw_size {
+                if limit > 0 && size > limit {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("rpc lz4 block too large, size: {}, limit: {}", size, limit)));
+                }
+            }
+            //预分配不超过lz4块的最大压缩比和limit，limit为0时也不按对端提供的长度分配
+            let mut capacity = header.raw_size.unwrap_or(0).min(body.len().saturating_mul(LZ4_BLOCK_MAX_RATIO));
+            if limit > 0 {
+                capacity = capacity.min(limit);
+            }
+            let mut vec = Vec::with_capacity(capacity);
+            if uncompress(body, &mut vec).is_err() {
+                return Err(Error::new(ErrorKind::InvalidData, "rpc lz4 block uncompress failed"));
+            }
+            //旧布局没有解压后长度，只能在解压后检查
+            if limit > 0 && vec.len() > limit {
+                return Err(Error::new(ErrorKind::InvalidData, format!("rpc lz4 block too large, size: {}, limit: {}", vec.len(), limit)));
+            }
+            if let Some(size) = header.raw_size {
+                if vec.len() != size {
+                    return Err(Error::new(ErrorKind::InvalidData, format!("rpc lz4 block size mismatch, expect: {}, real: {}", size, vec.len())));
+                }
+            }
+            Ok(vec)
+        }
+        LZ4_FRAME => {
+            let mut vec = Vec::new();
+            let mut decoder = Decoder::new(Cursor::new(body))?;
+            if limit > 0 {
+                (&mut decoder).take(limit as u64 + 1).read_to_end(&mut vec)?;
+            } else {
+                decoder.read_to_end(&mut vec)?;
+            }
+            Ok(vec)
+        }
+        c => Err(Error::new(ErrorKind::InvalidData, format!("rpc compress not support, compress: {}", c))),
+    }
+}
+
+//解压rpc消息体并写入writer，返回写入的字节数，limit大于0时，LZ4_FRAME最多只解压并写入limit + 1字节，调用者可通过返回长度判断是否超限
+//不压缩和LZ4_FRAME不缓冲消息体，LZ4_BLOCK只能整体解压，会先解压到缓冲区
+pub fn uncompress_rpc_body_to(header: &RpcHeader, data: &[u8], writer: &mut Write, limit: usize) -> Result<usize> {
+    let body = &data[header.body_offset..];
+    match header.compress {
+        UNCOMPRESS => {
+            writer.write_all(body)?;
+            Ok(body.len())
+        }
+        LZ4_FRAME => {
+            let mut decoder = Decoder::new(Cursor::new(body))?;
+            let size = if limit > 0 {
+                io::copy(&mut (&mut decoder).take(limit as u64 + 1), writer)?
+            } else {
+                io::copy(&mut decoder, writer)?
+            };
+            Ok(size as usize)
+        }
+        _ => {
+            let vec = uncompress_rpc_body(header, data, limit)?;
+            writer.write_all(&vec)?;
+            Ok(vec.len())
+        }
+    }
+}
+
 pub fn encode(msg: Vec<u8>) -> Vec<u8> {
     let  mut msg = msg;
     //let msg_size = msg.len();
@@ -0,0 +1,79 @@
+#![feature(fnbox)]
+
+extern crate mqtt;
+extern crate net;
+extern crate pi_lib;
+
+use std::boxed::FnBox;
+use std::time::Duration;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::channel;
+
+use mqtt::client::{ClientNode, PingPolicy};
+use mqtt::data::Client;
+use net::{Socket, Stream};
+use net::timer::{NetTimers, TimerCallback};
+use pi_lib::atom::Atom;
+
+//立即触发ping定时器，不等待定时器到期
+fn tick(timers: &Arc<RwLock<NetTimers<TimerCallback>>>) {
+    let src = Atom::from("client_ping");
+    let func = timers.write().unwrap().cancel_timeout(src.clone()).unwrap();
+    func.call_box((src,));
+}
+
+#[test]
+fn test_ping_policy_interval() {
+    //每个未回应的ping包使间隔减半，不小于最小间隔
+    let policy = PingPolicy { max_missed: 2, min_interval: 200 };
+    assert_eq!(policy.interval(1, 0), Duration::from_millis(1000));
+    assert_eq!(policy.interval(1, 1), Duration::from_millis(500));
+    assert_eq!(policy.interval(1, 2), Duration::from_millis(250));
+    assert_eq!(policy.interval(1, 3), Duration::from_millis(200));
+
+    //不检查回应时固定间隔
+    let policy = PingPolicy::default();
+    assert_eq!(policy.interval(1, 2), Duration::from_millis(1000));
+}
+
+#[test]
+fn test_ping_policy_escalate() {
+    let (sender, receiver) = channel();
+    let socket = Socket {
+        socket: 0,
+        sender: sender,
+        gray: None,
+    };
+    let timers = Arc::new(RwLock::new(NetTimers::new()));
+    let stream = Arc::new(RwLock::new(Stream::new(0, Arc::new(RwLock::new(Vec::new())), timers.clone())));
+
+    let client = ClientNode::new();
+    client.set_stream(socket, stream);
+    let (dead_sender, dead_receiver) = channel();
+    let dead_sender = Mutex::new(dead_sender);
+    client.set_ping_policy(PingPolicy { max_missed: 2, min_interval: 200 }, Some(Arc::new(move || {
+        dead_sender.lock().unwrap().send(()).unwrap();
+    })));
+    client.connect(1, None, None, None);
+    while receiver.try_recv().is_ok() {}
+
+    //发送ping，未收到回应
+    tick(&timers);
+    assert_eq!(client.get_missed_pongs(), 1);
+    assert!(receiver.try_recv().is_ok());
+    tick(&timers);
+    assert_eq!(client.get_missed_pongs(), 2);
+    assert!(receiver.try_recv().is_ok());
+    assert!(dead_receiver.try_recv().is_err());
+
+    //超过阈值，连接失效，关闭连接并停止ping
+    tick(&timers);
+    assert!(dead_receiver.try_recv().is_ok());
+    assert!(receiver.try_recv().is_ok());
+    assert!(!timers.read().unwrap().contains(&Atom::from("client_ping")));
+
+    //重新连接时重置未回应的ping包数量
+    client.connect(1, None, None, None);
+    assert_eq!(client.get_missed_pongs(), 0);
+    assert!(timers.read().unwrap().contains(&Atom::from("client_ping")));
+}
@@ -0,0 +1,252 @@
+extern crate lz4;
+extern crate mqtt;
+
+use std::io::{ErrorKind, Write};
+
+use lz4::EncoderBuilder;
+
+use mqtt::util::{self, RpcHeader};
+
+#[test]
+fn test_rpc_header_uncompress() {
+    let mut data = util::encode_rpc_header(util::UNCOMPRESS, 0x01020304, 10, 5);
+    data.extend_from_slice(b"hello");
+
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert_eq!(header, RpcHeader {
+        compress: util::UNCOMPRESS,
+        vsn: 0,
+        msg_id: 0x01020304,
+        timeout: 10,
+        raw_size: None,
+        body_offset: util::RPC_HEADER_SIZE + util::RPC_FLAGS_LEN,
+        ext: true,
+        batch: false,
+        encrypted: false,
+    });
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), b"hello".to_vec());
+}
+
+#[test]
+fn test_rpc_header_legacy() {
+    let msg = vec![7u8; 1024];
+
+    //基础布局与旧版本的消息相同，只有固定头
+    let data = util::encode_rpc_legacy_frame(0x01020304, 10, b"hello".to_vec(), false);
+    assert_eq!(data, vec![0, 1, 2, 3, 4, 10, b'h', b'e', b'l', b'l', b'o']);
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert!(!header.ext);
+    assert_eq!(header.body_offset, util::RPC_HEADER_SIZE);
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), b"hello".to_vec());
+
+    //基础布局的LZ4_BLOCK没有解压后长度，压缩结果直接在固定头之后
+    let data = util::encode_rpc_legacy_frame(7, 0, msg.clone(), true);
+    assert_eq!(data[0], util::LZ4_BLOCK << 6);
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert_eq!(header.compress, util::LZ4_BLOCK);
+    assert!(!header.ext);
+    assert_eq!(header.raw_size, None);
+    assert_eq!(header.body_offset, util::RPC_HEADER_SIZE);
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), msg);
+}
+
+#[test]
+fn test_rpc_header_encrypted() {
+    //加密标记在扩展布局的消息标记中
+    let data = util::encode_rpc_encrypted_frame(0x01020304, 10, b"hello".to_vec(), false);
+    assert_eq!(data[util::RPC_HEADER_SIZE], util::RPC_FLAG_ENCRYPT);
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert!(header.ext);
+    assert!(header.encrypted);
+    assert!(!header.batch);
+    assert_eq!(header.vsn, 0);
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), b"hello".to_vec());
+
+    //加密后压缩
+    let msg = vec![7u8; 1024];
+    let data = util::encode_rpc_encrypted_frame(7, 0, msg.clone(), true);
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert!(header.encrypted);
+    assert_eq!(header.compress, util::LZ4_BLOCK);
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), msg);
+
+    //基础布局的5位消息版本不会被当作加密标记
+    let data = vec![0b11111, 0, 0, 0, 1, 10, b'h', b'i'];
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert!(!header.ext);
+    assert!(!header.encrypted);
+    assert_eq!(header.vsn, 0b11111);
+    assert_eq!(header.body_offset, util::RPC_HEADER_SIZE);
+}
+
+#[test]
+fn test_rpc_header_lz4_block() {
+    let data = util::encode_rpc_header(util::LZ4_BLOCK, 7, 0, 1024);
+
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert_eq!(header.compress, util::LZ4_BLOCK);
+    assert_eq!(header.raw_size, Some(1024));
+    assert_eq!(header.body_offset, util::RPC_HEADER_SIZE + util::RPC_FLAGS_LEN + util::RPC_RAW_SIZE_LEN);
+}
+
+#[test]
+fn test_rpc_header_lz4_frame() {
+    let raw = vec![7u8; 1024];
+    let mut encoder = EncoderBuilder::new().build(Vec::new()).unwrap();
+    encoder.write_all(&raw).unwrap();
+    let (frame, r) = encoder.finish();
+    r.unwrap();
+
+    //压缩算法只由消息头决定，两种布局都可以使用LZ4_FRAME
+    let headers = vec![
+        (util::encode_rpc_header(util::LZ4_FRAME, 7, 0, 0), util::RPC_HEADER_SIZE + util::RPC_FLAGS_LEN),
+        (util::encode_rpc_legacy_header(util::LZ4_FRAME, 7, 0), util::RPC_HEADER_SIZE),
+    ];
+    for (mut data, offset) in headers {
+        data.extend_from_slice(&frame);
+
+        let header = util::parse_rpc_header(&data).unwrap();
+        assert_eq!(header.compress, util::LZ4_FRAME);
+        assert_eq!(header.body_offset, offset);
+        assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), raw);
+        //限制解压长度
+        assert_eq!(util::uncompress_rpc_body(&header, &data, 100).unwrap().len(), 101);
+    }
+
+    //基础布局的LZ4_BLOCK消息体即使以帧魔数开始，也不会识别为LZ4_FRAME
+    let mut data = util::encode_rpc_legacy_header(util::LZ4_BLOCK, 7, 0);
+    data.extend_from_slice(&frame);
+    assert_eq!(util::parse_rpc_header(&data).unwrap().compress, util::LZ4_BLOCK);
+}
+
+#[test]
+fn test_compress_rpc_frame() {
+    //多个LZ4块，压缩结果直接追加在消息头之后
+    let raw: Vec<u8> = (0..200000u32).map(|i| (i % 251) as u8).collect();
+    let mut data = util::encode_rpc_header(util::LZ4_FRAME, 9, 5, raw.len());
+    util::compress_rpc_frame(&mut data, &raw).unwrap();
+    assert!(data.len() < raw.len());
+
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert_eq!(header.compress, util::LZ4_FRAME);
+    assert_eq!(header.msg_id, 9);
+    assert_eq!(header.timeout, 5);
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), raw);
+}
+
+#[test]
+fn test_uncompress_rpc_body_to() {
+    let raw: Vec<u8> = (0..200000u32).map(|i| (i % 251) as u8).collect();
+    let mut data = util::encode_rpc_header(util::LZ4_FRAME, 9, 5, raw.len());
+    util::compress_rpc_frame(&mut data, &raw).unwrap();
+    let header = util::parse_rpc_header(&data).unwrap();
+
+    let mut out = Vec::new();
+    assert_eq!(util::uncompress_rpc_body_to(&header, &data, &mut out, 0).unwrap(), raw.len());
+    assert_eq!(out, raw);
+
+    //超过限制时最多写入limit + 1字节
+    let mut out = Vec::new();
+    assert_eq!(util::uncompress_rpc_body_to(&header, &data, &mut out, 1000).unwrap(), 1001);
+    assert_eq!(&out[..], &raw[..1001]);
+
+    let mut data = util::encode_rpc_header(util::UNCOMPRESS, 1, 0, 5);
+    data.extend_from_slice(b"hello");
+    let header = util::parse_rpc_header(&data).unwrap();
+    let mut out = Vec::new();
+    assert_eq!(util::uncompress_rpc_body_to(&header, &data, &mut out, 0).unwrap(), 5);
+    assert_eq!(out, b"hello".to_vec());
+}
+
+#[test]
+fn test_rpc_header_invalid() {
+    assert!(util::parse_rpc_header(&[0, 0, 0]).is_err());
+    //扩展布局缺少消息标记或解压后长度
+    assert!(util::parse_rpc_header(&[util::RPC_EXT, 0, 0, 0, 1, 0]).is_err());
+    assert!(util::parse_rpc_header(&[(util::LZ4_BLOCK << 6) | util::RPC_EXT, 0, 0, 0, 1, 0, 0]).is_err());
+    assert!(util::parse_rpc_header(&[3 << 6, 0, 0, 0, 1, 0]).is_err());
+}
+
+#[test]
+fn test_rpc_batch() {
+    let mut first = util::encode_rpc_header(util::UNCOMPRESS, 1, 10, 5);
+    first.extend_from_slice(b"hello");
+    let second = util::encode_rpc_header(util::UNCOMPRESS, 2, 0, 0);
+    let batch = util::encode_rpc_batch(&[first.clone(), second.clone()]);
+
+    let mut data = util::encode_rpc_batch_header(util::UNCOMPRESS, batch.len());
+    data.extend_from_slice(&batch);
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert!(header.batch);
+    assert_eq!(header.compress, util::UNCOMPRESS);
+    assert_eq!(header.vsn, 0);
+    assert_eq!(header.msg_id, 0);
+
+    let body = util::uncompress_rpc_body(&header, &data, 0).unwrap();
+    assert_eq!(util::decode_rpc_batch(&body).unwrap(), vec![first.as_slice(), second.as_slice()]);
+
+    //截断的批量消息
+    assert!(util::decode_rpc_batch(&body[..body.len() - 1]).is_err());
+}
+
+#[test]
+fn test_dump_rpc_frame() {
+    let mut data = util::encode_rpc_header(util::UNCOMPRESS, 0x01020304, 10, 2);
+    data.extend_from_slice(b"hi");
+
+    let dump = util::dump_rpc_frame(&data);
+    assert!(dump.contains("[0] 20: compress: 0 (uncompress), ext: true, vsn: 0\n"));
+    assert!(dump.contains("[1..5] 01 02 03 04: msg_id: 16909060\n"));
+    assert!(dump.contains("[5] 0a: timeout: 10\n"));
+    assert!(dump.contains("[6] 00: batch: false, encrypted: false\n"));
+    assert!(dump.contains("[7..9] body: 2 bytes\n"));
+    assert!(dump.ends_with("00000000  20 01 02 03 04 0a 00 68 69\n"));
+
+    let dump = util::dump_rpc_frame(&util::encode_rpc_header(util::LZ4_BLOCK, 1, 0, 2));
+    assert!(dump.contains("[7..11] 00 00 00 02: raw_size: 2\n"));
+
+    assert!(util::dump_rpc_frame(&[0]).starts_with("invalid header: "));
+}
+
+#[test]
+fn test_encode_rpc_frame() {
+    let msg = vec![7u8; 1024];
+
+    let data = util::encode_rpc_frame(0x01020304, 10, msg.clone(), false);
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert_eq!(header.compress, util::UNCOMPRESS);
+    assert_eq!(header.msg_id, 0x01020304);
+    assert_eq!(header.timeout, 10);
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), msg);
+
+    let data = util::encode_rpc_frame(0x01020304, 10, msg.clone(), true);
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert_eq!(header.compress, util::LZ4_BLOCK);
+    assert_eq!(header.msg_id, 0x01020304);
+    assert_eq!(header.timeout, 10);
+    assert!(data.len() < msg.len());
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), msg);
+}
+
+#[test]
+fn test_rpc_lz4_block_limit() {
+    let msg = vec![7u8; 1024];
+    let data = util::encode_rpc_frame(1, 0, msg.clone(), true);
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert_eq!(header.raw_size, Some(1024));
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 0).unwrap(), msg);
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 1024).unwrap(), msg);
+
+    //解压后长度超限时不解压
+    let e = util::uncompress_rpc_body(&header, &data, 1023).err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    let mut out = Vec::new();
+    assert!(util::uncompress_rpc_body_to(&header, &data, &mut out, 1023).is_err());
+    assert!(out.is_empty());
+
+    //伪造的解压后长度
+    let mut data = util::encode_rpc_header(util::LZ4_BLOCK, 1, 0, u32::max_value() as usize);
+    data.extend_from_slice(&[0; 16]);
+    let header = util::parse_rpc_header(&data).unwrap();
+    assert_eq!(util::uncompress_rpc_body(&header, &data, 1024).err().unwrap().kind(), ErrorKind::InvalidData);
+}
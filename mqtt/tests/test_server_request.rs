@@ -0,0 +1,72 @@
+extern crate mqtt;
+extern crate mqtt3;
+extern crate net;
+extern crate pi_lib;
+
+use std::io::Result;
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc::channel;
+
+use mqtt::data::Server;
+use mqtt::server::{ClientStub, ServerNode, DEFAULT_MAX_REQUEST_SIZE};
+use mqtt::util;
+use net::{Socket, Stream};
+use net::timer::NetTimers;
+use pi_lib::atom::Atom;
+
+#[test]
+fn test_server_max_request_size() {
+    let (sender, _receiver) = channel();
+    let socket = Socket {
+        socket: 0,
+        sender: sender,
+        gray: None,
+    };
+    let timers = Arc::new(RwLock::new(NetTimers::new()));
+    let stream = Arc::new(RwLock::new(Stream::new(0, Arc::new(RwLock::new(Vec::new())), timers.clone())));
+
+    let server = ServerNode::new();
+    assert_eq!(server.get_rpc_max_request_size(), DEFAULT_MAX_REQUEST_SIZE);
+    server.set_rpc_max_request_size(100);
+
+    //回调参数为消息ID、超时时长和消息体
+    let (req_sender, req_receiver) = channel();
+    server.set_topic_meta(Atom::from("test"), true, true, Box::new(move |_client: ClientStub, r: Result<Arc<Vec<u8>>>| {
+        req_sender.send(r.unwrap().len() - (util::RPC_HEADER_SIZE - 1)).unwrap();
+    })).unwrap();
+    server.handle_connect(&socket, stream, mqtt3::Connect {
+        protocol: mqtt3::Protocol::MQTT(4),
+        keep_alive: 0,
+        client_id: String::from("test"),
+        clean_session: true,
+        last_will: None,
+        username: None,
+        password: None,
+    });
+
+    //未超限的请求正常回调
+    server.handle_publish(&socket, "test", util::encode_rpc_frame(1, 0, vec![7; 100], true));
+    assert_eq!(req_receiver.try_recv().unwrap(), 100);
+
+    //扩展布局LZ4_BLOCK按消息头中的解压后长度检查，伪造的长度在分配缓冲区前丢弃
+    let mut buff = util::encode_rpc_header(util::LZ4_BLOCK, 2, 0, 0xffffffff);
+    buff.extend_from_slice(&[0; 16]);
+    server.handle_publish(&socket, "test", buff);
+    assert!(req_receiver.try_recv().is_err());
+    server.handle_publish(&socket, "test", util::encode_rpc_frame(3, 0, vec![7; 101], true));
+    assert!(req_receiver.try_recv().is_err());
+
+    //旧布局LZ4_BLOCK没有解压后长度，解压后检查
+    server.handle_publish(&socket, "test", util::encode_rpc_legacy_frame(4, 0, vec![7; 101], true));
+    assert!(req_receiver.try_recv().is_err());
+    server.handle_publish(&socket, "test", util::encode_rpc_legacy_frame(5, 0, vec![7; 100], true));
+    assert_eq!(req_receiver.try_recv().unwrap(), 100);
+
+    //LZ4_FRAME最多只解压limit + 1字节，不压缩的请求同样检查
+    let mut buff = util::encode_rpc_header(util::LZ4_FRAME, 6, 0, 0);
+    util::compress_rpc_frame(&mut buff, &vec![7; 100000]).unwrap();
+    server.handle_publish(&socket, "test", buff);
+    assert!(req_receiver.try_recv().is_err());
+    server.handle_publish(&socket, "test", util::encode_rpc_frame(7, 0, vec![7; 101], false));
+    assert!(req_receiver.try_recv().is_err());
+}
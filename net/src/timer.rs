@@ -69,6 +69,10 @@ impl NetTimers<TimerCallback> {
         self.timers.remove(&src);
         v
     }
+    //判断定时器是否存在
+    pub fn contains(&self, src: &Atom) -> bool {
+        self.timers.contains_key(src)
+    }
     //mio中轮训，到期开新进程触发回调
     pub fn poll(&mut self) {
         let mut vec = Vec::new();
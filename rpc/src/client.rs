@@ -2,92 +2,595 @@ use std::io::Result;
 /**
  * RPC传输协议：
  * 消息体：1字节表示压缩和版本,4字节消息ID，1字节超时时长（0表示不超时), 剩下的BonBuffer ,
- * 第一字节：前3位表示压缩算法，后5位表示版本（灰度）
- * 压缩算法：0：不压缩，1：rsync, 2:LZ4 BLOCK, 3:LZ4 SEREAM, 4、5、6、7预留
+ * 第一字节：前2位表示压缩算法，后5位表示版本（灰度）
+ * 压缩算法：0：不压缩，1：LZ4 BLOCK, 2:LZ4 FRAME, 3预留
+ * 第一字节的第3位表示扩展布局，扩展布局在超时时长后有1字节消息标记，LZ4 BLOCK时消息标记后有4字节解压后长度
+ * 只有握手确认服务器支持扩展布局后才发送扩展布局的消息，否则发送与旧版本兼容的基础布局
+ * 消息标记的第1位表示批量消息，批量消息的消息体解压后由多条不压缩的消息组成，每条消息前有4字节长度
+ * 消息标记的第2位表示消息体已加密，消息体在压缩前加密，解压后解密，加密的消息只使用扩展布局
  */
+use std::io::{self, Error, ErrorKind, Write};
+use std::boxed::FnBox;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use std::collections::VecDeque;
 
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHashSet};
+use futures::Future;
+use futures::sync::oneshot;
 use pi_lib::atom::Atom;
 
 use mqtt3;
 use mqtt3::LastWill;
 
-use mqtt::client::ClientNode;
+use mqtt::client::{ClientNode, UnhandledCallback};
 use mqtt::data::{Client, ClientCallback};
 use mqtt::util;
+pub use mqtt::util::RpcHeader;
 
 use net::{Socket, Stream};
 use net::timer::{NetTimers, TimerCallback};
 
-use pi_base::util::{compress, uncompress, CompressLevel};
-use traits::RPCClientTraits;
+use pi_base::util::{compress, CompressLevel};
+use traits::{RPCClientTraits, RPCCipher};
+
+//默认的最大回应大小，解压后，单位字节
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+//默认的回应主题，服务器直接发送，不需要订阅
+pub const DEFAULT_RESPONSE_TOPIC: &str = "$r";
+//默认的请求压缩阈值，消息大于阈值时压缩，单位字节
+pub const DEFAULT_COMPRESS_THRESHOLD: usize = 64;
+//默认的重复回应检测时长，请求完成后在此时长内到达的同一消息ID的回应作为重复回应忽略，单位毫秒
+pub const DEFAULT_DUPLICATE_WINDOW: u64 = 10000;
+//默认的幂等请求最大重发次数，超过后请求在连接断开时失败，避免无限重发
+pub const DEFAULT_MAX_REPLAYS: usize = 3;
+//握手主题，连接成功后通过该主题交换双方支持的压缩算法集合
+pub const HANDSHAKE_TOPIC: &str = "$h";
+//握手请求的超时时长，单位秒，超时后保持只使用不压缩和基础布局
+pub const HANDSHAKE_TIMEOUT: u8 = 10;
+//握手超时的net定时器名
+pub const HANDSHAKE_TIMER: &str = "rpc_handshake";
+
+/*
+* 单次请求的压缩策略
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressPolicy {
+    Default,            //使用客户端的压缩阈值
+    Never,              //不压缩
+    Always,             //总是压缩
+    Threshold(usize),   //消息大于指定阈值时压缩
+}
+
+/*
+* 回应统计
+*/
+#[derive(Debug, Clone, Default)]
+pub struct RPCResponseMetrics {
+    pub count: usize,    //已接收的回应数量
+    pub total: usize,    //已接收的回应总大小，解压后
+    pub max: usize,      //已接收的最大回应大小，解压后
+    pub oversize: usize, //因超过最大回应大小而拒绝的回应数量
+    pub duplicate: usize,//被忽略的重复回应数量
+    pub replayed: usize, //重新连接后重发的请求数量
+}
+
+/*
+* 孤立回应的处理策略，孤立回应指找不到对应请求的回应，一般是请求超时后到达的回应
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrphanPolicy {
+    Close,  //关闭连接
+    Ignore, //忽略回应，保持连接
+}
+
+//孤立回应回调，参数为消息ID和回应
+pub type OrphanHandler = Arc<Fn(u32, Result<Arc<Vec<u8>>>) + Send + Sync>;
+
+//带消息头的回应回调，可以获取回应的压缩算法和版本等信息
+pub type RPCResponseHandler = Box<Fn(Result<(RpcHeader, Arc<Vec<u8>>)>) + Send>;
+
+//写入writer的回应回调，回调回应的消息头和写入的字节数
+pub type RPCWriterHandler = Box<Fn(Result<(RpcHeader, usize)>) + Send>;
+
+//流式回应的批量回调，一次回调多个回应帧
+pub type RPCStreamHandler = Arc<Fn(Result<Vec<Arc<Vec<u8>>>>) + Send + Sync>;
+
+/*
+* 等待回应的幂等请求，保存已编码的消息，重新连接后使用同一消息ID原样重发
+*/
+struct Replay {
+    topic: Atom,        //请求主题
+    frame: Vec<u8>,     //已编码的请求消息
+    lost: bool,         //是否因连接断开而等待重发
+    count: usize,       //已重发的次数
+}
+
+/*
+* 流式回应的批量选项，满足任一条件即回调，默认每帧回调一次
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamBatch {
+    pub size: usize,    //批量的最大帧数，为0或1表示每帧回调
+    pub window: u64,    //批量的最大等待时长，从批量的第一帧开始计算，单位毫秒，为0表示不限制
+}
+
+impl Default for StreamBatch {
+    fn default() -> Self {
+        StreamBatch {
+            size: 1,
+            window: 0,
+        }
+    }
+}
+
+//将超时时长转换为协议的超时字节，单位秒，不足1秒的部分向上取整，避免被截断为不超时
+pub fn encode_timeout(timeout: Duration) -> Result<u8> {
+    let mut secs = timeout.as_secs();
+    if timeout.subsec_nanos() > 0 {
+        secs += 1;
+    }
+    if secs > u8::max_value() as u64 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("rpc timeout too large, timeout: {:?}, max: {}s", timeout, u8::max_value()),
+        ));
+    }
+    Ok(secs as u8)
+}
+
+/*
+* 最近完成的请求的消息ID，用于检测重复回应
+*/
+struct CompletedIds {
+    window: u64,                        //检测时长，单位毫秒，为0表示不检测
+    order: VecDeque<(u32, Instant)>,    //按完成时间排序的消息ID
+    ids: FnvHashSet<u32>,               //消息ID集合
+}
+
+impl CompletedIds {
+    fn new(window: u64) -> Self {
+        CompletedIds {
+            window,
+            order: VecDeque::new(),
+            ids: FnvHashSet::default(),
+        }
+    }
+
+    //记录已完成的消息ID
+    fn insert(&mut self, msg_id: u32) {
+        self.expire();
+        if self.window > 0 && self.ids.insert(msg_id) {
+            self.order.push_back((msg_id, Instant::now()));
+        }
+    }
+
+    //判断消息ID是否在检测时长内完成
+    fn contains(&mut self, msg_id: u32) -> bool {
+        self.expire();
+        self.ids.contains(&msg_id)
+    }
+
+    //移除超过检测时长的消息ID
+    fn expire(&mut self) {
+        let window = Duration::from_millis(self.window);
+        while let Some(&(msg_id, time)) = self.order.front() {
+            if time.elapsed() < window {
+                break;
+            }
+            self.order.pop_front();
+            self.ids.remove(&msg_id);
+        }
+    }
+}
+
+/*
+* 流式回应的缓冲区
+*/
+struct StreamBuffer {
+    timers: Arc<RwLock<NetTimers<TimerCallback>>>,  //连接的net定时器，用于批量等待
+    timer: Atom,                                    //批量等待定时器的名称
+    batch: StreamBatch,
+    frames: Mutex<(usize, Vec<Arc<Vec<u8>>>)>,  //当前批量的序号和已缓冲的回应帧
+    handler: RPCStreamHandler,
+}
+
+impl StreamBuffer {
+    fn new(timers: Arc<RwLock<NetTimers<TimerCallback>>>, msg_id: u32, batch: StreamBatch, handler: RPCStreamHandler) -> Self {
+        StreamBuffer {
+            timers,
+            timer: Atom::from(format!("rpc_stream_{}", msg_id)),
+            batch,
+            frames: Mutex::new((0, Vec::new())),
+            handler,
+        }
+    }
+
+    //缓冲回应帧，批量已满时回调，批量的第一帧时通过net定时器开始等待
+    fn push(buffer: &Arc<StreamBuffer>, frame: Arc<Vec<u8>>) {
+        let (frames, wait) = {
+            let mut frames = buffer.frames.lock().unwrap();
+            frames.1.push(frame);
+            if frames.1.len() >= buffer.batch.size {
+                frames.0 += 1;
+                (Some(frames.1.drain(..).collect::<Vec<_>>()), None)
+            } else if frames.1.len() == 1 && buffer.batch.window > 0 {
+                (None, Some(frames.0))
+            } else {
+                (None, None)
+            }
+        };
+
+        if let Some(frames) = frames {
+            buffer.cancel_timer();
+            (buffer.handler)(Ok(frames));
+        }
+        if let Some(index) = wait {
+            let copy = buffer.clone();
+            buffer.timers.write().unwrap().set_timeout(
+                buffer.timer.clone(),
+                Duration::from_millis(buffer.batch.window),
+                Box::new(move |_src: Atom| {
+                    copy.flush(Some(index));
+                }),
+            );
+        }
+    }
+
+    //取消批量等待定时器，批量已回调时不需要再等待
+    fn cancel_timer(&self) {
+        if self.batch.window > 0 {
+            self.timers.write().unwrap().cancel_timeout(self.timer.clone());
+        }
+    }
+
+    //立即回调已缓冲的回应帧，指定批量序号时只回调该批量，批量已回调则忽略
+    fn flush(&self, index: Option<usize>) {
+        let frames = {
+            let mut frames = self.frames.lock().unwrap();
+            match index {
+                Some(index) if index != frames.0 => return,
+                _ => (),
+            }
+            frames.0 += 1;
+            frames.1.drain(..).collect::<Vec<_>>()
+        };
+        if index.is_none() {
+            self.cancel_timer();
+        }
+        if frames.len() > 0 {
+            (self.handler)(Ok(frames));
+        }
+    }
+}
+
+/*
+* ping定时器守卫，最后一个客户端释放时停止ping，定时器回调只持有mqtt客户端，不会阻止守卫释放
+*/
+struct PingGuard(ClientNode);
+
+impl Drop for PingGuard {
+    fn drop(&mut self) {
+        self.0.stop_ping();
+    }
+}
 
 #[derive(Clone)]
 pub struct RPCClient {
     mqtt: ClientNode,
-    msg_id: Arc<Mutex<u32>>,
-    handlers: Arc<Mutex<FnvHashMap<u32, Box<Fn(Result<Arc<Vec<u8>>>)>>>>,
+    _ping_guard: Arc<PingGuard>,    //只用于最后一个客户端释放时停止ping
+    msg_id: Arc<AtomicUsize>,
+    handlers: Arc<Mutex<FnvHashMap<u32, RPCResponseHandler>>>,
+    streams: Arc<Mutex<FnvHashMap<u32, Arc<StreamBuffer>>>>,
+    writers: Arc<Mutex<FnvHashMap<u32, (Box<Write + Send>, RPCWriterHandler)>>>,
+    max_response_size: Arc<AtomicUsize>,
+    compress_threshold: Arc<AtomicUsize>,
+    frame_compress: Arc<AtomicBool>,
+    metrics: Arc<Mutex<RPCResponseMetrics>>,
+    orphan_policy: Arc<Mutex<OrphanPolicy>>,
+    orphan_handler: Arc<Mutex<Option<OrphanHandler>>>,
+    completed: Arc<Mutex<CompletedIds>>,
+    replays: Arc<Mutex<FnvHashMap<u32, Replay>>>,
+    max_replays: Arc<AtomicUsize>,
+    cipher: Arc<RwLock<Option<Arc<RPCCipher>>>>,
+    compress_modes: Arc<AtomicUsize>,   //与服务器协商的压缩算法集合
 }
 
-unsafe impl Sync for RPCClient {}
-unsafe impl Send for RPCClient {}
-
 impl RPCClient {
     pub fn new(mqtt: ClientNode) -> Self {
         RPCClient {
+            _ping_guard: Arc::new(PingGuard(mqtt.clone())),
             mqtt,
-            msg_id: Arc::new(Mutex::new(0)),
+            msg_id: Arc::new(AtomicUsize::new(0)),
             handlers: Arc::new(Mutex::new(FnvHashMap::default())),
+            streams: Arc::new(Mutex::new(FnvHashMap::default())),
+            writers: Arc::new(Mutex::new(FnvHashMap::default())),
+            max_response_size: Arc::new(AtomicUsize::new(DEFAULT_MAX_RESPONSE_SIZE)),
+            compress_threshold: Arc::new(AtomicUsize::new(DEFAULT_COMPRESS_THRESHOLD)),
+            frame_compress: Arc::new(AtomicBool::new(false)),
+            metrics: Arc::new(Mutex::new(RPCResponseMetrics::default())),
+            orphan_policy: Arc::new(Mutex::new(OrphanPolicy::Close)),
+            orphan_handler: Arc::new(Mutex::new(None)),
+            completed: Arc::new(Mutex::new(CompletedIds::new(DEFAULT_DUPLICATE_WINDOW))),
+            replays: Arc::new(Mutex::new(FnvHashMap::default())),
+            max_replays: Arc::new(AtomicUsize::new(DEFAULT_MAX_REPLAYS)),
+            cipher: Arc::new(RwLock::new(None)),
+            compress_modes: Arc::new(AtomicUsize::new(util::RPC_COMPRESS_MODES as usize)),
         }
     }
+
+    //设置孤立回应的处理策略和回调，回调在执行策略前调用
+    pub fn set_orphan_handler(&self, policy: OrphanPolicy, handler: Option<OrphanHandler>) {
+        *self.orphan_policy.lock().unwrap() = policy;
+        *self.orphan_handler.lock().unwrap() = handler;
+    }
+
+    //获取孤立回应的处理策略
+    pub fn get_orphan_policy(&self) -> OrphanPolicy {
+        *self.orphan_policy.lock().unwrap()
+    }
+
+    //设置重复回应检测时长，单位毫秒，为0表示不检测，重复回应作为孤立回应处理
+    pub fn set_duplicate_window(&self, window: u64) {
+        let mut completed = self.completed.lock().unwrap();
+        completed.window = window;
+        completed.expire();
+    }
+
+    //获取重复回应检测时长
+    pub fn get_duplicate_window(&self) -> u64 {
+        self.completed.lock().unwrap().window
+    }
+
+    //设置最大回应大小，解压后，单位字节，为0表示不限制
+    pub fn set_max_response_size(&self, size: usize) {
+        self.max_response_size.store(size, Ordering::Relaxed);
+    }
+
+    //获取最大回应大小
+    pub fn get_max_response_size(&self) -> usize {
+        self.max_response_size.load(Ordering::Relaxed)
+    }
+
+    //设置请求压缩阈值，消息大于阈值时压缩，单位字节
+    pub fn set_compress_threshold(&self, threshold: usize) {
+        self.compress_threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    //获取请求压缩阈值
+    pub fn get_compress_threshold(&self) -> usize {
+        self.compress_threshold.load(Ordering::Relaxed)
+    }
+
+    //设置压缩请求时是否使用LZ4帧流式压缩，压缩结果直接写入消息，避免缓冲完整的压缩结果，需要服务器支持LZ4_FRAME，不影响批量请求
+    pub fn set_frame_compress(&self, enable: bool) {
+        self.frame_compress.store(enable, Ordering::Relaxed);
+    }
+
+    //获取压缩请求时是否使用LZ4帧流式压缩
+    pub fn get_frame_compress(&self) -> bool {
+        self.frame_compress.load(Ordering::Relaxed)
+    }
+
+    //设置幂等请求的最大重发次数，为0表示不重发
+    pub fn set_max_replays(&self, max: usize) {
+        self.max_replays.store(max, Ordering::Relaxed);
+    }
+
+    //获取幂等请求的最大重发次数
+    pub fn get_max_replays(&self) -> usize {
+        self.max_replays.load(Ordering::Relaxed)
+    }
+
+    //设置请求和回应消息体的加解密，None表示不加密，默认不加密，设置后请求都会加密，加密的回应需要设置后才能解密
+    //服务器需要设置相同的加解密才能处理加密的请求
+    pub fn set_cipher(&self, cipher: Option<Arc<RPCCipher>>) {
+        *self.cipher.write().unwrap() = cipher;
+    }
+
+    //获取请求和回应消息体的加解密
+    pub fn get_cipher(&self) -> Option<Arc<RPCCipher>> {
+        self.cipher.read().unwrap().clone()
+    }
+
+    //获取与服务器协商的压缩算法集合，每个压缩算法占一位，未连接时为本地支持的所有压缩算法
+    pub fn get_compress_modes(&self) -> u8 {
+        self.compress_modes.load(Ordering::Relaxed) as u8 & util::RPC_COMPRESS_MODES
+    }
+
+    //判断是否可以发送扩展布局的消息，只有握手确认服务器支持时才可以，否则发送基础布局的消息
+    pub fn is_ext_layout(&self) -> bool {
+        self.compress_modes.load(Ordering::Relaxed) as u8 & util::RPC_EXT_LAYOUT != 0
+    }
+
+    //判断是否可以使用指定的压缩算法
+    pub fn is_compress_supported(&self, compress: u8) -> bool {
+        self.get_compress_modes() & (1 << compress) != 0
+    }
+
+    //与服务器握手，交换双方支持的压缩算法，握手完成前只发送不压缩的请求，连接成功后自动握手，返回握手请求的消息ID
+    //服务器不支持握手、握手失败或超时时保持不压缩和基础布局，超时后到达的握手回应被忽略
+    pub fn handshake(&self) -> u32 {
+        handshake(&self.mqtt, &self.msg_id, &self.handlers, &self.compress_modes)
+    }
+
+    //获取回应统计
+    pub fn get_response_metrics(&self) -> RPCResponseMetrics {
+        self.metrics.lock().unwrap().clone()
+    }
     pub fn connect(
         &self,
         keep_alive: u16,        //ping-pong
         will: Option<LastWill>, //遗言
         close_func: Option<ClientCallback>,
         connect_func: Option<ClientCallback>,
+    ) {
+        self.connect_with_topics(keep_alive, will, close_func, connect_func, vec![Atom::from(DEFAULT_RESPONSE_TOPIC)])
+    }
+
+    //连接，并从多个回应主题接收回应，所有主题的回应按消息ID分发，主题支持通配符
+    //除默认回应主题外，其它主题需要订阅才能收到回应
+    //重新连接时，之前连接上等待回应的幂等请求在连接成功后重发，其它请求失败
+    pub fn connect_with_topics(
+        &self,
+        keep_alive: u16,        //ping-pong
+        will: Option<LastWill>, //遗言
+        close_func: Option<ClientCallback>,
+        connect_func: Option<ClientCallback>,
+        topics: Vec<Atom>,      //回应主题
     ) {
         println!("rpc client connect!!!!!!!!");
+        self.disconnected();
+        let mqtt = self.mqtt.clone();
+        let replays = self.replays.clone();
+        let metrics = self.metrics.clone();
+        let msg_id = self.msg_id.clone();
+        let handlers = self.handlers.clone();
+        let compress_modes = self.compress_modes.clone();
+        let connect_func: ClientCallback = Box::new(move |r: Result<()>| {
+            if r.is_ok() {
+                handshake(&mqtt, &msg_id, &handlers, &compress_modes);
+                replay(&mqtt, &replays, &metrics);
+            }
+            if let Some(func) = connect_func {
+                func.call_box((r,));
+            }
+        });
         //连接MQTTser
         self.mqtt
-            .connect(keep_alive, will, close_func, connect_func);
+            .connect(keep_alive, will, close_func, Some(connect_func));
         let handlers = self.handlers.clone();
+        let streams = self.streams.clone();
+        let writers = self.writers.clone();
+        let max_response_size = self.max_response_size.clone();
+        let metrics = self.metrics.clone();
+        let orphan_policy = self.orphan_policy.clone();
+        let orphan_handler = self.orphan_handler.clone();
+        let completed = self.completed.clone();
+        let replays = self.replays.clone();
+        let cipher = self.cipher.clone();
         //topic回调方法
         let topic_handle = move |r: Result<(Socket, &[u8])>| {
             let (socket, data) = r.unwrap();
-            let header = data[0];
-            //压缩版本
-            let compress = (&header >> 6) as u8;
-            //消息版本
-            let _vsn = &header & 0b11111;
-            let msg_id = u32::from_be(unsafe { *((data[1..4].as_ptr()) as *mut u32) });
-            let mut rdata = Vec::new();
-            match compress {
-                util::UNCOMPRESS => rdata.extend_from_slice(&data[6..]),
-                util::LZ4_BLOCK => {
-                    let mut vec_ = Vec::new();
-                    uncompress(&data[6..], &mut vec_).is_ok();
-                    rdata.extend_from_slice(&vec_[..]);
+            let header = match util::parse_rpc_header(data) {
+                Ok(header) => header,
+                Err(e) => {
+                    println!("!!!> Rpc Client Invalid Response, reason: {:?}", e);
+                    socket.close(true);
+                    return;
+                }
+            };
+            let msg_id = header.msg_id;
+            let max = max_response_size.load(Ordering::Relaxed);
+            let oversize = |size: usize| {
+                metrics.lock().unwrap().oversize += 1;
+                Error::new(
+                    ErrorKind::Other,
+                    format!("rpc response too large, size: {}, max: {}", size, max),
+                )
+            };
+            //解压前先检查已知的回应大小，避免分配超限的缓冲区
+            let size = match header.compress {
+                util::UNCOMPRESS => Some(data.len() - header.body_offset),
+                _ => header.raw_size,
+            };
+            //写入writer的回应直接解压到writer，不构建回应缓冲区
+            let sink = writers.lock().unwrap().remove(&msg_id);
+            if let Some((mut writer, func)) = sink {
+                completed.lock().unwrap().insert(msg_id);
+                let r = match size {
+                    Some(size) if max > 0 && size > max => Err(oversize(size)),
+                    //加密的回应只能整体解密，解密后再写入writer
+                    _ if header.encrypted => match util::uncompress_rpc_body(&header, data, max) {
+                        Ok(ref rdata) if max > 0 && rdata.len() > max => Err(oversize(rdata.len())),
+                        Ok(rdata) => decrypt_body(&cipher, &header, rdata).and_then(|rdata| {
+                            writer.write_all(&rdata)?;
+                            record_response(&metrics, rdata.len());
+                            Ok(rdata.len())
+                        }),
+                        Err(e) => Err(e),
+                    },
+                    _ => match util::uncompress_rpc_body_to(&header, data, &mut *writer, max) {
+                        Ok(len) if max > 0 && len > max => Err(oversize(len)),
+                        Ok(len) => {
+                            record_response(&metrics, len);
+                            Ok(len)
+                        }
+                        Err(e) => Err(e),
+                    },
+                };
+                func(r.map(|len| (header, len)));
+                return;
+            }
+            let r = match size {
+                Some(size) if max > 0 && size > max => Err(oversize(size)),
+                _ => match util::uncompress_rpc_body(&header, data, max) {
+                    Ok(ref rdata) if max > 0 && rdata.len() > max => Err(oversize(rdata.len())),
+                    Ok(rdata) => decrypt_body(&cipher, &header, rdata).map(|rdata| {
+                        record_response(&metrics, rdata.len());
+                        Arc::new(rdata)
+                    }),
+                    Err(e) => Err(e),
+                },
+            };
+            //流式回应保留回调，直到结束流
+            let stream = streams.lock().unwrap().get(&msg_id).cloned();
+            if let Some(stream) = stream {
+                match r {
+                    Ok(rdata) => StreamBuffer::push(&stream, rdata),
+                    Err(e) => {
+                        stream.flush(None);
+                        (stream.handler)(Err(e));
+                    }
                 }
-                _ => socket.close(true),
+                return;
             }
-            let rdata = Arc::new(rdata);
-            let mut handlers = handlers.lock().unwrap();
-            match handlers.get(&msg_id) {
+            //先移除回调再调用，避免回调中再次请求时死锁
+            let func = handlers.lock().unwrap().remove(&msg_id);
+            match func {
                 Some(func) => {
-                    func(Ok(rdata));
+                    replays.lock().unwrap().remove(&msg_id);
+                    completed.lock().unwrap().insert(msg_id);
+                    func(r.map(|rdata| (header, rdata)));
+                }
+                None if completed.lock().unwrap().contains(msg_id) => {
+                    //服务器重复发送的回应，忽略并保持连接
+                    println!("!!!> Rpc Client Duplicate Response, msg_id: {}", msg_id);
+                    metrics.lock().unwrap().duplicate += 1;
+                }
+                None => {
+                    //先复制回调和策略再调用，回调中可以重新设置孤立回应的处理策略和回调，新的设置从下一个孤立回应开始生效
+                    let handler = orphan_handler.lock().unwrap().clone();
+                    let policy = *orphan_policy.lock().unwrap();
+                    if let Some(handler) = handler {
+                        handler(msg_id, r);
+                    }
+                    if policy == OrphanPolicy::Close {
+                        socket.close(true);
+                    }
                 }
-                None => socket.close(true),
             };
-            handlers.remove(&msg_id);
         };
-        self.mqtt
-            .set_topic_handler(
-                Atom::from(String::from("$r").as_str()),
-                Box::new(move |r| topic_handle(r)),
-            )
-            .is_ok();
+        let topic_handle = Arc::new(topic_handle);
+        let mut subscribes = Vec::with_capacity(topics.len());
+        for topic in topics {
+            let handle = topic_handle.clone();
+            self.mqtt
+                .set_topic_handler(
+                    topic.clone(),
+                    Box::new(move |r| handle(r)),
+                )
+                .is_ok();
+            if topic.as_str() != DEFAULT_RESPONSE_TOPIC {
+                subscribes.push(((*topic).clone(), mqtt3::QoS::AtMostOnce));
+            }
+        }
+        if subscribes.len() > 0 {
+            if let Err(e) = self.mqtt.subscribe(subscribes, None) {
+                println!("!!!> Rpc Client Subscribe Response Topics Error, reason: {:?}", e);
+            }
+        }
     }
 
     pub fn set_stream(&self, socket: Socket, stream: Arc<RwLock<Stream>>) {
@@ -105,53 +608,504 @@ impl RPCClient {
     ) -> Result<()> {
         self.mqtt.set_topic_handler(name, handler)
     }
-}
 
-impl RPCClientTraits for RPCClient {
-    fn request(
+    //设置未处理消息回调，回应主题和已设置回调的主题以外的消息会回调，用于记录或转发，None表示忽略
+    //回调在持有mqtt客户端锁时调用，不能在回调中调用会锁定mqtt客户端的方法
+    pub fn set_unhandled_handler(&self, handler: Option<UnhandledCallback>) {
+        self.mqtt.set_unhandled_handler(handler)
+    }
+
+    //取消请求，取消后回应到达时会被忽略，不会作为孤立回应处理，返回请求是否还在等待回应
+    pub fn cancel(&self, msg_id: u32) -> bool {
+        if let Some(stream) = self.streams.lock().unwrap().get_mut(&msg_id) {
+            //丢弃已缓冲的回应帧，之后的回应帧直接忽略
+            {
+                let mut frames = stream.frames.lock().unwrap();
+                frames.0 += 1;
+                frames.1.clear();
+            }
+            stream.cancel_timer();
+            let timers = stream.timers.clone();
+            *stream = Arc::new(StreamBuffer::new(timers, msg_id, StreamBatch::default(), Arc::new(|_| ())));
+            return true;
+        }
+        if let Some(sink) = self.writers.lock().unwrap().get_mut(&msg_id) {
+            *sink = (Box::new(io::sink()), Box::new(|_| ()));
+            return true;
+        }
+        //取消的请求不再重发
+        self.replays.lock().unwrap().remove(&msg_id);
+        match self.handlers.lock().unwrap().get_mut(&msg_id) {
+            Some(handler) => {
+                *handler = Box::new(|_| ());
+                true
+            }
+            None => false,
+        }
+    }
+
+    //连接断开，等待回应的幂等请求在重新连接成功后重发，已达到最大重发次数的幂等请求和其它请求以ErrorKind::ConnectionAborted失败
+    //重新连接时会自动调用，提前调用可以让非幂等请求尽快失败，不影响流式请求
+    pub fn disconnected(&self) {
+        let max = self.get_max_replays();
+        let mut lost = Vec::new();
+        {
+            let mut handlers = self.handlers.lock().unwrap();
+            let mut replays = self.replays.lock().unwrap();
+            let msg_ids: Vec<u32> = handlers.keys().cloned().collect();
+            for msg_id in msg_ids {
+                let exhausted = match replays.get_mut(&msg_id) {
+                    Some(replay) => {
+                        if replay.count < max {
+                            replay.lost = true;
+                            continue;
+                        }
+                        true
+                    }
+                    None => false,
+                };
+                replays.remove(&msg_id);
+                if let Some(handler) = handlers.remove(&msg_id) {
+                    lost.push((msg_id, handler, exhausted));
+                }
+            }
+        }
+
+        let sinks: Vec<(u32, (Box<Write + Send>, RPCWriterHandler))> = self.writers.lock().unwrap().drain().collect();
+        for (msg_id, (_, handler)) in sinks {
+            self.completed.lock().unwrap().insert(msg_id);
+            handler(Err(Error::new(ErrorKind::ConnectionAborted, format!("rpc request lost on disconnect, msg_id: {}", msg_id))));
+        }
+        for (msg_id, handler, exhausted) in lost {
+            //之后到达的旧连接的回应作为重复回应忽略
+            self.completed.lock().unwrap().insert(msg_id);
+            let reason = if exhausted {
+                format!("rpc request replay limit reached, msg_id: {}, max: {}", msg_id, max)
+            } else {
+                format!("rpc request lost on disconnect, msg_id: {}", msg_id)
+            };
+            handler(Err(Error::new(ErrorKind::ConnectionAborted, reason)));
+        }
+    }
+
+    //结束流式请求，立即回调已缓冲的回应帧，之后到达的回应作为孤立回应处理，返回流是否存在
+    pub fn end_stream(&self, msg_id: u32) -> bool {
+        let stream = self.streams.lock().unwrap().remove(&msg_id);
+        match stream {
+            Some(stream) => {
+                stream.flush(None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    //流式请求，同一消息ID的多个回应帧按批量选项合并回调，直到结束流，返回消息ID
+    pub fn request_stream(
         &self,
         topic: Atom,
         msg: Vec<u8>,
-        resp: Box<Fn(Result<Arc<Vec<u8>>>)>,
+        resp: RPCStreamHandler,
         timeout: u8,
-    ) {
+        batch: StreamBatch,
+    ) -> u32 {
+        let msg_id = (self.msg_id.fetch_add(1, Ordering::SeqCst) + 1) as u32;
+        let buff = match self.encode_frame(msg_id, msg, timeout, CompressPolicy::Default) {
+            Err(e) => {
+                //加密失败，不发布请求
+                resp(Err(e));
+                return msg_id;
+            }
+            Ok(buff) => buff,
+        };
+        //先注册回调再发布消息，避免回应先于回调注册到达
+        let stream = StreamBuffer::new(self.get_timers(), msg_id, batch, resp);
+        self.streams.lock().unwrap().insert(msg_id, Arc::new(stream));
+        self.publish(&topic, buff);
+        msg_id
+    }
+
+    //请求，返回回应的Future，可以与其它异步操作组合，请求被取消或客户端释放时以ErrorKind::Interrupted失败
+    pub fn request_future(
+        &self,
+        topic: Atom,
+        msg: Vec<u8>,
+        timeout: u8,
+    ) -> Box<Future<Item = Arc<Vec<u8>>, Error = Error> + Send> {
+        let (sender, receiver) = oneshot::channel();
+        //回调可能被多次调用，只发送第一次的结果
+        let sender = Mutex::new(Some(sender));
+        self.request_with_header(
+            topic,
+            msg,
+            Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+                if let Some(sender) = sender.lock().unwrap().take() {
+                    //Future已释放则忽略回应
+                    let _ = sender.send(r.map(|(_, rdata)| rdata));
+                }
+            }),
+            timeout,
+        );
+        Box::new(receiver.then(|r| match r {
+            Ok(r) => r,
+            Err(_) => Err(Error::new(ErrorKind::Interrupted, "rpc request canceled")),
+        }))
+    }
+
+    //请求，回应解压时直接写入writer，不构建回应缓冲区，回调写入的字节数，返回消息ID，可用于取消请求
+    //超过最大回应大小时writer中可能已写入部分回应，LZ4_BLOCK压缩的回应只能整体解压，仍会先解压到缓冲区
+    pub fn request_to_writer(
+        &self,
+        topic: Atom,
+        msg: Vec<u8>,
+        writer: Box<Write + Send>,
+        resp: RPCWriterHandler,
+        timeout: u8,
+    ) -> u32 {
+        let msg_id = (self.msg_id.fetch_add(1, Ordering::SeqCst) + 1) as u32;
+        let buff = match self.encode_frame(msg_id, msg, timeout, CompressPolicy::Default) {
+            Err(e) => {
+                //加密失败，不发布请求
+                resp(Err(e));
+                return msg_id;
+            }
+            Ok(buff) => buff,
+        };
+        //先注册回调再发布消息，避免回应先于回调注册到达
+        self.writers.lock().unwrap().insert(msg_id, (writer, resp));
+        self.publish(&topic, buff);
+        msg_id
+    }
+
+    //请求，超时时长为Duration，为0表示不超时，超过可编码的范围则返回错误且不发送请求，成功返回消息ID
+    pub fn request_dur(
+        &self,
+        topic: Atom,
+        msg: Vec<u8>,
+        resp: Box<Fn(Result<Arc<Vec<u8>>>) + Send>,
+        timeout: Duration,
+    ) -> Result<u32> {
+        let timeout = encode_timeout(timeout)?;
+        Ok(self.request_with_header(
+            topic,
+            msg,
+            Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| resp(r.map(|(_, rdata)| rdata))),
+            timeout,
+        ))
+    }
+
+    //请求，回调时同时提供回应的消息头，返回消息ID，可用于取消请求
+    pub fn request_with_header(
+        &self,
+        topic: Atom,
+        msg: Vec<u8>,
+        resp: RPCResponseHandler,
+        timeout: u8,
+    ) -> u32 {
+        self.request_with_compress(topic, msg, resp, timeout, CompressPolicy::Default)
+    }
+
+    //请求，idempotent为true表示请求是幂等的，连接断开时还在等待回应则在重新连接后使用同一消息ID重发，返回消息ID，可用于取消请求
+    pub fn request_with_replay(
+        &self,
+        topic: Atom,
+        msg: Vec<u8>,
+        resp: RPCResponseHandler,
+        timeout: u8,
+        idempotent: bool,
+    ) -> u32 {
+        if !idempotent {
+            return self.request_with_header(topic, msg, resp, timeout);
+        }
+
+        let msg_id = (self.msg_id.fetch_add(1, Ordering::SeqCst) + 1) as u32;
+        let buff = match self.encode_frame(msg_id, msg, timeout, CompressPolicy::Default) {
+            Err(e) => {
+                //加密失败，不发布请求
+                resp(Err(e));
+                return msg_id;
+            }
+            Ok(buff) => buff,
+        };
+        //先保存重发的消息和注册回调再发布消息，避免回应先于回调注册到达
+        self.replays.lock().unwrap().insert(msg_id, Replay {
+            topic: topic.clone(),
+            frame: buff.clone(),
+            lost: false,
+            count: 0,
+        });
+        self.handlers.lock().unwrap().insert(msg_id, resp);
+        self.publish(&topic, buff);
+        msg_id
+    }
+
+    //请求，使用指定的压缩策略，返回消息ID，可用于取消请求
+    pub fn request_with_compress(
+        &self,
+        topic: Atom,
+        msg: Vec<u8>,
+        resp: RPCResponseHandler,
+        timeout: u8,
+        policy: CompressPolicy,
+    ) -> u32 {
         println!("pi_net rpc client request !!!!!!!!!!!!");
-        *self.msg_id.lock().unwrap() += 1;
+        //原子的分配消息ID，保证多线程请求时消息ID唯一
+        let msg_id = (self.msg_id.fetch_add(1, Ordering::SeqCst) + 1) as u32;
+        let buff = match self.encode_frame(msg_id, msg, timeout, policy) {
+            Err(e) => {
+                //加密失败，不发布请求
+                resp(Err(e));
+                return msg_id;
+            }
+            Ok(buff) => buff,
+        };
+        //先注册回调再发布消息，避免回应先于回调注册到达
+        self.handlers.lock().unwrap().insert(msg_id, resp);
+        self.publish(&topic, buff);
+        msg_id
+    }
+
+    //发布已编码的请求消息
+    fn publish(&self, topic: &Atom, buff: Vec<u8>) {
         println!("pi_net rpc client request 00000000000000");
         let socket = self.mqtt.get_socket();
-        println!("pi_net rpc client request 00000000000000");
-        let mut buff: Vec<u8> = vec![];
-        println!("pi_net rpc client request 00000000000000");
+        println!("pi_net rpc client request 11111111111");
+        //发布消息
+        util::send_publish(&socket, false, mqtt3::QoS::AtMostOnce, topic, buff);
+    }
+
+    //编码请求消息，设置了加解密时先加密消息体再压缩
+    fn encode_frame(&self, msg_id: u32, msg: Vec<u8>, timeout: u8, policy: CompressPolicy) -> Result<Vec<u8>> {
+        let (encrypted, msg) = self.encrypt_body(msg)?;
+        Ok(self.compress_frame(msg_id, msg, timeout, policy, encrypted))
+    }
+
+    //加密请求的消息体，没有设置加解密则原样返回，返回是否已加密和消息体
+    fn encrypt_body(&self, msg: Vec<u8>) -> Result<(bool, Vec<u8>)> {
+        match *self.cipher.read().unwrap() {
+            None => Ok((false, msg)),
+            Some(ref cipher) => cipher.encrypt(msg).map(|body| (true, body)),
+        }
+    }
+
+    //按压缩策略压缩并编码请求消息
+    fn compress_frame(&self, msg_id: u32, msg: Vec<u8>, timeout: u8, policy: CompressPolicy, encrypted: bool) -> Vec<u8> {
         let msg_size = msg.len();
-        println!("pi_net rpc client request 00000000000000");
-        let msg_id = *self.msg_id.lock().unwrap();
-        let mut compress_vsn = util::UNCOMPRESS;
-        let mut body = vec![];
-        if msg_size > 64 {
-            compress_vsn = util::LZ4_BLOCK;
-            compress(msg.as_slice(), &mut body, CompressLevel::High).is_ok();
-        } else {
-            body = msg;
+        if self.get_frame_compress() && self.is_compress_supported(util::LZ4_FRAME) && self.is_compress(msg_size, policy) {
+            //流式压缩到消息头之后
+            let mut buff = self.encode_header(util::LZ4_FRAME, msg_id, timeout, msg_size, encrypted);
+            match util::compress_rpc_frame(&mut buff, msg.as_slice()) {
+                Ok(_) => return buff,
+                Err(e) => println!("!!!> Rpc Client Frame Compress Error, msg_id: {}, reason: {:?}", msg_id, e),
+            }
         }
-        println!("pi_net rpc client request 00000000000000");
-        //第一字节：3位压缩版本、5位消息版本 TODO 消息版本以后定义
-        buff.push(((compress_vsn << 6) | 0) as u8);
-        let b1: u8 = ((msg_id >> 24) & 0xff) as u8;
-        let b2: u8 = ((msg_id >> 16) & 0xff) as u8;
-        let b3: u8 = ((msg_id >> 8) & 0xff) as u8;
-        let b4: u8 = (msg_id & 0xff) as u8;
-        //4字节消息ID
-        buff.extend_from_slice(&[b1, b2, b3, b4]);
-        //一字节超时时长（秒）
-        buff.push(timeout);
+        let (compress_vsn, body) = self.compress_body(msg, policy);
+        //消息头，扩展布局LZ4_BLOCK压缩时包括解压后的长度
+        let mut buff = self.encode_header(compress_vsn, msg_id, timeout, msg_size, encrypted);
         //剩下的消息体
         buff.extend_from_slice(body.as_slice());
-        //发布消息
+        buff
+    }
+
+    //编码请求消息头，握手确认服务器支持时使用扩展布局，否则使用基础布局，加密的消息只使用扩展布局
+    fn encode_header(&self, compress: u8, msg_id: u32, timeout: u8, raw_size: usize, encrypted: bool) -> Vec<u8> {
+        if encrypted {
+            let mut buff = util::encode_rpc_header(compress, msg_id, timeout, raw_size);
+            buff[util::RPC_HEADER_SIZE] |= util::RPC_FLAG_ENCRYPT;
+            buff
+        } else if self.is_ext_layout() {
+            util::encode_rpc_header(compress, msg_id, timeout, raw_size)
+        } else {
+            util::encode_rpc_legacy_header(compress, msg_id, timeout)
+        }
+    }
+
+    //获取请求将要发布的消息的注释转储，不会发送，也不会分配消息ID，消息ID为下一个请求的消息ID
+    pub fn debug_frame(&self, topic: &Atom, msg: Vec<u8>, timeout: u8, policy: CompressPolicy) -> String {
+        let msg_id = (self.msg_id.load(Ordering::SeqCst) + 1) as u32;
+        match self.encode_frame(msg_id, msg, timeout, policy) {
+            Err(e) => format!("topic: {}\ninvalid frame: {}\n", **topic, e),
+            Ok(buff) => format!("topic: {}\n{}", **topic, util::dump_rpc_frame(&buff)),
+        }
+    }
+
+    //批量请求，多个请求合并为一条消息发布，压缩策略作用于整个批量，设置了加解密时每条消息分别加密，返回每个请求的消息ID
+    //批量消息只使用扩展布局，服务器不支持扩展布局时逐条发布不压缩的请求
+    pub fn request_batch(
+        &self,
+        topic: Atom,
+        reqs: Vec<(Vec<u8>, RPCResponseHandler, u8)>,
+        policy: CompressPolicy,
+    ) -> Vec<u32> {
+        let socket = self.mqtt.get_socket();
+        let mut msg_ids = Vec::with_capacity(reqs.len());
+        let mut msgs = Vec::with_capacity(reqs.len());
+        let mut failed = Vec::new();
+        {
+            let mut handlers = self.handlers.lock().unwrap();
+            for (msg, resp, timeout) in reqs {
+                let msg_id = (self.msg_id.fetch_add(1, Ordering::SeqCst) + 1) as u32;
+                msg_ids.push(msg_id);
+                let (encrypted, msg) = match self.encrypt_body(msg) {
+                    Err(e) => {
+                        //加密失败的请求不加入批量，释放锁后回调
+                        failed.push((resp, e));
+                        continue;
+                    }
+                    Ok(r) => r,
+                };
+                //批量中的每条消息都不压缩
+                let mut buff = self.encode_header(util::UNCOMPRESS, msg_id, timeout, msg.len(), encrypted);
+                buff.extend_from_slice(msg.as_slice());
+                msgs.push(buff);
+                //先注册回调再发布消息，避免回应先于回调注册到达
+                handlers.insert(msg_id, resp);
+            }
+        }
+        for (resp, e) in failed {
+            resp(Err(e));
+        }
+        if msgs.is_empty() {
+            return msg_ids;
+        }
+        if !self.is_ext_layout() {
+            for buff in msgs {
+                util::send_publish(&socket, false, mqtt3::QoS::AtMostOnce, &topic, buff);
+            }
+            return msg_ids;
+        }
+
+        let batch = util::encode_rpc_batch(&msgs);
+        let batch_size = batch.len();
+        let (compress_vsn, body) = self.compress_body(batch, policy);
+        let mut buff = util::encode_rpc_batch_header(compress_vsn, batch_size);
+        buff.extend_from_slice(body.as_slice());
         util::send_publish(&socket, false, mqtt3::QoS::AtMostOnce, &topic, buff);
-        println!("pi_net rpc client request 11111111111");
-        let mut handlers = self.handlers.lock().unwrap();
-        println!("pi_net rpc client request 2222222222");
-        handlers.insert(msg_id, resp);
-        println!("pi_net rpc client request 333333333");
+        msg_ids
+    }
+
+    //按压缩策略判断指定大小的消息体是否需要压缩
+    fn is_compress(&self, msg_size: usize, policy: CompressPolicy) -> bool {
+        match policy {
+            CompressPolicy::Default => msg_size > self.get_compress_threshold(),
+            CompressPolicy::Never => false,
+            CompressPolicy::Always => true,
+            CompressPolicy::Threshold(threshold) => msg_size > threshold,
+        }
+    }
+
+    //按压缩策略压缩消息体，服务器不支持LZ4_BLOCK时不压缩，返回压缩算法和压缩后的消息体
+    fn compress_body(&self, msg: Vec<u8>, policy: CompressPolicy) -> (u8, Vec<u8>) {
+        if self.is_compress_supported(util::LZ4_BLOCK) && self.is_compress(msg.len(), policy) {
+            let mut body = vec![];
+            compress(msg.as_slice(), &mut body, CompressLevel::High).is_ok();
+            (util::LZ4_BLOCK, body)
+        } else {
+            (util::UNCOMPRESS, msg)
+        }
+    }
+}
+
+//解密回应的消息体，消息体未加密则原样返回
+fn decrypt_body(cipher: &RwLock<Option<Arc<RPCCipher>>>, header: &RpcHeader, body: Vec<u8>) -> Result<Vec<u8>> {
+    if !header.encrypted {
+        return Ok(body);
+    }
+    match *cipher.read().unwrap() {
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("rpc response encrypted without cipher, msg_id: {}", header.msg_id),
+        )),
+        Some(ref cipher) => cipher.decrypt(body),
+    }
+}
+
+//统计已接收的回应
+fn record_response(metrics: &Mutex<RPCResponseMetrics>, size: usize) {
+    let mut metrics = metrics.lock().unwrap();
+    metrics.count += 1;
+    metrics.total += size;
+    if size > metrics.max {
+        metrics.max = size;
+    }
+}
+
+//发送握手请求，消息体为本地支持的压缩算法集合和扩展布局标记，回应为双方都支持的压缩算法集合和扩展布局标记
+//握手请求使用基础布局，不支持握手的旧版本服务器会忽略
+fn handshake(mqtt: &ClientNode, msg_id: &AtomicUsize, handlers: &Mutex<FnvHashMap<u32, RPCResponseHandler>>, compress_modes: &Arc<AtomicUsize>) -> u32 {
+    //握手完成前只使用不压缩
+    compress_modes.store((1 << util::UNCOMPRESS) as usize, Ordering::Relaxed);
+    let id = (msg_id.fetch_add(1, Ordering::SeqCst) + 1) as u32;
+    let mut buff = util::encode_rpc_legacy_header(util::UNCOMPRESS, id, HANDSHAKE_TIMEOUT);
+    buff.push(util::RPC_COMPRESS_MODES | util::RPC_EXT_LAYOUT);
+
+    //握手完成和超时只有先发生的生效
+    let done = Arc::new(AtomicBool::new(false));
+    let timers = mqtt.get_timers();
+    let modes = compress_modes.clone();
+    let done_ = done.clone();
+    let timers_ = timers.clone();
+    handlers.lock().unwrap().insert(id, Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+        if done_.swap(true, Ordering::SeqCst) {
+            println!("!!!> Rpc Client Handshake Response After Timeout, msg_id: {}", id);
+            return;
+        }
+        timers_.write().unwrap().cancel_timeout(Atom::from(HANDSHAKE_TIMER));
+        match r {
+            Ok((_, ref rdata)) if rdata.len() == 1 => {
+                let negotiated = (rdata[0] & (util::RPC_COMPRESS_MODES | util::RPC_EXT_LAYOUT)) | (1 << util::UNCOMPRESS);
+                modes.store(negotiated as usize, Ordering::Relaxed);
+            }
+            Ok((_, rdata)) => println!("!!!> Rpc Client Invalid Handshake, len: {}", rdata.len()),
+            Err(e) => println!("!!!> Rpc Client Handshake Error, reason: {:?}", e),
+        }
+    }));
+    timers.write().unwrap().set_timeout(
+        Atom::from(HANDSHAKE_TIMER),
+        Duration::from_secs(HANDSHAKE_TIMEOUT as u64),
+        Box::new(move |_src: Atom| {
+            if !done.swap(true, Ordering::SeqCst) {
+                println!("!!!> Rpc Client Handshake Timeout, msg_id: {}", id);
+            }
+        }),
+    );
+    util::send_publish(&mqtt.get_socket(), false, mqtt3::QoS::AtMostOnce, HANDSHAKE_TOPIC, buff);
+    id
+}
+
+//重发因连接断开而等待重发的幂等请求，按消息ID顺序重发
+fn replay(mqtt: &ClientNode, replays: &Mutex<FnvHashMap<u32, Replay>>, metrics: &Mutex<RPCResponseMetrics>) {
+    let mut frames: Vec<(u32, Atom, Vec<u8>)> = replays.lock().unwrap().iter_mut().filter(|&(_, ref replay)| replay.lost).map(|(msg_id, replay)| {
+        replay.lost = false;
+        replay.count += 1;
+        (*msg_id, replay.topic.clone(), replay.frame.clone())
+    }).collect();
+    if frames.is_empty() {
+        return;
+    }
+
+    frames.sort_by_key(|&(msg_id, _, _)| msg_id);
+    metrics.lock().unwrap().replayed += frames.len();
+    let socket = mqtt.get_socket();
+    for (msg_id, topic, frame) in frames {
+        println!("!!!> Rpc Client Replay Request, msg_id: {}, topic: {}", msg_id, *topic);
+        util::send_publish(&socket, false, mqtt3::QoS::AtMostOnce, &topic, frame);
+    }
+}
+
+impl RPCClientTraits for RPCClient {
+    fn request(
+        &self,
+        topic: Atom,
+        msg: Vec<u8>,
+        resp: Box<Fn(Result<Arc<Vec<u8>>>) + Send>,
+        timeout: u8,
+    ) {
+        self.request_with_header(
+            topic,
+            msg,
+            Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| resp(r.map(|(_, rdata)| rdata))),
+            timeout,
+        );
     }
 }
@@ -1,10 +1,12 @@
 //! rpc 实现
 //!
+#![feature(fnbox)]
 
 extern crate net;
 extern crate mqtt;
 extern crate mqtt3;
 extern crate fnv;
+extern crate futures;
 extern crate pi_base;
 extern crate pi_lib;
 
@@ -6,6 +6,7 @@ use std::io::Result;
  * 压缩算法：0：不压缩，1：lz4, 2:zstd
  */
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::SystemTime;
 
 use pi_lib::atom::Atom;
@@ -13,15 +14,19 @@ use pi_lib::atom::Atom;
 use mqtt::data::{Server, SetAttrFun};
 use mqtt::server::{ClientStub, ServerNode};
 use mqtt::session::Session;
+use mqtt::util;
 
 use pi_lib::handler::{Args, Handler};
-use traits::RPCServerTraits;
+use traits::{RPCServerTraits, RPCCipher};
+use client::HANDSHAKE_TOPIC;
 
 use net::{CloseFn, Socket, Stream};
 
 #[derive(Clone)]
 pub struct RPCServer {
     pub mqtt: ServerNode,
+    compress_threshold: Arc<AtomicUsize>,   //回应压缩阈值，为0表示不压缩
+    compress_modes: Arc<AtomicUsize>,       //支持的请求压缩算法集合，握手时告知客户端
 }
 
 // enum Compress {
@@ -33,7 +38,74 @@ pub struct RPCServer {
 
 impl RPCServer {
     pub fn new(mqtt: ServerNode) -> Self {
-        RPCServer { mqtt }
+        let server = RPCServer {
+            mqtt,
+            compress_threshold: Arc::new(AtomicUsize::new(0)),
+            compress_modes: Arc::new(AtomicUsize::new(util::RPC_COMPRESS_MODES as usize)),
+        };
+        server.register_handshake().is_ok();
+        server
+    }
+
+    //设置支持的请求压缩算法集合，每个压缩算法占一位，不压缩总是支持，只影响之后的握手
+    pub fn set_compress_modes(&self, modes: u8) {
+        let modes = (modes & util::RPC_COMPRESS_MODES) | (1 << util::UNCOMPRESS);
+        self.compress_modes.store(modes as usize, Ordering::Relaxed);
+    }
+
+    //获取支持的请求压缩算法集合
+    pub fn get_compress_modes(&self) -> u8 {
+        self.compress_modes.load(Ordering::Relaxed) as u8
+    }
+
+    //注册握手主题，回应客户端和服务器都支持的压缩算法集合，客户端支持时同时回应扩展布局标记
+    fn register_handshake(&self) -> Result<()> {
+        let compress_modes = self.compress_modes.clone();
+        let handshake = move |client: ClientStub, r: Result<Arc<Vec<u8>>>| {
+            let rdata = r.unwrap();
+            if rdata.len() != 6 {
+                println!("!!!> Rpc Server Invalid Handshake, len: {}", rdata.len());
+                return;
+            }
+            let msg_id = ((rdata[0] as u32) << 24) | ((rdata[1] as u32) << 16) | ((rdata[2] as u32) << 8) | (rdata[3] as u32);
+            let modes = rdata[5] & (compress_modes.load(Ordering::Relaxed) as u8 | util::RPC_EXT_LAYOUT);
+            //握手回应不压缩
+            let session = Session::new(client, true, msg_id);
+            session.respond(Atom::from(HANDSHAKE_TOPIC), vec![modes | (1 << util::UNCOMPRESS)]);
+        };
+        self.mqtt.set_topic_meta(Atom::from(HANDSHAKE_TOPIC), true, true, Box::new(handshake))
+    }
+
+    //设置回应压缩阈值，回应大于阈值时以LZ4_BLOCK压缩，客户端按消息头自动解压，为0表示不压缩，默认不压缩
+    //只影响之后收到的请求的回应
+    pub fn set_compress_threshold(&self, threshold: usize) {
+        self.compress_threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    //获取回应压缩阈值
+    pub fn get_compress_threshold(&self) -> usize {
+        self.compress_threshold.load(Ordering::Relaxed)
+    }
+
+    //设置请求和回应消息体的加解密，None表示不支持加密的请求，默认不支持，需要与客户端设置相同的加解密
+    //发送过加密请求的客户端，回应也会加密
+    pub fn set_cipher(&self, cipher: Option<Arc<RPCCipher>>) {
+        self.mqtt.set_rpc_cipher(cipher)
+    }
+
+    //获取消息体的加解密
+    pub fn get_cipher(&self) -> Option<Arc<RPCCipher>> {
+        self.mqtt.get_rpc_cipher()
+    }
+
+    //设置最大请求大小，解压后，单位字节，超限的请求直接丢弃，为0表示不限制，默认为mqtt::server::DEFAULT_MAX_REQUEST_SIZE
+    pub fn set_max_request_size(&self, size: usize) {
+        self.mqtt.set_rpc_max_request_size(size)
+    }
+
+    //获取最大请求大小
+    pub fn get_max_request_size(&self) -> usize {
+        self.mqtt.get_rpc_max_request_size()
     }
 
     pub fn unset_topic_meta(&self, topic: Atom) {
@@ -73,15 +145,18 @@ impl RPCServerTraits for RPCServer {
         >,
     ) -> Result<()> {
         let topic2 = topic.clone();
+        let compress_threshold = self.compress_threshold.clone();
         let rpc_handle = move |client: ClientStub, r: Result<Arc<Vec<u8>>>| {
             //println!("rpc_handle -----------------------------------------{:?}", &topic2);
             let rdata = r.unwrap();
-            let uid = rdata[0..3].as_ptr();
+            //4字节大端消息ID，回应使用同一消息ID
+            let msg_id = ((rdata[0] as u32) << 24) | ((rdata[1] as u32) << 16) | ((rdata[2] as u32) << 8) | (rdata[3] as u32);
             let mut session = Session::new(
                 client.clone(),
                 sync,
-                u32::from_be(unsafe { *(uid as *mut u32) }),
+                msg_id,
             );
+            session.set_compress_threshold(compress_threshold.load(Ordering::Relaxed));
             let now = SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .unwrap()
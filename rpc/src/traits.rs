@@ -11,13 +11,16 @@ pub trait RPCClientTraits {
         &self,
         topic: Atom,
         msg: Vec<u8>,
-        resp: Box<Fn(Result<Arc<Vec<u8>>>)>,
+        resp: Box<Fn(Result<Arc<Vec<u8>>>) + Send>,
         timeout: u8,
     );
 
     //订阅$r/#
 }
 
+// rpc消息体的加解密，客户端和服务器共用
+pub use mqtt::util::RPCCipher;
+
 pub trait RPCServerTraits {
     // $q 请求
     // $r 回应
@@ -3,11 +3,27 @@ extern crate mqtt3;
 extern crate net;
 extern crate pi_lib;
 extern crate rpc;
+extern crate futures;
 
 mod client;
 
 use std::thread::sleep;
 use std::time::Duration;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::{channel, Receiver};
+use std::io::{Error, ErrorKind, Result};
+
+use futures::Future;
+
+use mqtt::client::ClientNode;
+use mqtt::data::Client;
+use net::{Socket, Stream};
+use net::data::SendClosureFn;
+use net::timer::{NetTimers, TimerCallback};
+use pi_lib::atom::Atom;
+use mqtt::util;
+use rpc::client::{RPCClient, RPCResponseHandler, RpcHeader, CompressPolicy, StreamBatch, OrphanPolicy, DEFAULT_DUPLICATE_WINDOW, DEFAULT_COMPRESS_THRESHOLD, HANDSHAKE_TIMER, DEFAULT_MAX_REPLAYS, encode_timeout};
+use rpc::traits::RPCCipher;
 
 use client::start_client;
 
@@ -23,3 +39,517 @@ fn run() {
         sleep(Duration::from_secs(1))
     }
 }
+
+//创建使用测试连接的客户端节点，返回的接收端收到连接上发布的消息和关闭，返回的定时器需要在测试中手动触发
+fn test_client_node() -> (ClientNode, Receiver<SendClosureFn>, Arc<RwLock<NetTimers<TimerCallback>>>) {
+    let (sender, receiver) = channel();
+    let socket = Socket {
+        socket: 0,
+        sender: sender,
+        gray: None,
+    };
+    let timers = Arc::new(RwLock::new(NetTimers::new()));
+    let stream = Arc::new(RwLock::new(Stream::new(0, Arc::new(RwLock::new(Vec::new())), timers.clone())));
+
+    let client_node = ClientNode::new();
+    client_node.set_stream(socket, stream);
+    (client_node, receiver, timers)
+}
+
+#[test]
+fn test_rpc_client_send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<RPCClient>();
+}
+
+#[test]
+fn test_rpc_client_drop_stop_ping() {
+    let (client_node, _receiver, timers) = test_client_node();
+    let rpc = RPCClient::new(client_node);
+    rpc.connect(10, None, None, None);
+    assert!(timers.read().unwrap().contains(&Atom::from("client_ping")));
+
+    //复制的客户端释放时不会停止ping
+    let copy = rpc.clone();
+    drop(copy);
+    assert!(timers.read().unwrap().contains(&Atom::from("client_ping")));
+
+    drop(rpc);
+    assert!(!timers.read().unwrap().contains(&Atom::from("client_ping")));
+}
+
+#[test]
+fn test_rpc_client_request_future_cancel() {
+    let (client_node, receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node);
+    let future = rpc.request_future(Atom::from("test"), vec![1, 2, 3], 10);
+    //请求已发布
+    assert!(receiver.try_recv().is_ok());
+
+    //取消后Future以Interrupted失败
+    assert!(rpc.cancel(1));
+    assert_eq!(future.wait().err().unwrap().kind(), ErrorKind::Interrupted);
+}
+
+#[test]
+fn test_rpc_client_duplicate_window() {
+    let (client_node, receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node.clone());
+    rpc.connect(10, None, None, None);
+    assert_eq!(rpc.get_duplicate_window(), DEFAULT_DUPLICATE_WINDOW);
+    assert_eq!(rpc.get_orphan_policy(), OrphanPolicy::Close);
+    assert_eq!(rpc.get_response_metrics().duplicate, 0);
+    while receiver.try_recv().is_ok() {}
+
+    let (resp_sender, resp_receiver) = channel();
+    let msg_id = rpc.request_with_header(Atom::from("test"), vec![1], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+        resp_sender.send(r.unwrap().1.len()).unwrap();
+    }), 10);
+    assert!(receiver.try_recv().is_ok());
+
+    //同一消息ID的回应只回调一次，重复回应被统计并忽略，不会按孤立回应关闭连接
+    client_node.handle_publish("$r", util::encode_rpc_frame(msg_id, 0, vec![1, 2], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), 2);
+    client_node.handle_publish("$r", util::encode_rpc_frame(msg_id, 0, vec![1, 2], false));
+    assert!(resp_receiver.try_recv().is_err());
+    assert_eq!(rpc.get_response_metrics().duplicate, 1);
+    assert!(receiver.try_recv().is_err());
+
+    //为0表示不检测，重复回应作为孤立回应处理
+    rpc.set_duplicate_window(0);
+    assert_eq!(rpc.get_duplicate_window(), 0);
+    client_node.handle_publish("$r", util::encode_rpc_frame(msg_id, 0, vec![1, 2], false));
+    assert_eq!(rpc.get_response_metrics().duplicate, 1);
+    assert!(receiver.try_recv().is_ok());
+}
+
+#[test]
+fn test_rpc_encode_timeout() {
+    assert_eq!(encode_timeout(Duration::from_secs(0)).unwrap(), 0);
+    assert_eq!(encode_timeout(Duration::from_secs(30)).unwrap(), 30);
+    //不足1秒的部分向上取整
+    assert_eq!(encode_timeout(Duration::from_millis(1)).unwrap(), 1);
+    assert_eq!(encode_timeout(Duration::from_millis(2500)).unwrap(), 3);
+    assert_eq!(encode_timeout(Duration::from_secs(255)).unwrap(), 255);
+    assert_eq!(encode_timeout(Duration::from_millis(255001)).err().unwrap().kind(), ErrorKind::InvalidInput);
+}
+
+#[test]
+fn test_rpc_client_replay_idempotent() {
+    let (client_node, receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node);
+    assert_eq!(rpc.get_max_replays(), DEFAULT_MAX_REPLAYS);
+
+    let (resp_sender, resp_receiver) = channel();
+    let copy = resp_sender.clone();
+    let idempotent = rpc.request_with_replay(Atom::from("test"), vec![1], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+        copy.send((1, r.err().unwrap().kind())).unwrap();
+    }), 10, true);
+    let copy = resp_sender.clone();
+    let normal = rpc.request_with_replay(Atom::from("test"), vec![2], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+        copy.send((2, r.err().unwrap().kind())).unwrap();
+    }), 10, false);
+    assert_eq!((idempotent, normal), (1, 2));
+    //请求已发布
+    assert!(receiver.try_recv().is_ok());
+    assert!(receiver.try_recv().is_ok());
+
+    //连接断开时非幂等请求失败，幂等请求等待重发
+    rpc.disconnected();
+    assert_eq!(resp_receiver.try_recv().unwrap(), (2, ErrorKind::ConnectionAborted));
+    assert!(resp_receiver.try_recv().is_err());
+
+    //达到最大重发次数后幂等请求也失败
+    rpc.set_max_replays(0);
+    rpc.disconnected();
+    assert_eq!(resp_receiver.try_recv().unwrap(), (1, ErrorKind::ConnectionAborted));
+    assert!(!rpc.cancel(idempotent));
+    assert_eq!(rpc.get_response_metrics().replayed, 0);
+}
+
+#[test]
+fn test_rpc_client_request_to_writer() {
+    let (client_node, receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node);
+
+    let (resp_sender, resp_receiver) = channel();
+    let copy = resp_sender.clone();
+    let canceled = rpc.request_to_writer(Atom::from("test"), vec![1], Box::new(Vec::new()), Box::new(move |r: Result<(RpcHeader, usize)>| {
+        copy.send((1, r.err().unwrap().kind())).unwrap();
+    }), 10);
+    let lost = rpc.request_to_writer(Atom::from("test"), vec![2], Box::new(Vec::new()), Box::new(move |r: Result<(RpcHeader, usize)>| {
+        resp_sender.send((2, r.err().unwrap().kind())).unwrap();
+    }), 10);
+    //请求已发布
+    assert!(receiver.try_recv().is_ok());
+    assert!(receiver.try_recv().is_ok());
+
+    //取消的请求不再回调，连接断开时未完成的请求失败
+    assert!(rpc.cancel(canceled));
+    rpc.disconnected();
+    assert_eq!(resp_receiver.try_recv().unwrap(), (2, ErrorKind::ConnectionAborted));
+    assert!(resp_receiver.try_recv().is_err());
+    assert!(!rpc.cancel(lost));
+}
+
+//按字节异或的测试加解密，key为0时加密失败
+struct XorCipher(u8);
+
+impl RPCCipher for XorCipher {
+    fn encrypt(&self, body: Vec<u8>) -> Result<Vec<u8>> {
+        if self.0 == 0 {
+            return Err(Error::new(ErrorKind::InvalidInput, "invalid key"));
+        }
+        Ok(body.into_iter().map(|b| b ^ self.0).collect())
+    }
+
+    fn decrypt(&self, body: Vec<u8>) -> Result<Vec<u8>> {
+        self.encrypt(body)
+    }
+}
+
+#[test]
+fn test_rpc_client_cipher() {
+    let (client_node, receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node.clone());
+    rpc.connect(10, None, None, None);
+    while receiver.try_recv().is_ok() {}
+    let topic = Atom::from("test");
+
+    //默认不加密
+    assert!(rpc.get_cipher().is_none());
+    let dump = rpc.debug_frame(&topic, vec![1, 2, 3], 10, CompressPolicy::Never);
+    assert!(dump.contains("ext: false"));
+    assert!(dump.contains("01 02 03\n"));
+
+    //消息体加密后设置消息标记的加密标记，加密的消息只使用扩展布局
+    rpc.set_cipher(Some(Arc::new(XorCipher(0xff))));
+    let dump = rpc.debug_frame(&topic, vec![1, 2, 3], 10, CompressPolicy::Never);
+    assert!(dump.contains("[0] 20: compress: 0 (uncompress), ext: true, vsn: 0\n"));
+    assert!(dump.contains("[6] 02: batch: false, encrypted: true\n"));
+    assert!(dump.contains("fe fd fc\n"));
+
+    //加密失败时不发布请求，直接回调错误
+    rpc.set_cipher(Some(Arc::new(XorCipher(0))));
+    let (resp_sender, resp_receiver) = channel();
+    rpc.request_with_header(topic, vec![1], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+        resp_sender.send(r.err().unwrap().kind()).unwrap();
+    }), 10);
+    assert_eq!(resp_receiver.try_recv().unwrap(), ErrorKind::InvalidInput);
+    assert!(receiver.try_recv().is_err());
+
+    //加密的回应解压后解密
+    rpc.set_cipher(Some(Arc::new(XorCipher(0xff))));
+    let (resp_sender, resp_receiver) = channel();
+    let msg_id = rpc.request_with_header(Atom::from("test"), vec![1], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+        resp_sender.send(r.map(|(header, rdata)| (header.encrypted, (*rdata).clone())).map_err(|e| e.kind())).unwrap();
+    }), 10);
+    client_node.handle_publish("$r", util::encode_rpc_encrypted_frame(msg_id, 0, vec![0xfe, 0xfd], true));
+    assert_eq!(resp_receiver.try_recv().unwrap(), Ok((true, vec![1, 2])));
+}
+
+#[test]
+fn test_rpc_client_handshake() {
+    let (client_node, receiver, timers) = test_client_node();
+    let rpc = RPCClient::new(client_node);
+    let topic = Atom::from("test");
+
+    //未握手时可以使用本地支持的所有压缩算法
+    assert_eq!(rpc.get_compress_modes(), util::RPC_COMPRESS_MODES);
+    assert!(rpc.debug_frame(&topic, vec![1; 100], 10, CompressPolicy::Always).contains("compress: 1 (lz4 block)"));
+
+    //握手请求已发布，握手完成前只发送不压缩的请求，同时开始等待握手超时
+    assert_eq!(rpc.handshake(), 1);
+    assert!(receiver.try_recv().is_ok());
+    assert!(timers.read().unwrap().contains(&Atom::from(HANDSHAKE_TIMER)));
+    assert_eq!(rpc.get_compress_modes(), 1 << util::UNCOMPRESS);
+    assert!(rpc.is_compress_supported(util::UNCOMPRESS));
+    assert!(!rpc.is_compress_supported(util::LZ4_BLOCK));
+    assert!(rpc.debug_frame(&topic, vec![1; 100], 10, CompressPolicy::Always).contains("compress: 0 (uncompress)"));
+
+    //握手请求和普通请求一样可以取消
+    assert!(rpc.cancel(1));
+}
+
+#[test]
+fn test_rpc_client_ext_layout() {
+    let (client_node, receiver, timers) = test_client_node();
+    let rpc = RPCClient::new(client_node.clone());
+    rpc.connect(10, None, None, None);
+    let topic = Atom::from("test");
+    let batch = || vec![
+        (vec![1], Box::new(|_: Result<(RpcHeader, Arc<Vec<u8>>)>| {}) as RPCResponseHandler, 10),
+        (vec![2], Box::new(|_: Result<(RpcHeader, Arc<Vec<u8>>)>| {}) as RPCResponseHandler, 10),
+    ];
+
+    //握手确认前只发送与旧版本兼容的基础布局，批量请求逐条发布
+    assert!(!rpc.is_ext_layout());
+    assert!(rpc.debug_frame(&topic, vec![1; 100], 10, CompressPolicy::Always).contains("compress: 1 (lz4 block), ext: false"));
+    while receiver.try_recv().is_ok() {}
+    rpc.request_batch(topic.clone(), batch(), CompressPolicy::Never);
+    assert_eq!(receiver.try_iter().count(), 2);
+
+    //服务器回应扩展布局标记后使用扩展布局
+    let id = rpc.handshake();
+    let modes = (1 << util::UNCOMPRESS) | (1 << util::LZ4_BLOCK) | util::RPC_EXT_LAYOUT;
+    client_node.handle_publish("$r", util::encode_rpc_legacy_frame(id, 0, vec![modes], false));
+    assert!(rpc.is_ext_layout());
+    assert!(!timers.read().unwrap().contains(&Atom::from(HANDSHAKE_TIMER)));
+    assert_eq!(rpc.get_compress_modes(), (1 << util::UNCOMPRESS) | (1 << util::LZ4_BLOCK));
+    let dump = rpc.debug_frame(&topic, vec![1; 100], 10, CompressPolicy::Always);
+    assert!(dump.contains("compress: 1 (lz4 block), ext: true"));
+    assert!(dump.contains("[7..11] 00 00 00 64: raw_size: 100\n"));
+    while receiver.try_recv().is_ok() {}
+    rpc.request_batch(topic.clone(), batch(), CompressPolicy::Never);
+    assert_eq!(receiver.try_iter().count(), 1);
+
+    //旧版本的服务器回应中没有扩展布局标记
+    let id = rpc.handshake();
+    client_node.handle_publish("$r", util::encode_rpc_legacy_frame(id, 0, vec![util::RPC_COMPRESS_MODES], false));
+    assert!(!rpc.is_ext_layout());
+}
+
+#[test]
+fn test_rpc_client_compress_policy() {
+    let (client_node, receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node);
+    let topic = Atom::from("test");
+    let compress = |size: usize, policy: CompressPolicy| {
+        rpc.debug_frame(&topic, vec![7; size], 10, policy).contains("compress: 1 (lz4 block)")
+    };
+
+    //默认使用客户端的压缩阈值
+    assert_eq!(rpc.get_compress_threshold(), DEFAULT_COMPRESS_THRESHOLD);
+    assert!(!compress(DEFAULT_COMPRESS_THRESHOLD, CompressPolicy::Default));
+    assert!(compress(DEFAULT_COMPRESS_THRESHOLD + 1, CompressPolicy::Default));
+    rpc.set_compress_threshold(1000);
+    assert!(!compress(DEFAULT_COMPRESS_THRESHOLD + 1, CompressPolicy::Default));
+
+    //单次请求的压缩策略优先于客户端的压缩阈值
+    assert!(!compress(2000, CompressPolicy::Never));
+    assert!(compress(10, CompressPolicy::Always));
+    assert!(!compress(100, CompressPolicy::Threshold(100)));
+    assert!(compress(101, CompressPolicy::Threshold(100)));
+
+    //服务器不支持压缩时总是不压缩
+    rpc.handshake();
+    assert!(!compress(10, CompressPolicy::Always));
+
+    //按压缩策略编码后发布
+    while receiver.try_recv().is_ok() {}
+    rpc.request_with_compress(topic.clone(), vec![7; 10], Box::new(|_: Result<(RpcHeader, Arc<Vec<u8>>)>| {}), 10, CompressPolicy::Always);
+    assert!(receiver.try_recv().is_ok());
+}
+
+#[test]
+fn test_rpc_client_response_compress() {
+    let (client_node, _receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node.clone());
+    rpc.connect(10, None, None, None);
+
+    let (resp_sender, resp_receiver) = channel();
+    for _ in 0..4 {
+        let copy = resp_sender.clone();
+        rpc.request_with_header(Atom::from("test"), vec![1], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+            let (header, rdata) = r.unwrap();
+            copy.send((header.msg_id, header.compress, header.ext, (*rdata).clone())).unwrap();
+        }), 10);
+    }
+
+    //回调的消息头是回应使用的压缩算法，回应体已解压
+    client_node.handle_publish("$r", util::encode_rpc_frame(1, 0, vec![7; 100], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), (1, util::UNCOMPRESS, true, vec![7; 100]));
+    client_node.handle_publish("$r", util::encode_rpc_frame(2, 0, vec![7; 100], true));
+    assert_eq!(resp_receiver.try_recv().unwrap(), (2, util::LZ4_BLOCK, true, vec![7; 100]));
+    client_node.handle_publish("$r", util::encode_rpc_legacy_frame(3, 0, vec![7; 100], true));
+    assert_eq!(resp_receiver.try_recv().unwrap(), (3, util::LZ4_BLOCK, false, vec![7; 100]));
+    let mut buff = util::encode_rpc_header(util::LZ4_FRAME, 4, 0, 100);
+    util::compress_rpc_frame(&mut buff, &vec![7; 100]).unwrap();
+    client_node.handle_publish("$r", buff);
+    assert_eq!(resp_receiver.try_recv().unwrap(), (4, util::LZ4_FRAME, true, vec![7; 100]));
+}
+
+#[test]
+fn test_rpc_client_max_response_size() {
+    let (client_node, _receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node.clone());
+    rpc.connect(10, None, None, None);
+    rpc.set_max_response_size(100);
+
+    let (resp_sender, resp_receiver) = channel();
+    for _ in 0..3 {
+        let copy = resp_sender.clone();
+        rpc.request_with_header(Atom::from("test"), vec![1], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+            copy.send(r.map(|(_, rdata)| rdata.len()).map_err(|e| e.kind())).unwrap();
+        }), 10);
+    }
+
+    //压缩后的回应按解压后长度检查，超限时请求失败，连接保持
+    client_node.handle_publish("$r", util::encode_rpc_frame(1, 0, vec![7; 1000], true));
+    assert_eq!(resp_receiver.try_recv().unwrap(), Err(ErrorKind::Other));
+    client_node.handle_publish("$r", util::encode_rpc_frame(2, 0, vec![7; 100], true));
+    assert_eq!(resp_receiver.try_recv().unwrap(), Ok(100));
+    client_node.handle_publish("$r", util::encode_rpc_frame(3, 0, vec![7; 50], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), Ok(50));
+
+    let metrics = rpc.get_response_metrics();
+    assert_eq!((metrics.count, metrics.total, metrics.max, metrics.oversize), (2, 150, 100, 1));
+}
+
+#[test]
+fn test_rpc_client_response_topics() {
+    let (client_node, receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node.clone());
+    rpc.connect_with_topics(10, None, None, None, vec![Atom::from("$r"), Atom::from("resp/a"), Atom::from("resp/stream/+")]);
+    //连接后订阅默认回应主题以外的主题
+    assert!(receiver.try_recv().is_ok());
+    while receiver.try_recv().is_ok() {}
+
+    let (resp_sender, resp_receiver) = channel();
+    for _ in 0..3 {
+        let copy = resp_sender.clone();
+        rpc.request_with_header(Atom::from("test"), vec![1], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+            let (header, rdata) = r.unwrap();
+            copy.send((header.msg_id, (*rdata).clone())).unwrap();
+        }), 10);
+    }
+    while receiver.try_recv().is_ok() {}
+
+    //所有回应主题的回应都按消息ID分发到对应的请求，与回应的主题无关
+    client_node.handle_publish("resp/a", util::encode_rpc_frame(2, 0, vec![2], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), (2, vec![2]));
+    client_node.handle_publish("resp/stream/x", util::encode_rpc_frame(3, 0, vec![3], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), (3, vec![3]));
+    client_node.handle_publish("$r", util::encode_rpc_frame(1, 0, vec![1], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), (1, vec![1]));
+
+    //未注册的主题不作为回应处理
+    let copy = resp_sender.clone();
+    rpc.request_with_header(Atom::from("test"), vec![1], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+        copy.send((r.unwrap().0.msg_id, vec![])).unwrap();
+    }), 10);
+    client_node.handle_publish("resp/b", util::encode_rpc_frame(4, 0, vec![4], false));
+    assert!(resp_receiver.try_recv().is_err());
+    client_node.handle_publish("resp/a", util::encode_rpc_frame(4, 0, vec![4], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), (4, vec![]));
+}
+
+#[test]
+fn test_rpc_client_unhandled_handler() {
+    let (client_node, _receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node.clone());
+    rpc.connect(10, None, None, None);
+
+    let (topic_sender, topic_receiver) = channel();
+    rpc.set_topic_handler(Atom::from("event/a"), Box::new(move |r: Result<(Socket, &[u8])>| {
+        topic_sender.send(r.unwrap().1.to_vec()).unwrap();
+    })).unwrap();
+
+    //未设置时忽略未处理消息
+    client_node.handle_publish("event/b", vec![1]);
+    assert!(topic_receiver.try_recv().is_err());
+
+    let (unhandled_sender, unhandled_receiver) = channel();
+    let unhandled_sender = Mutex::new(unhandled_sender);
+    rpc.set_unhandled_handler(Some(Arc::new(move |topic: Atom, payload: &[u8]| {
+        unhandled_sender.lock().unwrap().send(((*topic).clone(), payload.to_vec())).unwrap();
+    })));
+
+    //已设置回调的主题和回应主题不回调未处理消息回调
+    client_node.handle_publish("event/a", vec![2]);
+    assert_eq!(topic_receiver.try_recv().unwrap(), vec![2]);
+    assert!(unhandled_receiver.try_recv().is_err());
+    let (resp_sender, resp_receiver) = channel();
+    rpc.request_with_header(Atom::from("test"), vec![1], Box::new(move |r: Result<(RpcHeader, Arc<Vec<u8>>)>| {
+        resp_sender.send(r.unwrap().0.msg_id).unwrap();
+    }), 10);
+    client_node.handle_publish("$r", util::encode_rpc_frame(1, 0, vec![3], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), 1);
+    assert!(unhandled_receiver.try_recv().is_err());
+
+    //其它主题回调主题和负载
+    client_node.handle_publish("event/b", vec![4]);
+    assert_eq!(unhandled_receiver.try_recv().unwrap(), (String::from("event/b"), vec![4]));
+    assert!(topic_receiver.try_recv().is_err());
+
+    //取消后忽略
+    rpc.set_unhandled_handler(None);
+    client_node.handle_publish("event/b", vec![5]);
+    assert!(unhandled_receiver.try_recv().is_err());
+}
+
+#[test]
+fn test_rpc_client_stream_batch_window() {
+    let (client_node, _receiver, timers) = test_client_node();
+    let rpc = RPCClient::new(client_node.clone());
+    rpc.connect(10, None, None, None);
+
+    let (resp_sender, resp_receiver) = channel();
+    let msg_id = rpc.request_stream(Atom::from("test"), vec![1], Arc::new(move |r: Result<Vec<Arc<Vec<u8>>>>| {
+        resp_sender.send(r.unwrap().len()).unwrap();
+    }), 10, StreamBatch {
+        size: 3,
+        window: 50,
+    });
+    let timer = Atom::from(format!("rpc_stream_{}", msg_id));
+
+    //批量未满时通过net定时器等待，不回调
+    client_node.handle_publish("$r", util::encode_rpc_frame(msg_id, 0, vec![1], false));
+    client_node.handle_publish("$r", util::encode_rpc_frame(msg_id, 0, vec![2], false));
+    assert!(timers.read().unwrap().contains(&timer));
+    assert!(resp_receiver.try_recv().is_err());
+
+    //等待时长到期后回调已缓冲的回应帧
+    sleep(Duration::from_millis(100));
+    timers.write().unwrap().poll();
+    assert_eq!(resp_receiver.recv_timeout(Duration::from_millis(1000)).unwrap(), 2);
+    assert!(!timers.read().unwrap().contains(&timer));
+
+    //批量已满时立即回调，并取消等待定时器
+    for data in 3..6 {
+        client_node.handle_publish("$r", util::encode_rpc_frame(msg_id, 0, vec![data], false));
+    }
+    assert_eq!(resp_receiver.try_recv().unwrap(), 3);
+    assert!(!timers.read().unwrap().contains(&timer));
+
+    //结束流时回调剩余的回应帧
+    client_node.handle_publish("$r", util::encode_rpc_frame(msg_id, 0, vec![6], false));
+    assert!(timers.read().unwrap().contains(&timer));
+    assert!(rpc.end_stream(msg_id));
+    assert_eq!(resp_receiver.try_recv().unwrap(), 1);
+    assert!(!timers.read().unwrap().contains(&timer));
+}
+
+#[test]
+fn test_rpc_client_orphan_policy() {
+    let (client_node, receiver, _timers) = test_client_node();
+    let rpc = RPCClient::new(client_node.clone());
+    rpc.connect(10, None, None, None);
+    assert_eq!(rpc.get_orphan_policy(), OrphanPolicy::Close);
+    while receiver.try_recv().is_ok() {}
+
+    //忽略孤立回应时回调并保持连接
+    let (resp_sender, resp_receiver) = channel();
+    let copy = resp_sender.clone();
+    rpc.set_orphan_handler(OrphanPolicy::Ignore, Some(Arc::new(move |msg_id: u32, r: Result<Arc<Vec<u8>>>| {
+        copy.send((msg_id, r.unwrap().len())).unwrap();
+    })));
+    client_node.handle_publish("$r", util::encode_rpc_frame(100, 0, vec![1, 2, 3], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), (100, 3));
+    assert!(receiver.try_recv().is_err());
+
+    //关闭连接时先回调，回调中可以重新设置，新的设置从下一个孤立回应开始生效
+    let rpc_ = rpc.clone();
+    rpc.set_orphan_handler(OrphanPolicy::Close, Some(Arc::new(move |msg_id: u32, r: Result<Arc<Vec<u8>>>| {
+        rpc_.set_orphan_handler(OrphanPolicy::Ignore, None);
+        resp_sender.send((msg_id, r.unwrap().len())).unwrap();
+    })));
+    client_node.handle_publish("$r", util::encode_rpc_frame(101, 0, vec![1], false));
+    assert_eq!(resp_receiver.try_recv().unwrap(), (101, 1));
+    assert!(receiver.try_recv().is_ok());
+    assert_eq!(rpc.get_orphan_policy(), OrphanPolicy::Ignore);
+
+    client_node.handle_publish("$r", util::encode_rpc_frame(102, 0, vec![1], false));
+    assert!(resp_receiver.try_recv().is_err());
+    assert!(receiver.try_recv().is_err());
+}